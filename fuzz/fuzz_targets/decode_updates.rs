@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use crufty::net::replication::decode_updates;
+
+// run with `cargo +nightly fuzz run decode_updates`
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_updates(data);
+});
@@ -0,0 +1,215 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use glium::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use glium::Surface;
+
+use crate::application::client::{ClientContext, ClientTransition};
+use crate::net::{ConnectionStatus, DisconnectReason};
+use crate::util::State;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_CONNECT_ATTEMPTS: u32 = 5;
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const FRAME_DURATION: Duration = Duration::from_millis(10);
+
+const CONNECTING_COLOR: (f32, f32, f32, f32) = (0.1, 0.1, 0.2, 1.0);
+const DISCONNECTED_COLOR: (f32, f32, f32, f32) = (0.3, 0.05, 0.05, 1.0);
+
+enum ScreenInput {
+    Confirm,
+    Quit,
+}
+
+/// Runs a single frame of a screen shown outside of the game: handles window events,
+/// keeps the connection going and clears the window to `clear_color`.
+fn run_frame(ctx: &mut ClientContext, clear_color: (f32, f32, f32, f32)) -> Option<ScreenInput> {
+    let mut input = None;
+
+    ctx.events_loop.poll_events(|event| {
+        let event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return,
+        };
+
+        match event {
+            WindowEvent::CloseRequested => input = Some(ScreenInput::Quit),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Released,
+                        virtual_keycode: Some(vkc),
+                        ..
+                    },
+                ..
+            } => match vkc {
+                VirtualKeyCode::Return => input = Some(ScreenInput::Confirm),
+                VirtualKeyCode::Escape => input = Some(ScreenInput::Quit),
+                _ => (),
+            },
+            _ => (),
+        }
+    });
+
+    ctx.client.maintain(&mut ctx.world);
+    ctx.world.services.changed_flags.clear();
+
+    let mut target = ctx.display.draw();
+    let (r, g, b, a) = clear_color;
+    target.clear_color(r, g, b, a);
+    target.finish().unwrap();
+
+    thread::sleep(FRAME_DURATION);
+
+    input
+}
+
+fn describe_reason(ctx: &ClientContext, reason: DisconnectReason) -> String {
+    match reason {
+        DisconnectReason::Unknown => "lost connection to the server".to_string(),
+        DisconnectReason::MalformedPackets => "kicked for sending malformed packets".to_string(),
+        DisconnectReason::SessionTakenOver => "logged in from somewhere else".to_string(),
+        DisconnectReason::TimedOut => "server did not respond".to_string(),
+        DisconnectReason::Cheating => "kicked for cheating".to_string(),
+        DisconnectReason::Kicked => "kicked by the server".to_string(),
+        DisconnectReason::Banned => "banned from the server".to_string(),
+        DisconnectReason::ServerShutdown => "server shut down".to_string(),
+        DisconnectReason::JoinRejected => match ctx.client.join_rejection() {
+            Some(rejection) => format!("could not join, {}", rejection),
+            None => "could not join".to_string(),
+        },
+    }
+}
+
+/// Waits for the connection started by `net::Client::start_connect` to be established,
+/// retrying up to `MAX_CONNECT_ATTEMPTS` times.
+pub struct ConnectingState {
+    ctx: ClientContext,
+}
+
+impl ConnectingState {
+    pub fn new(ctx: ClientContext) -> ConnectingState {
+        ConnectingState { ctx }
+    }
+}
+
+impl State<ClientTransition> for ConnectingState {
+    fn run(self: Box<Self>) -> ClientTransition {
+        let mut ctx = self.ctx;
+
+        let set_title = |ctx: &ClientContext, attempt: u32| {
+            ctx.set_title(&format!(
+                "crufty - connecting (attempt {}/{})",
+                attempt, MAX_CONNECT_ATTEMPTS
+            ));
+        };
+
+        let mut attempt = 1;
+        let mut attempt_start = Instant::now();
+        set_title(&ctx, attempt);
+
+        loop {
+            if let Some(ScreenInput::Quit) = run_frame(&mut ctx, CONNECTING_COLOR) {
+                return ClientTransition::Shutdown;
+            }
+
+            let attempt_failed = match ctx.client.status() {
+                ConnectionStatus::Connecting => attempt_start.elapsed() >= CONNECT_TIMEOUT,
+                ConnectionStatus::WaitingForSnapshot | ConnectionStatus::InGame => {
+                    return ClientTransition::WaitForSnapshot(ctx);
+                }
+                // enet gave up on the connection attempt by itself
+                ConnectionStatus::Disconnected(DisconnectReason::Unknown) => true,
+                ConnectionStatus::Disconnected(reason) => {
+                    return ClientTransition::Disconnected(ctx, reason);
+                }
+            };
+
+            if attempt_failed {
+                ctx.client.abort_connect(&mut ctx.world);
+
+                if attempt == MAX_CONNECT_ATTEMPTS {
+                    return ClientTransition::Disconnected(ctx, DisconnectReason::TimedOut);
+                }
+
+                ctx.client.reconnect();
+                attempt += 1;
+                attempt_start = Instant::now();
+                set_title(&ctx, attempt);
+            }
+        }
+    }
+}
+
+/// Waits for the server to welcome us and send the initial snapshot of the world.
+pub struct WaitingForSnapshotState {
+    ctx: ClientContext,
+}
+
+impl WaitingForSnapshotState {
+    pub fn new(ctx: ClientContext) -> WaitingForSnapshotState {
+        WaitingForSnapshotState { ctx }
+    }
+}
+
+impl State<ClientTransition> for WaitingForSnapshotState {
+    fn run(self: Box<Self>) -> ClientTransition {
+        let mut ctx = self.ctx;
+        ctx.set_title("crufty - loading world");
+
+        let start = Instant::now();
+
+        loop {
+            if let Some(ScreenInput::Quit) = run_frame(&mut ctx, CONNECTING_COLOR) {
+                return ClientTransition::Shutdown;
+            }
+
+            match ctx.client.status() {
+                ConnectionStatus::InGame => return ClientTransition::StartGame(ctx),
+                ConnectionStatus::Disconnected(reason) => {
+                    return ClientTransition::Disconnected(ctx, reason);
+                }
+                _ if start.elapsed() >= SNAPSHOT_TIMEOUT => {
+                    ctx.client.abort_connect(&mut ctx.world);
+                    return ClientTransition::Disconnected(ctx, DisconnectReason::TimedOut);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Shows why we were disconnected, and lets the player reconnect or quit.
+pub struct DisconnectedState {
+    ctx: ClientContext,
+    reason: DisconnectReason,
+}
+
+impl DisconnectedState {
+    pub fn new(ctx: ClientContext, reason: DisconnectReason) -> DisconnectedState {
+        DisconnectedState { ctx, reason }
+    }
+}
+
+impl State<ClientTransition> for DisconnectedState {
+    fn run(self: Box<Self>) -> ClientTransition {
+        let DisconnectedState { mut ctx, reason } = *self;
+        ctx.set_title(&format!(
+            "crufty - disconnected: {} (Enter to reconnect, Escape to quit)",
+            describe_reason(&ctx, reason)
+        ));
+
+        loop {
+            match run_frame(&mut ctx, DISCONNECTED_COLOR) {
+                Some(ScreenInput::Confirm) => {
+                    // reconnects with our session token, so we get our player back
+                    ctx.client.reconnect();
+                    return ClientTransition::Connect(ctx);
+                }
+                Some(ScreenInput::Quit) => return ClientTransition::Shutdown,
+                None => (),
+            }
+        }
+    }
+}
@@ -1,64 +1,45 @@
-use std::path::Path;
 use std::thread;
 use std::time::Duration;
 
-use ecs::{system::InteractSystem, World};
-use glium;
 use glium::glutin::{self, ElementState, VirtualKeyCode};
 
-use crate::application::{client::ClientTransition, InputManager};
-use crate::components::LevelComponents;
-use crate::game::ResourceStore;
+use crate::application::client::{ClientContext, ClientTransition};
+use crate::application::{InputManager, IntentCollector, TextEntry};
+use crate::game::prefabs;
 use crate::net;
-use crate::systems::{LevelSystems, RenderSystem};
+use crate::net::chat::{self, ChatError, ChatScope};
+use crate::net::demo::PlaybackCommand;
 use crate::util::State;
 
+// chat messages scrolled per page up or down
+const CHAT_SCROLL_LINES: usize = 5;
+
 pub struct GameState {
-    display: glium::Display,
-    events_loop: glutin::EventsLoop,
-    client: net::Client,
+    ctx: ClientContext,
 }
 
 impl GameState {
-    pub fn new(
-        display: glium::Display,
-        events_loop: glutin::EventsLoop,
-        client: net::Client,
-    ) -> GameState {
-        GameState {
-            display,
-            events_loop,
-            client,
-        }
+    pub fn new(ctx: ClientContext) -> GameState {
+        GameState { ctx }
     }
 }
 
 impl State<ClientTransition> for GameState {
-    fn run(mut self: Box<Self>) -> ClientTransition {
-        let mut world = World::<LevelSystems>::new();
-
-        let (_width, _height) = self.display.get_framebuffer_dimensions();
-        let render_system = RenderSystem::new(self.display.clone());
-
-        world.services.resource_store = ResourceStore::new(self.display.clone());
+    fn run(self: Box<Self>) -> ClientTransition {
+        let mut ctx = self.ctx;
+        ctx.set_title("crufty");
 
-        let _ss_handle = world
-            .services
-            .resource_store
-            .load_sprite_sheet(Path::new("assets/textures/sprites/player/animations.toml"));
-
-        world.systems.render_system.init(InteractSystem::new(
-            render_system,
-            aspect!(<LevelComponents> all: [camera]),
-            aspect!(<LevelComponents> all: [position]),
-        ));
-
-        process!(world, camera_system);
+        let ClientContext {
+            ref mut events_loop,
+            ref mut client,
+            ref mut world,
+            ..
+        } = ctx;
 
         let mut profiler_ticks = 0;
 
         let mut previous_time = clock_ticks::precise_time_ns();
-        let mut _lag_behind_simulation = 0u64;
+        let mut lag_behind_simulation = 0u64;
 
         // change these
         const MS_PER_UPDATE: u64 = 10;
@@ -77,6 +58,8 @@ impl State<ClientTransition> for GameState {
         world.services.delta_time_s = (MS_PER_UPDATE as f32) / 1000.0;
 
         let mut input_manager = InputManager::new();
+        let mut intent_collector = IntentCollector::new(prefabs::player_input_context());
+        let mut chat_scope = ChatScope::All;
 
         loop {
             hprof::start_frame();
@@ -84,14 +67,16 @@ impl State<ClientTransition> for GameState {
             let current_time = clock_ticks::precise_time_ns();
             let elapsed = current_time - previous_time;
             previous_time = current_time;
-            _lag_behind_simulation += elapsed;
+            lag_behind_simulation += elapsed;
 
             {
                 let _ = hprof::enter("window-events");
 
                 let mut shutdown = false;
+                let mut playback_command = None;
+                let mut open_chat = None;
 
-                self.events_loop.poll_events(|event| {
+                events_loop.poll_events(|event| {
                     use self::glutin::{dpi::LogicalSize, Event, KeyboardInput, WindowEvent};
                     let event = match event {
                         Event::WindowEvent {
@@ -114,6 +99,20 @@ impl State<ClientTransition> for GameState {
                                 },
                             ..
                         } => match (key_state, vkc) {
+                            // while typing a chat message, keys only edit it
+                            _ if input_manager.handle_text_key(key_state, vkc) => (),
+                            (ElementState::Pressed, VirtualKeyCode::Return) => {
+                                open_chat = Some(ChatScope::All)
+                            }
+                            (ElementState::Pressed, VirtualKeyCode::T) => {
+                                open_chat = Some(ChatScope::Team)
+                            }
+                            (ElementState::Released, VirtualKeyCode::PageUp) => {
+                                client.chat_log_mut().scroll_up(CHAT_SCROLL_LINES)
+                            }
+                            (ElementState::Released, VirtualKeyCode::PageDown) => {
+                                client.chat_log_mut().scroll_down(CHAT_SCROLL_LINES)
+                            }
                             (ElementState::Released, VirtualKeyCode::P) => profiler_ticks += 3,
                             (ElementState::Released, VirtualKeyCode::D) => {
                                 world
@@ -125,8 +124,24 @@ impl State<ClientTransition> for GameState {
                                     .toggle_physics_debug_render();
                             }
                             (ElementState::Released, VirtualKeyCode::Escape) => shutdown = true,
+                            (ElementState::Released, VirtualKeyCode::F1) => {
+                                playback_command = Some(PlaybackCommand::TogglePause)
+                            }
+                            (ElementState::Released, VirtualKeyCode::F2) => {
+                                playback_command = Some(PlaybackCommand::Slower)
+                            }
+                            (ElementState::Released, VirtualKeyCode::F3) => {
+                                playback_command = Some(PlaybackCommand::Faster)
+                            }
+                            (ElementState::Released, VirtualKeyCode::F4) => {
+                                playback_command = Some(PlaybackCommand::SeekBackward)
+                            }
+                            (ElementState::Released, VirtualKeyCode::F5) => {
+                                playback_command = Some(PlaybackCommand::SeekForward)
+                            }
                             _ => input_manager.handle_event(key_state, vkc),
                         },
+                        WindowEvent::ReceivedCharacter(c) => input_manager.handle_char(c),
                         WindowEvent::Resized(LogicalSize { width, height }) => {
                             world.systems.camera_system.resized =
                                 Some((width as u32, height as u32));
@@ -139,21 +154,45 @@ impl State<ClientTransition> for GameState {
                 if shutdown {
                     return ClientTransition::Shutdown;
                 }
+
+                if let Some(command) = playback_command {
+                    client.control_playback(command, world);
+                }
+
+                // started only now, so the character of the key opening the chat is not typed
+                if let Some(scope) = open_chat {
+                    chat_scope = scope;
+                    input_manager.begin_text_entry(chat::MAX_CHAT_LENGTH);
+                }
+
+                if let Some(TextEntry::Submitted(text)) = input_manager.take_finished_text_entry() {
+                    match client.send_chat(chat_scope, &text) {
+                        Ok(()) | Err(ChatError::Empty) => (),
+                        Err(err) => println!("could not send chat message: {}", err),
+                    }
+                }
             }
 
-            // input_manager.dispatch(&mut world.systems.keyboard_system.inner);
-            // input_manager.end_frame();
+            // our player is simulated by the server, so we only send it what we intend to do
+            input_manager.dispatch(&mut intent_collector);
+            input_manager.end_frame();
+            client.send_intents(&intent_collector.take_intents());
 
-            // while lag_behind_simulation >= NS_PER_UPDATE {
-            //     let _ = hprof::enter("world-update");
-            //     world.update();
-            //     lag_behind_simulation -= NS_PER_UPDATE;
-            // }
+            // the server simulates the world, we only advance animations locally
+            while lag_behind_simulation >= NS_PER_UPDATE {
+                let _ = hprof::enter("world-update");
+                process!(world, sprite_sheet_animation_system);
+                lag_behind_simulation -= NS_PER_UPDATE;
+            }
 
             // process!(world, intent_system);
             process!(world, render_system);
 
-            self.client.maintain(&mut world);
+            client.maintain(world);
+
+            if let net::ConnectionStatus::Disconnected(reason) = client.status() {
+                return ClientTransition::Disconnected(ctx, reason);
+            }
 
             world.services.changed_flags.clear();
 
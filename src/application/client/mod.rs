@@ -1,17 +1,85 @@
+mod connection;
 mod gamestate;
 
-use std::net::Ipv4Addr;
+use std::env;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use ecs::{system::InteractSystem, World};
 use glium::{self, glutin};
 
-use crate::net;
+use crate::components::LevelComponents;
+use crate::game::ResourceStore;
+use crate::net::{self, discovery, DisconnectReason};
+use crate::systems::{LevelSystems, RenderSystem};
 use crate::util::{State, Transition};
 
+use self::connection::{ConnectingState, DisconnectedState, WaitingForSnapshotState};
 use self::gamestate::GameState;
 
+/// Everything the client keeps across states, so that e.g. reconnecting
+/// does not have to recreate the window.
+pub struct ClientContext {
+    display: glium::Display,
+    events_loop: glutin::EventsLoop,
+    client: net::Client,
+    world: World<LevelSystems>,
+}
+
+impl ClientContext {
+    fn new(
+        display: glium::Display,
+        events_loop: glutin::EventsLoop,
+        client: net::Client,
+    ) -> ClientContext {
+        let mut world = World::<LevelSystems>::new();
+
+        let render_system = RenderSystem::new(display.clone());
+
+        world.services.resource_store = ResourceStore::new(display.clone());
+
+        let _ss_handle = world
+            .services
+            .resource_store
+            .load_sprite_sheet(Path::new("assets/textures/sprites/player/animations.toml"));
+
+        world.systems.render_system.init(InteractSystem::new(
+            render_system,
+            aspect!(<LevelComponents> all: [camera]),
+            aspect!(<LevelComponents> all: [position]),
+        ));
+
+        process!(world, camera_system);
+
+        ClientContext {
+            display,
+            events_loop,
+            client,
+            world,
+        }
+    }
+
+    fn set_title(&self, title: &str) {
+        self.display.gl_window().window().set_title(title);
+    }
+}
+
+/// The name to join servers with.
+pub const PLAYER_NAME_ENV_VAR: &str = "CRUFTY_PLAYER_NAME";
+
+// how long to wait for servers to answer when looking for them on startup
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub enum ClientTransition {
+    // connects to the first compatible server on the local network
     Startup,
-    StartGame(glium::Display, glutin::EventsLoop, net::Client),
+    ConnectTo(SocketAddrV4),
+    PlayDemo(PathBuf),
+    Connect(ClientContext),
+    WaitForSnapshot(ClientContext),
+    StartGame(ClientContext),
+    Disconnected(ClientContext, DisconnectReason),
     Shutdown,
     TerminateApplication,
 }
@@ -19,15 +87,70 @@ pub enum ClientTransition {
 impl Transition for ClientTransition {
     fn create_state(self) -> Option<Box<dyn State<ClientTransition>>> {
         match self {
-            ClientTransition::Startup => Some(Box::new(StartupState)),
-            ClientTransition::StartGame(d, el, c) => Some(Box::new(GameState::new(d, el, c))),
+            ClientTransition::Startup => Some(Box::new(StartupState {
+                mode: StartupMode::Discover,
+            })),
+            ClientTransition::ConnectTo(addr) => Some(Box::new(StartupState {
+                mode: StartupMode::Connect(addr),
+            })),
+            ClientTransition::PlayDemo(path) => Some(Box::new(StartupState {
+                mode: StartupMode::PlayDemo(path),
+            })),
+            ClientTransition::Connect(ctx) => Some(Box::new(ConnectingState::new(ctx))),
+            ClientTransition::WaitForSnapshot(ctx) => {
+                Some(Box::new(WaitingForSnapshotState::new(ctx)))
+            }
+            ClientTransition::StartGame(ctx) => Some(Box::new(GameState::new(ctx))),
+            ClientTransition::Disconnected(ctx, reason) => {
+                Some(Box::new(DisconnectedState::new(ctx, reason)))
+            }
             ClientTransition::Shutdown => Some(Box::new(ShutdownState)),
             ClientTransition::TerminateApplication => None,
         }
     }
 }
 
-pub struct StartupState;
+enum StartupMode {
+    Discover,
+    Connect(SocketAddrV4),
+    // play back this demo instead of connecting to a server
+    PlayDemo(PathBuf),
+}
+
+/// Looks for servers on the local network, and picks the first compatible one.
+/// Falls back to a server on this machine if none answered.
+fn discover_server() -> SocketAddrV4 {
+    let fallback = SocketAddrV4::new(Ipv4Addr::LOCALHOST, net::PORT);
+
+    let servers = match discovery::query_lan(DISCOVERY_TIMEOUT) {
+        Ok(servers) => servers,
+        Err(err) => {
+            println!("could not look for servers: {}", err);
+            return fallback;
+        }
+    };
+
+    for server in &servers {
+        println!(
+            "found {} at {}: map {}, {}/{} players{}",
+            server.info.name,
+            server.game_addr(),
+            server.info.map,
+            server.info.num_players,
+            server.info.max_peers,
+            if server.is_compatible() { "" } else { " (incompatible version)" }
+        );
+    }
+
+    servers
+        .iter()
+        .find(|server| server.is_compatible())
+        .map_or(fallback, |server| server.game_addr())
+}
+
+pub struct StartupState {
+    mode: StartupMode,
+}
 
 impl State<ClientTransition> for StartupState {
     fn run(self: Box<Self>) -> ClientTransition {
@@ -41,10 +164,32 @@ impl State<ClientTransition> for StartupState {
 
         let display = glium::Display::new(window, context, &events_loop).unwrap();
 
+        let server_addr = match self.mode {
+            StartupMode::Discover => discover_server(),
+            StartupMode::Connect(addr) => addr,
+            StartupMode::PlayDemo(ref path) => {
+                let client = net::Client::from_demo(path).expect("could not open demo file");
+                return ClientTransition::StartGame(ClientContext::new(
+                    display,
+                    events_loop,
+                    client,
+                ));
+            }
+        };
+
         let mut client = net::Client::new();
-        client.start_connect(Ipv4Addr::LOCALHOST);
 
-        ClientTransition::StartGame(display, events_loop, client)
+        if let Some(path) = env::var_os(net::CAPTURE_ENV_VAR) {
+            client
+                .enable_capture(Path::new(&path))
+                .expect("could not create capture file");
+        }
+
+        let name = env::var(PLAYER_NAME_ENV_VAR).unwrap_or_else(|_| "player".to_string());
+        client.set_credentials(name, env::var(net::PASSWORD_ENV_VAR).ok());
+
+        client.start_connect(server_addr);
+        ClientTransition::Connect(ClientContext::new(display, events_loop, client))
     }
 }
 
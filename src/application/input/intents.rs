@@ -5,4 +5,6 @@ pub enum InputIntent {
     MoveRight,
     Jump,
     Interact,
+    // together with `Jump`, drops through one-way platforms
+    MoveDown,
 }
@@ -2,6 +2,7 @@ use glium::glutin::{ElementState, VirtualKeyCode};
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::mem;
 
 mod intents;
 
@@ -58,11 +59,23 @@ pub trait KeyHandler {
     fn handle_key(&mut self, state: InputState, key: VirtualKeyCode) -> bool;
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextEntry {
+    Submitted(String),
+    Cancelled,
+}
+
 pub struct InputManager {
     keyboard_state: KeyboardState,
 
     new_this_frame: HashSet<VirtualKeyCode>,
     consumed: HashSet<VirtualKeyCode>,
+
+    // the text typed so far and its maximum length in chars, while entering text
+    text_entry: Option<(String, usize)>,
+    finished_text_entry: Option<TextEntry>,
+    // keys pressed while entering text, their release must not trigger anything either
+    text_keys: HashSet<VirtualKeyCode>,
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -74,6 +87,39 @@ pub struct InputContextKey(
 
 pub type InputContext = HashMap<InputContextKey, InputIntent>;
 
+/// Collects the intents an `InputContext` maps the pressed keys to, e.g. for sending them
+/// to the server instead of applying them to a local entity.
+pub struct IntentCollector {
+    context: InputContext,
+    intents: HashSet<InputIntent>,
+}
+
+impl IntentCollector {
+    pub fn new(context: InputContext) -> IntentCollector {
+        IntentCollector {
+            context,
+            intents: HashSet::new(),
+        }
+    }
+
+    /// The intents collected since the last call.
+    pub fn take_intents(&mut self) -> HashSet<InputIntent> {
+        std::mem::replace(&mut self.intents, HashSet::new())
+    }
+}
+
+impl KeyHandler for IntentCollector {
+    fn handle_key(&mut self, state: InputState, key: VirtualKeyCode) -> bool {
+        match self.context.get(&InputContextKey(key, state)) {
+            Some(&intent) => {
+                self.intents.insert(intent);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
 impl Default for InputManager {
     fn default() -> InputManager {
         InputManager::new()
@@ -86,15 +132,95 @@ impl InputManager {
             keyboard_state: KeyboardState::new(),
             new_this_frame: HashSet::new(),
             consumed: HashSet::new(),
+            text_entry: None,
+            finished_text_entry: None,
+            text_keys: HashSet::new(),
+        }
+    }
+
+    /// Starts entering text, until return submits it or escape cancels it. Meanwhile, keys are
+    /// not dispatched, so typing does not trigger any `InputIntent`s.
+    pub fn begin_text_entry(&mut self, max_length: usize) {
+        self.text_entry = Some((String::new(), max_length));
+        self.finished_text_entry = None;
+    }
+
+    pub fn is_entering_text(&self) -> bool {
+        self.text_entry.is_some()
+    }
+
+    /// The text typed so far, while entering text.
+    pub fn entered_text(&self) -> Option<&str> {
+        self.text_entry.as_ref().map(|(text, _)| text.as_str())
+    }
+
+    /// How the last text entry finished, once it did.
+    pub fn take_finished_text_entry(&mut self) -> Option<TextEntry> {
+        self.finished_text_entry.take()
+    }
+
+    /// Adds a character received from the window to the entered text.
+    pub fn handle_char(&mut self, c: char) {
+        if let Some((ref mut text, max_length)) = self.text_entry {
+            // return, escape and backspace arrive as control characters, see `handle_text_key`
+            if !c.is_control() && text.chars().count() < max_length {
+                text.push(c);
+            }
         }
     }
 
+    /// Handles keys which belong to text entry. Returns whether the key was consumed, in which
+    /// case it must not be handled any further.
+    pub fn handle_text_key(&mut self, state: ElementState, vkc: VirtualKeyCode) -> bool {
+        let (text, _) = match self.text_entry {
+            Some(ref mut text_entry) => text_entry,
+            None => {
+                let pressed_while_entering_text = state == ElementState::Released
+                    && self.text_keys.remove(&vkc);
+
+                return pressed_while_entering_text;
+            }
+        };
+
+        // keeps track of held keys, so they are not stuck once text entry is done
+        self.keyboard_state.handle_event(state, vkc);
+
+        if state == ElementState::Released {
+            self.text_keys.remove(&vkc);
+            return true;
+        }
+
+        self.text_keys.insert(vkc);
+
+        match vkc {
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                let text = mem::replace(text, String::new());
+                self.finished_text_entry = Some(TextEntry::Submitted(text));
+                self.text_entry = None;
+            }
+            VirtualKeyCode::Escape => {
+                self.finished_text_entry = Some(TextEntry::Cancelled);
+                self.text_entry = None;
+            }
+            VirtualKeyCode::Back => {
+                text.pop();
+            }
+            _ => (),
+        }
+
+        true
+    }
+
     pub fn handle_event(&mut self, state: ElementState, vkc: VirtualKeyCode) {
         self.keyboard_state.handle_event(state, vkc);
         self.new_this_frame.insert(vkc);
     }
 
     pub fn dispatch<T: KeyHandler>(&mut self, key_handler: &mut T) {
+        if self.is_entering_text() {
+            return;
+        }
+
         for vkc in &self.new_this_frame {
             if self.consumed.contains(&vkc) {
                 continue;
@@ -5,6 +5,7 @@ pub mod server;
 
 pub use self::client::ClientTransition;
 pub use self::input::{
-    InputContext, InputContextKey, InputIntent, InputManager, InputState, KeyHandler,
+    InputContext, InputContextKey, InputIntent, InputManager, InputState, IntentCollector,
+    KeyHandler, TextEntry,
 };
 pub use self::server::ServerTransition;
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 
@@ -7,20 +7,18 @@ use glium;
 use glium::glutin::{self, ElementState, VirtualKeyCode};
 
 use ecs::system::InteractSystem;
-use ecs::{BuildData /* , ModifyData */, World};
+use ecs::{BuildData /* , ModifyData */, Entity, World};
 
-use crate::application::{
-    client::ClientTransition, server::ServerTransition, InputContextKey, InputIntent, InputManager,
-    InputState,
-};
-use crate::game::{Interaction, ResourceStore};
-use crate::net;
+use crate::application::{client::ClientTransition, server::ServerTransition, InputManager};
+use crate::game::input_replay::{self, InputRecording};
+use crate::game::room::Room;
+use crate::game::{prefabs, Interaction, ResourceStore};
+use crate::net::{self, AdminRequest};
 use crate::util::State;
 
 use crate::components::{
-    Camera, CollisionShape, CollisionType, Facing, Gravity, Intents, InteractionPossibility,
-    Interactor, Jump, KeyboardInput, LevelComponents, Movement, Position, Sprite, SpriteInfo,
-    SpriteLayer, SpriteSheetAnimation, Velocity,
+    Camera, CollisionShape, CollisionType, InteractionPossibility, KeyboardInput, LevelComponents,
+    PlayerName, Position, Sprite, SpriteInfo, SpriteLayer,
 };
 use crate::systems::{LevelSystems, RenderSystem, WorldViewport};
 
@@ -34,6 +32,66 @@ use crate::nc::shape::Cuboid;
 
 use crate::resources::TextureSlug;
 
+// tiles of the room, the warp block is added by `create_room`
+const ROOM_PATH: &str = "assets/rooms/start.tmx";
+
+/// Creates the entities of `room`, and returns them so the room can be rebuilt.
+fn create_room(world: &mut World<LevelSystems>, room: &Room) -> Vec<Entity> {
+    let player_tex_info = TextureSlug::sprites__player__stand__p_stand.texture_info();
+
+    let mut entities = room.create_entities(world);
+
+    entities.push({
+        let position = Position {
+            x: 10. * 32.0,
+            y: 32.0,
+        };
+        let collision_shape = CollisionShape::new_single(
+            Cuboid::new(Vector2::new(16.0, 16.0)),
+            Vector2::new(16.0, 16.0),
+            CollisionType::Trigger,
+        );
+        let interaction_possibility = InteractionPossibility {
+            interaction: Interaction::WarpInRoom { x: 0.0, y: 500.0 },
+        };
+        let sprite = Sprite {
+            info: SpriteInfo {
+                width: 32.0,
+                height: 32.0,
+                texture_info: player_tex_info,
+            },
+            sprite_layer: SpriteLayer::Background,
+        };
+
+        let _e = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, position);
+                data.collision_shape.add(&entity, collision_shape.clone());
+                data.interaction_possibility
+                    .add(&entity, interaction_possibility);
+                data.sprite.add(&entity, sprite.clone());
+            },
+        );
+
+        world.services.changed_flags.position.insert(_e, position);
+        world
+            .services
+            .changed_flags
+            .collision_shape
+            .insert(_e, collision_shape);
+        world
+            .services
+            .changed_flags
+            .interaction_possibility
+            .insert(_e, interaction_possibility);
+        world.services.changed_flags.sprite.insert(_e, sprite);
+
+        _e
+    });
+
+    entities
+}
+
 pub struct GameState {
     display: glium::Display,
     events_loop: glutin::EventsLoop,
@@ -69,22 +127,6 @@ impl State<ServerTransition> for GameState {
             aspect!(<LevelComponents> all: [position]),
         ));
 
-        let tile_tex_info = TextureSlug::tilesets__cave__tile1.texture_info();
-        let player_tex_info = TextureSlug::sprites__player__stand__p_stand.texture_info();
-
-        let ss_handle = world
-            .services
-            .resource_store
-            .load_sprite_sheet(Path::new("assets/textures/sprites/player/animations.toml"));
-
-        let player_stand_animation = world
-            .services
-            .resource_store
-            .get_sprite_sheet(ss_handle)
-            .get("stand")
-            .unwrap()
-            .clone();
-
         let _ = world.create_entity(
             |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
                 data.position.add(&entity, Position { x: 0.0, y: 0.0 });
@@ -100,267 +142,21 @@ impl State<ServerTransition> for GameState {
         );
 
         let _player = {
-            let position = Position {
-                x: 8.0 * 32.0 + 0.0 * 10.0,
-                // y: 500.0,
-                y: 0.0,
-            };
-            let velocity = Velocity {
-                vx: 00.0,
-                vy: 00.0,
-                last_pos: position,
-            };
-            let collision_shape = CollisionShape::new_dual(
-                Cuboid::new(Vector2::new(16.0, 5.0)),
-                Vector2::new(16.0, 16.0),
-                Cuboid::new(Vector2::new(5.0, 16.0)),
-                Vector2::new(16.0, 16.0),
-                CollisionType::Solid,
-            );
-            let movement = Movement::new(Vector2::new(110.0, 0.0), Vector2::new(1000.0, 0.0));
-            let facing = Facing::Right;
-            let jump = Jump::new();
-            let gravity = Gravity::new();
-            let sprite = Sprite {
-                info: SpriteInfo {
-                    width: 32.0,
-                    height: 32.0,
-                    texture_info: player_tex_info,
-                },
-                sprite_layer: SpriteLayer::Foreground,
-            };
-            let ss_anim = SpriteSheetAnimation {
-                sheet_handle: ss_handle,
-                animation: player_stand_animation.clone(),
-                current_frame: 0,
-                frame_time_remaining: 0.1,
-            };
-            let intents = Intents::new();
-            let interactor = Interactor;
             let kb_input = KeyboardInput {
-                input_context: {
-                    let mut inputs = HashMap::new();
-                    inputs.insert(
-                        InputContextKey(VirtualKeyCode::O, InputState::PressedThisFrame),
-                        InputIntent::PrintDebugMessage,
-                    );
-                    inputs.insert(
-                        InputContextKey(VirtualKeyCode::Left, InputState::Pressed),
-                        InputIntent::MoveLeft,
-                    );
-                    inputs.insert(
-                        InputContextKey(VirtualKeyCode::Right, InputState::Pressed),
-                        InputIntent::MoveRight,
-                    );
-                    inputs.insert(
-                        InputContextKey(VirtualKeyCode::Space, InputState::Pressed),
-                        InputIntent::Jump,
-                    );
-                    inputs.insert(
-                        InputContextKey(VirtualKeyCode::E, InputState::PressedThisFrame),
-                        InputIntent::Interact,
-                    );
-                    inputs
-                },
+                input_context: prefabs::player_input_context(),
             };
 
-            let _player = world.create_entity(
-                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
-                    data.position.add(&entity, position);
-                    data.velocity.add(&entity, velocity);
-                    data.collision_shape.add(&entity, collision_shape.clone());
-                    data.movement.add(&entity, movement.clone());
-                    data.facing.add(&entity, facing);
-                    data.jump.add(&entity, jump);
-                    data.gravity.add(&entity, gravity);
-                    data.sprite.add(&entity, sprite.clone());
-                    data.sprite_sheet_animation.add(&entity, ss_anim.clone());
-                    data.intents.add(&entity, intents.clone());
-                    data.interactor.add(&entity, interactor);
-                    data.keyboard_input.add(&entity, kb_input.clone());
-                },
-            );
-
-            world
-                .services
-                .changed_flags
-                .position
-                .insert(_player, position);
-            world
-                .services
-                .changed_flags
-                .velocity
-                .insert(_player, velocity);
-            world
-                .services
-                .changed_flags
-                .collision_shape
-                .insert(_player, collision_shape);
-            world
-                .services
-                .changed_flags
-                .movement
-                .insert(_player, movement);
-            world.services.changed_flags.facing.insert(_player, facing);
-            world.services.changed_flags.jump.insert(_player, jump);
-            world
-                .services
-                .changed_flags
-                .gravity
-                .insert(_player, gravity);
-            world.services.changed_flags.sprite.insert(_player, sprite);
-            world
-                .services
-                .changed_flags
-                .sprite_sheet_animation
-                .insert(_player, ss_anim);
-            world
-                .services
-                .changed_flags
-                .intents
-                .insert(_player, intents);
-            world
-                .services
-                .changed_flags
-                .interactor
-                .insert(_player, interactor);
-            world
-                .services
-                .changed_flags
-                .keyboard_input
-                .insert(_player, kb_input);
-
-            _player
+            prefabs::create_player(
+                &mut world,
+                prefabs::PLAYER_SPAWN,
+                PlayerName("host".to_string()),
+                Some(kb_input),
+            )
         };
 
-        for x in 0..12 {
-            let _ = {
-                let position = Position {
-                    x: (x as f32) * 32.0,
-                    y: 0.0,
-                };
-                let collision_shape = CollisionShape::new_single(
-                    Cuboid::new(Vector2::new(16.0, 16.0)),
-                    Vector2::new(16.0, 16.0),
-                    CollisionType::Solid,
-                );
-                let sprite = Sprite {
-                    info: SpriteInfo {
-                        width: 32.0,
-                        height: 32.0,
-                        texture_info: tile_tex_info,
-                    },
-                    sprite_layer: SpriteLayer::Background,
-                };
-
-                let _e = world.create_entity(
-                    |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
-                        data.position.add(&entity, position);
-                        data.collision_shape.add(&entity, collision_shape.clone());
-                        data.sprite.add(&entity, sprite.clone());
-                    },
-                );
-
-                world.services.changed_flags.position.insert(_e, position);
-
-                world
-                    .services
-                    .changed_flags
-                    .collision_shape
-                    .insert(_e, collision_shape);
-                world.services.changed_flags.sprite.insert(_e, sprite);
-
-                _e
-            };
-        }
-
-        for x in 1..12 {
-            let _ = {
-                let position = Position {
-                    x: (x as f32) * 32.0,
-                    y: 96.0,
-                };
-                let collision_shape = CollisionShape::new_single(
-                    Cuboid::new(Vector2::new(16.0, 16.0)),
-                    Vector2::new(16.0, 16.0),
-                    CollisionType::Solid,
-                );
-                let sprite = Sprite {
-                    info: SpriteInfo {
-                        width: 32.0,
-                        height: 32.0,
-                        texture_info: tile_tex_info,
-                    },
-                    sprite_layer: SpriteLayer::Background,
-                };
-
-                let _e = world.create_entity(
-                    |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
-                        data.position.add(&entity, position);
-                        data.collision_shape.add(&entity, collision_shape.clone());
-                        data.sprite.add(&entity, sprite.clone());
-                    },
-                );
-
-                world.services.changed_flags.position.insert(_e, position);
-
-                world
-                    .services
-                    .changed_flags
-                    .collision_shape
-                    .insert(_e, collision_shape);
-                world.services.changed_flags.sprite.insert(_e, sprite);
-
-                _e
-            };
-        }
-
-        let _warp_block = {
-            let position = Position {
-                x: 10. * 32.0,
-                y: 32.0,
-            };
-            let collision_shape = CollisionShape::new_single(
-                Cuboid::new(Vector2::new(16.0, 16.0)),
-                Vector2::new(16.0, 16.0),
-                CollisionType::Trigger,
-            );
-            let interaction_possibility = InteractionPossibility {
-                interaction: Interaction::WarpInRoom { x: 0.0, y: 500.0 },
-            };
-            let sprite = Sprite {
-                info: SpriteInfo {
-                    width: 32.0,
-                    height: 32.0,
-                    texture_info: player_tex_info,
-                },
-                sprite_layer: SpriteLayer::Background,
-            };
-
-            let _e = world.create_entity(
-                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
-                    data.position.add(&entity, position);
-                    data.collision_shape.add(&entity, collision_shape.clone());
-                    data.interaction_possibility
-                        .add(&entity, interaction_possibility);
-                    data.sprite.add(&entity, sprite.clone());
-                },
-            );
-
-            world.services.changed_flags.position.insert(_e, position);
-            world
-                .services
-                .changed_flags
-                .collision_shape
-                .insert(_e, collision_shape);
-            world
-                .services
-                .changed_flags
-                .interaction_possibility
-                .insert(_e, interaction_possibility);
-            world.services.changed_flags.sprite.insert(_e, sprite);
-
-            _e
+        let mut room = {
+            let room = Room::load(Path::new(ROOM_PATH)).expect("could not load room");
+            create_room(&mut world, &room)
         };
 
         process!(world, camera_system);
@@ -388,6 +184,9 @@ impl State<ServerTransition> for GameState {
 
         let mut input_manager = InputManager::new();
 
+        let mut input_recording = env::var_os(input_replay::RECORDING_ENV_VAR)
+            .map(|path| (PathBuf::from(path), InputRecording::start(&mut world)));
+
         loop {
             hprof::start_frame();
 
@@ -396,11 +195,37 @@ impl State<ServerTransition> for GameState {
             previous_time = current_time;
             lag_behind_simulation += elapsed;
 
+            let mut shutdown = false;
+
+            // handled before updating, so peers receive the rebuilt room with the next update
+            for request in self.host.take_admin_requests() {
+                match request {
+                    AdminRequest::ReloadRoom => {
+                        let reply = match Room::load(Path::new(ROOM_PATH)) {
+                            Ok(new_room) => {
+                                for e in room.drain(..) {
+                                    self.host.remove_entity(&mut world, e);
+                                }
+
+                                room = create_room(&mut world, &new_room);
+                                Ok(Vec::new())
+                            }
+                            // keeps the current room, so a broken file does not kill the server
+                            Err(err) => {
+                                println!("could not reload room: {}", err);
+                                Err(format!("could not reload room: {}", err))
+                            }
+                        };
+
+                        self.host.finish_admin_request(request, reply);
+                    }
+                    AdminRequest::Shutdown => shutdown = true,
+                }
+            }
+
             {
                 let _ = hprof::enter("window-events");
 
-                let mut shutdown = false;
-
                 self.events_loop.poll_events(|event| {
                     use self::glutin::{dpi::LogicalSize, Event, KeyboardInput, WindowEvent};
                     let event = match event {
@@ -447,6 +272,12 @@ impl State<ServerTransition> for GameState {
                 });
 
                 if shutdown {
+                    self.host.shutdown();
+
+                    if let Some((path, recording)) = input_recording {
+                        recording.save(&path).expect("could not save input recording");
+                    }
+
                     return ServerTransition::Shutdown;
                 }
             }
@@ -457,6 +288,11 @@ impl State<ServerTransition> for GameState {
             while lag_behind_simulation >= NS_PER_UPDATE {
                 let _ = hprof::enter("world-update");
                 world.update();
+
+                if let Some((_, ref mut recording)) = input_recording {
+                    recording.record_tick(&world);
+                }
+
                 world.services.simulation_time += 1;
                 lag_behind_simulation -= NS_PER_UPDATE;
             }
@@ -467,6 +303,7 @@ impl State<ServerTransition> for GameState {
             self.host.maintain(&mut world);
 
             world.services.changed_flags.clear();
+            world.services.replicated_events.clear();
 
             hprof::end_frame();
 
@@ -1,5 +1,8 @@
 mod gamestate;
 
+use std::env;
+use std::path::Path;
+
 use glium::{self, glutin};
 
 use crate::net;
@@ -7,6 +10,15 @@ use crate::util::{State, Transition};
 
 use self::gamestate::GameState;
 
+/// If set, the server announces itself under this name instead of the default one.
+pub const SERVER_NAME_ENV_VAR: &str = "CRUFTY_SERVER_NAME";
+
+/// If set, the server enables the admin console with this password, see `net::admin`.
+pub const ADMIN_PASSWORD_ENV_VAR: &str = "CRUFTY_ADMIN_PASSWORD";
+
+/// If set, the admin console listens on this port instead of `net::ADMIN_PORT`.
+pub const ADMIN_PORT_ENV_VAR: &str = "CRUFTY_ADMIN_PORT";
+
 pub enum ServerTransition {
     Startup,
     StartGame(glium::Display, glutin::EventsLoop, net::Server),
@@ -39,7 +51,25 @@ impl State<ServerTransition> for StartupState {
 
         let display = glium::Display::new(window, context, &events_loop).unwrap();
 
-        let host = net::Server::new();
+        let mut config = net::ServerConfig::default();
+        if let Ok(name) = env::var(SERVER_NAME_ENV_VAR) {
+            config.name = name;
+        }
+        config.password = env::var(net::PASSWORD_ENV_VAR).ok();
+        config.admin_password = env::var(ADMIN_PASSWORD_ENV_VAR).ok();
+        if let Ok(port) = env::var(ADMIN_PORT_ENV_VAR) {
+            config.admin_port = port.parse().expect("invalid admin port");
+        }
+
+        let mut host = net::Server::new(config);
+
+        if let Some(path) = env::var_os(net::CAPTURE_ENV_VAR) {
+            host.enable_capture(Path::new(&path)).expect("could not create capture file");
+        }
+
+        if let Some(path) = env::var_os(net::DEMO_ENV_VAR) {
+            host.enable_demo_recording(Path::new(&path)).expect("could not create demo file");
+        }
 
         ServerTransition::StartGame(display, events_loop, host)
     }
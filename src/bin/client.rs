@@ -1,7 +1,27 @@
 extern crate crufty;
 
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
 use crufty::{application, util};
 
 fn main() {
-    util::run_state_machine(application::ClientTransition::Startup);
+    let mut args = env::args().skip(1);
+
+    let transition = match (args.next().as_ref().map(String::as_str), args.next()) {
+        (Some("--demo"), Some(path)) => {
+            application::ClientTransition::PlayDemo(PathBuf::from(path))
+        }
+        (Some("--connect"), Some(addr)) => match addr.parse() {
+            Ok(addr) => application::ClientTransition::ConnectTo(addr),
+            Err(_) => {
+                eprintln!("not a valid server address, expected ip:port: {}", addr);
+                process::exit(1);
+            }
+        },
+        _ => application::ClientTransition::Startup,
+    };
+
+    util::run_state_machine(transition);
 }
@@ -0,0 +1,136 @@
+extern crate crufty;
+extern crate ecs;
+extern crate nalgebra;
+extern crate ncollide2d;
+extern crate rand;
+
+use std::time::{Duration, Instant};
+
+use ecs::{BuildData, Entity, World};
+
+use nalgebra::Vector2;
+use ncollide2d::shape::Cuboid;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crufty::components::{CollisionShape, CollisionType, LevelComponents, Position};
+use crufty::systems::LevelSystems;
+use crufty::util::collision_world::{Collision, CollisionWorld};
+
+const TILE_SIZE: f32 = 32.0;
+const COLUMNS: usize = 300;
+const ROWS: usize = 100;
+const MOVERS: usize = 200;
+const TICKS: usize = 300;
+
+struct Scene {
+    tiles: Vec<(Entity, Position)>,
+    movers: Vec<(Entity, Position)>,
+}
+
+// a large cave of tiles, about half of them solid, and movers spread through it
+fn build_scene(world: &mut World<LevelSystems>) -> Scene {
+    let mut rng = StdRng::seed_from_u64(0x57e55);
+    let mut create =
+        || world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {});
+
+    let mut tiles = Vec::new();
+    for column in 0..COLUMNS {
+        for row in 0..ROWS {
+            if rng.gen_bool(0.5) {
+                let position = Position {
+                    x: column as f32 * TILE_SIZE,
+                    y: row as f32 * TILE_SIZE,
+                };
+                tiles.push((create(), position));
+            }
+        }
+    }
+
+    let movers = (0..MOVERS)
+        .map(|_| {
+            let position = Position {
+                x: rng.gen_range(0.0, COLUMNS as f32 * TILE_SIZE),
+                y: rng.gen_range(0.0, ROWS as f32 * TILE_SIZE),
+            };
+            (create(), position)
+        })
+        .collect();
+
+    Scene { tiles, movers }
+}
+
+fn solid_box(half_extents: Vector2<f32>) -> CollisionShape {
+    CollisionShape::new_single(
+        Cuboid::new(half_extents),
+        half_extents,
+        CollisionType::Solid,
+    )
+}
+
+// moves every mover around the scene for `TICKS` ticks, returns how long that took
+fn run(scene: &Scene, tiles_static: bool) -> Duration {
+    let tile = solid_box(Vector2::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0));
+    let mover = solid_box(Vector2::new(5.0, 8.0));
+
+    let mut collision_world = CollisionWorld::new();
+    for &(e, position) in &scene.tiles {
+        if tiles_static {
+            collision_world.add_static(e, &tile, position);
+        } else {
+            collision_world.add(e, &tile, position);
+        }
+    }
+
+    let mut movers = scene.movers.clone();
+    for &(e, position) in &movers {
+        collision_world.add(e, &mover, position);
+    }
+
+    // the same moves for both runs
+    let mut rng = StdRng::seed_from_u64(0x30e5);
+    let mut colls: Vec<Collision> = Vec::new();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        for (e, position) in &mut movers {
+            let new_pos = Position {
+                x: position.x + rng.gen_range(-4.0, 4.0),
+                y: position.y + rng.gen_range(-6.0, 2.0),
+            };
+            let last_pos = *position;
+
+            *position = collision_world
+                .move_entity(*e, &mover, new_pos, Some(&last_pos), &mut colls)
+                .position;
+            collision_world.on_ground(*e);
+            collision_world.touching_wall(*e);
+            colls.clear();
+        }
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let mut world = World::<LevelSystems>::new();
+    let scene = build_scene(&mut world);
+    println!(
+        "{} tiles, {} movers, {} ticks",
+        scene.tiles.len(),
+        scene.movers.len(),
+        TICKS
+    );
+
+    let in_trees = run(&scene, false);
+    println!("tiles in the trees: {:?}", in_trees);
+
+    let in_grid = run(&scene, true);
+    println!("tiles in the grid:  {:?}", in_grid);
+
+    println!(
+        "speedup: {:.2}x",
+        in_trees.as_secs_f64() / in_grid.as_secs_f64()
+    );
+}
@@ -0,0 +1,61 @@
+extern crate crufty;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use crufty::net::capture::{self, CaptureReader};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // without packet headers, the output of a server and a client capture can be diffed directly
+    let no_headers = args.iter().any(|arg| arg == "--no-headers");
+
+    let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: decode_capture [--no-headers] <capture file>");
+            process::exit(1);
+        }
+    };
+
+    let reader = CaptureReader::open(Path::new(path)).unwrap_or_else(|err| {
+        eprintln!("could not open {}: {}", path, err);
+        process::exit(1);
+    });
+
+    for packet in reader {
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(err) => {
+                eprintln!("capture file is truncated or corrupt: {}", err);
+                process::exit(1);
+            }
+        };
+
+        if !no_headers {
+            println!(
+                "[{:>12.3}ms] {:?} {} channel {} ({} bytes)",
+                packet.timestamp_ns as f64 / 1_000_000.0,
+                packet.direction,
+                packet.peer,
+                packet.channel_id,
+                packet.data.len()
+            );
+        }
+
+        match capture::describe_packet(&packet) {
+            Ok(lines) => {
+                for line in lines {
+                    if no_headers {
+                        println!("{}", line);
+                    } else {
+                        println!("    {}", line);
+                    }
+                }
+            }
+            Err(err) => println!("    could not decode packet: {}", err),
+        }
+    }
+}
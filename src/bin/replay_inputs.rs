@@ -0,0 +1,30 @@
+extern crate crufty;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use crufty::game::input_replay::InputRecording;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: replay_inputs <input recording>");
+            process::exit(1);
+        }
+    };
+
+    let recording = InputRecording::load(Path::new(&path)).unwrap_or_else(|err| {
+        eprintln!("could not load {}: {}", path, err);
+        process::exit(1);
+    });
+
+    match recording.replay() {
+        Ok(_) => println!("replayed {} ticks without divergence", recording.num_ticks()),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
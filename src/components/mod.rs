@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet};
+use std::ops::BitOr;
+use std::path::PathBuf;
 
 use ecs::Entity;
 use smallvec::SmallVec;
@@ -10,7 +12,7 @@ use crate::nc::shape::Cuboid;
 use crate::application::{InputContext, InputIntent};
 use crate::systems::WorldViewport;
 
-use crate::game::{self, Animation, SpriteSheetHandle, TextureInfo};
+use crate::game::{self, Animation, ResourceStore, SpriteSheetHandle, TextureInfo};
 
 use num::traits::Zero;
 
@@ -105,21 +107,200 @@ impl Gravity {
     }
 }
 
+/// How a `MovingPlatform` moves, in positions of the platform.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PlatformMotion {
+    /// Through `waypoints` at `speed` units per second, and from the last back to the first.
+    Path {
+        waypoints: Vec<Position>,
+        speed: f32,
+    },
+    /// Back and forth between `from` and `to`, returning to `from` after `period` seconds.
+    Oscillate {
+        from: Position,
+        to: Position,
+        period: f32,
+    },
+}
+
+impl PlatformMotion {
+    /// Where the platform is `time` seconds after it started moving.
+    pub fn position_at(&self, time: f32) -> Position {
+        match *self {
+            PlatformMotion::Path {
+                ref waypoints,
+                speed,
+            } => {
+                let segments = || {
+                    waypoints
+                        .iter()
+                        .zip(waypoints.iter().cycle().skip(1))
+                        .map(|(from, to)| (*from, *to, (to.as_vec() - from.as_vec()).norm()))
+                };
+                let length: f32 = segments().map(|(_, _, length)| length).sum();
+
+                if length == 0.0 {
+                    return waypoints
+                        .first()
+                        .cloned()
+                        .unwrap_or(Position { x: 0.0, y: 0.0 });
+                }
+
+                let mut distance = (time * speed).rem_euclid(length);
+                for (from, to, segment_length) in segments() {
+                    // waypoints at the same position have nothing in between
+                    if segment_length > 0.0 && distance <= segment_length {
+                        return lerp(from, to, distance / segment_length);
+                    }
+
+                    distance -= segment_length;
+                }
+
+                waypoints[0]
+            }
+            PlatformMotion::Oscillate { from, to, period } => {
+                if !(period.is_finite() && period > 0.0) {
+                    return from;
+                }
+
+                let t = (1.0 - (time / period * 2.0 * std::f32::consts::PI).cos()) / 2.0;
+
+                lerp(from, to, t)
+            }
+        }
+    }
+}
+
+fn lerp(from: Position, to: Position, t: f32) -> Position {
+    Position {
+        x: from.x + (to.x - from.x) * t,
+        y: from.y + (to.y - from.y) * t,
+    }
+}
+
+/// A platform that is moved along its `motion` instead of by velocity. It pushes entities out of
+/// its way and carries the ones standing on it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MovingPlatform {
+    pub motion: PlatformMotion,
+    // seconds since it started moving
+    pub time: f32,
+}
+
+impl MovingPlatform {
+    pub fn new(motion: PlatformMotion) -> MovingPlatform {
+        MovingPlatform { motion, time: 0.0 }
+    }
+
+    pub fn position(&self) -> Position {
+        self.motion.position_at(self.time)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CollisionType {
     Solid,
     Trigger,
+    // only blocks entities landing on it from above
+    OneWayPlatform,
+}
+
+impl CollisionType {
+    /// Whether solid entities are stopped by this type, at least from some direction.
+    pub fn is_blocking(self) -> bool {
+        match self {
+            CollisionType::Solid | CollisionType::OneWayPlatform => true,
+            CollisionType::Trigger => false,
+        }
+    }
+}
+
+/// The surface of a sloped tile, which walking entities are kept on instead of the top of the
+/// tile's box. Rising slopes go up to the right. The 22.5° ones rise half a tile per tile, so
+/// they are made of a low and a high tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slope {
+    Rising45,
+    Falling45,
+    Rising22Low,
+    Rising22High,
+    Falling22High,
+    Falling22Low,
+}
+
+impl Slope {
+    /// Height of the surface at `t`, both as fractions of the tile from its left and bottom.
+    pub fn height_at(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+
+        match self {
+            Slope::Rising45 => t,
+            Slope::Falling45 => 1.0 - t,
+            Slope::Rising22Low => t / 2.0,
+            Slope::Rising22High => 0.5 + t / 2.0,
+            Slope::Falling22High => 1.0 - t / 2.0,
+            Slope::Falling22Low => 0.5 - t / 2.0,
+        }
+    }
+}
+
+/// A set of collision layers, one per bit. Entities are on the layers of their `CollisionShape`,
+/// and only collide with entities on the layers of its mask, if those collide with them too.
+/// Queries of the `CollisionWorld` only find entities on the layers they ask for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CollisionLayers(pub u32);
+
+impl CollisionLayers {
+    pub const NONE: CollisionLayers = CollisionLayers(0);
+    pub const ALL: CollisionLayers = CollisionLayers(!0);
+
+    /// The room's tiles and platforms, the default.
+    pub const WORLD: CollisionLayers = CollisionLayers(1);
+    pub const PLAYERS: CollisionLayers = CollisionLayers(1 << 1);
+
+    /// The layer called `name`, as used in rooms.
+    pub fn by_name(name: &str) -> Option<CollisionLayers> {
+        let layers = match name {
+            "none" => CollisionLayers::NONE,
+            "all" => CollisionLayers::ALL,
+            "world" => CollisionLayers::WORLD,
+            "players" => CollisionLayers::PLAYERS,
+            _ => return None,
+        };
+
+        Some(layers)
+    }
+
+    pub fn intersects(self, other: CollisionLayers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn without(self, other: CollisionLayers) -> CollisionLayers {
+        CollisionLayers(self.0 & !other.0)
+    }
+}
+
+impl BitOr for CollisionLayers {
+    type Output = CollisionLayers;
+
+    fn bitor(self, other: CollisionLayers) -> CollisionLayers {
+        CollisionLayers(self.0 | other.0)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CollisionShape {
     coll_type: CollisionType,
+    layers: CollisionLayers,
+    mask: CollisionLayers,
+    mass: Option<f32>,
     #[serde(with = "crate::net::serde_impls::cuboid")]
     r_x: Cuboid<f32>,
     off_x: Vector2<f32>,
     #[serde(with = "crate::net::serde_impls::cuboid")]
     r_y: Cuboid<f32>,
     off_y: Vector2<f32>,
+    slope: Option<Slope>,
     pub ongoing_collisions: OngoingCollisions,
 }
 
@@ -141,18 +322,62 @@ impl CollisionShape {
     ) -> CollisionShape {
         CollisionShape {
             coll_type: collision_type,
+            layers: CollisionLayers::WORLD,
+            mask: CollisionLayers::ALL,
+            mass: None,
             r_x: rect_x,
             off_x,
             r_y: rect_y,
             off_y,
+            slope: None,
             ongoing_collisions: Default::default(),
         }
     }
 
+    /// Turns the y box into a slope, it should be a single tile.
+    pub fn with_slope(mut self, slope: Slope) -> CollisionShape {
+        self.slope = Some(slope);
+        self
+    }
+
     pub fn collision_type(&self) -> CollisionType {
         self.coll_type
     }
 
+    pub fn slope(&self) -> Option<Slope> {
+        self.slope
+    }
+
+    pub fn with_layers(mut self, layers: CollisionLayers) -> CollisionShape {
+        self.layers = layers;
+        self
+    }
+
+    pub fn layers(&self) -> CollisionLayers {
+        self.layers
+    }
+
+    /// Sets the layers this collides with.
+    pub fn with_mask(mut self, mask: CollisionLayers) -> CollisionShape {
+        self.mask = mask;
+        self
+    }
+
+    pub fn mask(&self) -> CollisionLayers {
+        self.mask
+    }
+
+    /// Makes this a dynamic body, which pushes and is pushed by other dynamic bodies, the less
+    /// the heavier it is. Without a mass, it does not give way to them.
+    pub fn with_mass(mut self, mass: f32) -> CollisionShape {
+        self.mass = Some(mass);
+        self
+    }
+
+    pub fn mass(&self) -> Option<f32> {
+        self.mass
+    }
+
     // pub fn rect_x(&self) -> &Cuboid<f32> {
     //     &self.r_x
     // }
@@ -229,6 +454,10 @@ pub struct InteractionPossibility {
     pub interaction: game::Interaction,
 }
 
+/// Display name of a player, rendered above its sprite.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerName(pub String);
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SpriteLayer {
     Background,
@@ -265,6 +494,56 @@ pub struct SpriteSheetAnimation {
     pub animation: Animation,
     pub current_frame: u8,
     pub frame_time_remaining: f32,
+    pub start_tick: u64,
+}
+
+impl SpriteSheetAnimation {
+    pub fn to_replicated(&self, resource_store: &ResourceStore) -> ReplicatedAnimation {
+        ReplicatedAnimation {
+            sheet_path: resource_store
+                .get_sprite_sheet_path(self.sheet_handle)
+                .to_owned(),
+            animation_name: self.animation.name.as_ref().clone(),
+            start_tick: self.start_tick,
+        }
+    }
+}
+
+/// A `SpriteSheetAnimation` as it is sent over the network. Clients look up the
+/// sprite sheet in their own `ResourceStore` and advance the frames locally.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplicatedAnimation {
+    pub sheet_path: PathBuf,
+    pub animation_name: String,
+    pub start_tick: u64,
+}
+
+impl ReplicatedAnimation {
+    /// Creates the animation state for `simulation_time`, as if it had been running since `start_tick`.
+    /// Nothing is created for sprite sheets which are not in the assets.
+    pub fn resolve(
+        &self,
+        resource_store: &mut ResourceStore,
+        simulation_time: u64,
+        delta_time_s: f32,
+    ) -> Option<SpriteSheetAnimation> {
+        let sheet_handle = resource_store.find_sprite_sheet(&self.sheet_path)?;
+        let animation = resource_store
+            .get_sprite_sheet(sheet_handle)
+            .get(&self.animation_name)?
+            .clone();
+
+        let elapsed_s = simulation_time.saturating_sub(self.start_tick) as f32 * delta_time_s;
+        let (current_frame, frame_time_remaining) = animation.frame_at(elapsed_s);
+
+        Some(SpriteSheetAnimation {
+            sheet_handle,
+            animation,
+            current_frame,
+            frame_time_remaining,
+            start_tick: self.start_tick,
+        })
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -327,6 +606,8 @@ components! {
         #[cold] intents: Intents,
         #[cold] interactor: Interactor,
         #[cold] interaction_possibility: InteractionPossibility,
+        #[cold] player_name: PlayerName,
+        #[cold] moving_platform: MovingPlatform,
     }
 }
 
@@ -347,6 +628,8 @@ pub struct LevelChangedFlags {
     pub intents: HashMap<Entity, Intents>,
     pub interactor: HashMap<Entity, Interactor>,
     pub interaction_possibility: HashMap<Entity, InteractionPossibility>,
+    pub player_name: HashMap<Entity, PlayerName>,
+    pub moving_platform: HashMap<Entity, MovingPlatform>,
 }
 
 impl LevelChangedFlags {
@@ -365,5 +648,7 @@ impl LevelChangedFlags {
         self.intents.clear();
         self.interactor.clear();
         self.interaction_possibility.clear();
+        self.player_name.clear();
+        self.moving_platform.clear();
     }
 }
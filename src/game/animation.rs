@@ -25,6 +25,28 @@ impl Animation {
             texture_info: new_tex_info,
         }
     }
+
+    /// Returns the frame which is shown `elapsed_s` seconds after the animation started,
+    /// together with the time remaining until the next frame.
+    pub fn frame_at(&self, elapsed_s: f32) -> (u8, f32) {
+        let total_duration: f32 = self.frame_durations.iter().sum();
+
+        let mut time_in_loop = if total_duration > 0.0 {
+            elapsed_s % total_duration
+        } else {
+            0.0
+        };
+
+        for (idx, duration) in self.frame_durations.iter().enumerate() {
+            if time_in_loop < *duration {
+                return (idx as u8, duration - time_in_loop);
+            }
+
+            time_in_loop -= duration;
+        }
+
+        (0, self.frame_durations[0])
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -8,6 +8,11 @@ pub trait EventReceiver<T> {
     fn receive_event(&mut self, event: T);
 }
 
+/// Receives events which happened on the server and were replicated to this client.
+pub trait RemoteEventReceiver<T> {
+    fn receive_remote_event(&mut self, event: T);
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct CollisionStarted {
     pub collider: Entity,
@@ -27,20 +32,132 @@ pub struct InteractionDone {
     pub interaction: game::Interaction,
 }
 
+/// Events that are sent to the clients they are relevant to over the reliable event channel, see
+/// `net::Server`.
+#[derive(Copy, Clone, Debug)]
+pub enum ReplicatedEvent {
+    CollisionStarted(CollisionStarted),
+    CollisionEnded(CollisionEnded),
+    InteractionDone(InteractionDone),
+}
+
+/// Which clients a replicated event is sent to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Relevance {
+    /// Every client, like interactions other players can see.
+    Everyone,
+    /// Only the clients controlling one of these entities.
+    Involving(Entity, Entity),
+}
+
+impl ReplicatedEvent {
+    pub fn relevance(&self) -> Relevance {
+        match *self {
+            ReplicatedEvent::CollisionStarted(event) => {
+                Relevance::Involving(event.collider, event.collided)
+            }
+            ReplicatedEvent::CollisionEnded(event) => {
+                Relevance::Involving(event.collider, event.collided)
+            }
+            ReplicatedEvent::InteractionDone(_) => Relevance::Everyone,
+        }
+    }
+
+    /// Whether the event is sent to a client controlling `player`.
+    pub fn relevant_to(&self, player: Option<Entity>) -> bool {
+        match self.relevance() {
+            Relevance::Everyone => true,
+            Relevance::Involving(a, b) => player == Some(a) || player == Some(b),
+        }
+    }
+}
+
+impl From<CollisionStarted> for ReplicatedEvent {
+    fn from(event: CollisionStarted) -> ReplicatedEvent {
+        ReplicatedEvent::CollisionStarted(event)
+    }
+}
+
+impl From<CollisionEnded> for ReplicatedEvent {
+    fn from(event: CollisionEnded) -> ReplicatedEvent {
+        ReplicatedEvent::CollisionEnded(event)
+    }
+}
+
+impl From<InteractionDone> for ReplicatedEvent {
+    fn from(event: InteractionDone) -> ReplicatedEvent {
+        ReplicatedEvent::InteractionDone(event)
+    }
+}
+
+// collisions only have an effect on clients when the collider can interact with what it
+// collided with, see `interaction_system`. the others are not replicated.
+fn may_interact(
+    data: &mut DataHelper<LevelComponents, LevelServices>,
+    collider: Entity,
+    collided: Entity,
+) -> bool {
+    let is_interactor = data
+        .with_entity_data(&collider, |en, comps| comps.interactor.has(&en))
+        .unwrap_or(false);
+    let is_interactable = data
+        .with_entity_data(&collided, |en, comps| {
+            comps.interaction_possibility.has(&en)
+        })
+        .unwrap_or(false);
+
+    is_interactor && is_interactable
+}
+
+fn replicate<T: Into<ReplicatedEvent>>(
+    data: &mut DataHelper<LevelComponents, LevelServices>,
+    event: T,
+) {
+    let sim_time = data.services.simulation_time;
+    data.services
+        .replicated_events
+        .push((sim_time, event.into()));
+}
+
 impl EventReceiver<CollisionStarted> for DataHelper<LevelComponents, LevelServices> {
     fn receive_event(&mut self, event: CollisionStarted) {
         interaction_system::on_collision_started(self, &event);
+
+        if may_interact(self, event.collider, event.collided) {
+            replicate(self, event);
+        }
     }
 }
 
 impl EventReceiver<CollisionEnded> for DataHelper<LevelComponents, LevelServices> {
     fn receive_event(&mut self, event: CollisionEnded) {
         interaction_system::on_collision_ended(self, &event);
+
+        if may_interact(self, event.collider, event.collided) {
+            replicate(self, event);
+        }
     }
 }
 
 impl EventReceiver<InteractionDone> for DataHelper<LevelComponents, LevelServices> {
     fn receive_event(&mut self, _event: InteractionDone) {
         dbg!(_event);
+        replicate(self, _event);
     }
 }
+
+impl RemoteEventReceiver<CollisionStarted> for DataHelper<LevelComponents, LevelServices> {
+    fn receive_remote_event(&mut self, event: CollisionStarted) {
+        interaction_system::on_collision_started(self, &event);
+    }
+}
+
+impl RemoteEventReceiver<CollisionEnded> for DataHelper<LevelComponents, LevelServices> {
+    fn receive_remote_event(&mut self, event: CollisionEnded) {
+        interaction_system::on_collision_ended(self, &event);
+    }
+}
+
+impl RemoteEventReceiver<InteractionDone> for DataHelper<LevelComponents, LevelServices> {
+    fn receive_remote_event(&mut self, _event: InteractionDone) {}
+}
@@ -0,0 +1,347 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use ecs::{Entity, World};
+
+use crate::components::Intents;
+use crate::net::replication::{self, ComponentUpdates, EntityMapping};
+use crate::net::DecodeError;
+use crate::systems::LevelSystems;
+
+/// If set, the server records its inputs to the file at this path, see `InputRecording`.
+pub const RECORDING_ENV_VAR: &str = "CRUFTY_RECORD_INPUTS";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedTick {
+    // keyed by entity ids of the recorded world, entities without intents are left out
+    intents: Vec<(u64, Intents)>,
+    state_hash: u64,
+}
+
+/// A recorded run: the initial world, and the intents of every entity for each tick.
+/// Since the systems only depend on `Intents`, `delta_time_s` and the world itself,
+/// replaying the intents has to reproduce the run exactly.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputRecording {
+    // see `ComponentUpdates::new_from_world_unfiltered()`
+    initial_world: Vec<u8>,
+    start_tick: u64,
+    delta_time_s: f32,
+    ticks: Vec<RecordedTick>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Decode(DecodeError),
+    Diverged {
+        tick: u64,
+        expected_hash: u64,
+        actual_hash: u64,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ReplayError::Decode(ref err) => write!(f, "could not decode initial world: {}", err),
+            ReplayError::Diverged {
+                tick,
+                expected_hash,
+                actual_hash,
+            } => write!(
+                f,
+                "state diverged at tick {} (expected hash {:x}, got {:x})",
+                tick, expected_hash, actual_hash
+            ),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<DecodeError> for ReplayError {
+    fn from(err: DecodeError) -> ReplayError {
+        ReplayError::Decode(err)
+    }
+}
+
+/// Hashes `Position`, `Velocity` and `Jump` of all entities `id_of` returns an id for.
+fn state_hash(world: &World<LevelSystems>, id_of: impl Fn(Entity) -> Option<u64>) -> u64 {
+    let mut states = Vec::new();
+
+    for en in world.entities() {
+        let id = match id_of(**en) {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let state = (
+            world.position.get(&en),
+            world.velocity.get(&en),
+            world.jump.get(&en),
+        );
+
+        states.push((id, bincode::serialize(&state).unwrap()));
+    }
+
+    states.sort_by_key(|&(id, _)| id);
+
+    let mut hasher = DefaultHasher::new();
+    for (id, state) in states {
+        hasher.write_u64(id);
+        hasher.write(&state);
+    }
+
+    hasher.finish()
+}
+
+impl InputRecording {
+    /// Starts recording, with `world` in its current state as the initial world.
+    pub fn start(world: &mut World<LevelSystems>) -> InputRecording {
+        let initial_world = ComponentUpdates::new_from_world_unfiltered(world)
+            .serialize_updates()
+            .unwrap_or_default();
+
+        InputRecording {
+            initial_world,
+            start_tick: world.services.simulation_time,
+            delta_time_s: world.services.delta_time_s,
+            ticks: Vec::new(),
+        }
+    }
+
+    /// Records the tick which was just simulated, has to be called after every `world.update()`.
+    pub fn record_tick(&mut self, world: &World<LevelSystems>) {
+        let mut intents = Vec::new();
+
+        for en in world.entities() {
+            if let Some(en_intents) = world.intents.get(&en) {
+                if !en_intents.is_empty() {
+                    intents.push((en.id(), en_intents));
+                }
+            }
+        }
+
+        let state_hash = state_hash(world, |e| Some(e.id()));
+
+        self.ticks.push(RecordedTick { intents, state_hash });
+    }
+
+    pub fn num_ticks(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        bincode::serialize_into(BufWriter::new(File::create(path)?), self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    pub fn load(path: &Path) -> io::Result<InputRecording> {
+        bincode::deserialize_from(BufReader::new(File::open(path)?))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Replays the recording headlessly, failing at the first tick whose state differs from
+    /// the recorded one. Entities created during the recorded run are not supported.
+    pub fn replay(&self) -> Result<World<LevelSystems>, ReplayError> {
+        let mut world = World::<LevelSystems>::new();
+        world.services.delta_time_s = self.delta_time_s;
+
+        let mut mapping = EntityMapping::default();
+        if !self.initial_world.is_empty() {
+            let updates = replication::decode_updates(&self.initial_world)?;
+            replication::apply_updates(updates, &mut mapping, &mut world);
+        }
+
+        world.services.simulation_time = self.start_tick;
+
+        let ids = mapping.server_ids();
+
+        for tick in &self.ticks {
+            let mut tick_intents: HashMap<u64, &Intents> =
+                tick.intents.iter().map(|(e_id, intents)| (*e_id, intents)).collect();
+
+            for (e_id, e) in mapping.iter() {
+                let intents = tick_intents.remove(&e_id).cloned().unwrap_or_default();
+
+                world.with_entity_data(&e, |en, comps| {
+                    if let Some(en_intents) = comps.intents.borrow(&en) {
+                        *en_intents = intents;
+                    }
+                });
+            }
+
+            world.update();
+
+            let actual_hash = state_hash(&world, |e| ids.get(&e).cloned());
+            if actual_hash != tick.state_hash {
+                return Err(ReplayError::Diverged {
+                    tick: world.services.simulation_time,
+                    expected_hash: tick.state_hash,
+                    actual_hash,
+                });
+            }
+
+            world.services.simulation_time += 1;
+            world.services.changed_flags.clear();
+            world.services.replicated_events.clear();
+        }
+
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::{BuildData, Entity, World};
+
+    use super::*;
+    use crate::application::InputIntent;
+    use crate::components::*;
+    use crate::na::Vector2;
+    use crate::nc::shape::Cuboid;
+
+    fn create_world() -> (World<LevelSystems>, Entity) {
+        let mut world = World::<LevelSystems>::new();
+        world.services.delta_time_s = 0.01;
+        world.services.simulation_time = 100;
+
+        let player = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                let position = Position { x: 32.0, y: 64.0 };
+                data.position.add(&entity, position);
+                data.velocity.add(
+                    &entity,
+                    Velocity {
+                        vx: 0.0,
+                        vy: 0.0,
+                        last_pos: position,
+                    },
+                );
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_dual(
+                        Cuboid::new(Vector2::new(16.0, 5.0)),
+                        Vector2::new(16.0, 16.0),
+                        Cuboid::new(Vector2::new(5.0, 16.0)),
+                        Vector2::new(16.0, 16.0),
+                        CollisionType::Solid,
+                    ),
+                );
+                data.movement.add(
+                    &entity,
+                    Movement::new(Vector2::new(110.0, 0.0), Vector2::new(1000.0, 0.0)),
+                );
+                data.facing.add(&entity, Facing::Left);
+                data.jump.add(&entity, Jump::new());
+                data.gravity.add(&entity, Gravity::new());
+                data.intents.add(&entity, Intents::new());
+            },
+        );
+
+        let _floor = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, Position { x: 0.0, y: 0.0 });
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_single(
+                        Cuboid::new(Vector2::new(192.0, 16.0)),
+                        Vector2::new(192.0, 16.0),
+                        CollisionType::Solid,
+                    ),
+                );
+            },
+        );
+
+        (world, player)
+    }
+
+    fn scripted_intents(tick: u64) -> Intents {
+        let mut intents = Intents::new();
+
+        match tick {
+            0..=49 => {
+                intents.insert(InputIntent::MoveRight);
+            }
+            60..=70 => {
+                intents.insert(InputIntent::Jump);
+                intents.insert(InputIntent::MoveLeft);
+            }
+            120..=180 => {
+                intents.insert(InputIntent::MoveLeft);
+            }
+            _ => (),
+        }
+
+        intents
+    }
+
+    fn record_run(num_ticks: u64) -> (InputRecording, Position) {
+        let (mut world, player) = create_world();
+        let mut recording = InputRecording::start(&mut world);
+
+        for tick in 0..num_ticks {
+            let intents = scripted_intents(tick);
+            world.with_entity_data(&player, |en, comps| {
+                *comps.intents.borrow(&en).unwrap() = intents;
+            });
+
+            world.update();
+            recording.record_tick(&world);
+
+            world.services.simulation_time += 1;
+            world.services.changed_flags.clear();
+            world.services.replicated_events.clear();
+        }
+
+        let position = world
+            .with_entity_data(&player, |en, comps| comps.position.get(&en))
+            .unwrap()
+            .unwrap();
+
+        (recording, position)
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_run() {
+        let (recording, recorded_position) = record_run(250);
+        assert_eq!(recording.num_ticks(), 250);
+
+        let recorded_data = bincode::serialize(&recording).unwrap();
+        let recording: InputRecording = bincode::deserialize(&recorded_data).unwrap();
+
+        let replayed_world = recording.replay().unwrap();
+
+        let replayed_positions: Vec<Position> = replayed_world
+            .entities()
+            .filter(|en| replayed_world.intents.has(en))
+            .map(|en| replayed_world.position.get(&en).unwrap())
+            .collect();
+
+        assert_eq!(replayed_positions, vec![recorded_position]);
+
+        // make sure the run actually moved the player
+        assert_ne!(recorded_position, Position { x: 32.0, y: 64.0 });
+    }
+
+    #[test]
+    fn replay_reports_first_divergent_tick() {
+        let (mut recording, _) = record_run(250);
+
+        let (player_id, _) = recording.ticks[0].intents[0].clone();
+        let mut intents = Intents::new();
+        intents.insert(InputIntent::MoveRight);
+        recording.ticks[90].intents = vec![(player_id, intents)];
+
+        match recording.replay() {
+            Err(ReplayError::Diverged { tick, .. }) => assert_eq!(tick, 100 + 90),
+            res => panic!("replay did not diverge: {:?}", res.map(|_| ())),
+        }
+    }
+}
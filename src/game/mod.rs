@@ -2,6 +2,8 @@ pub use self::animation::{Animation, SpriteSheet};
 use self::events::EventReceiver;
 pub use self::resource_store::*;
 
+use std::fmt;
+
 use ecs::{DataHelper, Entity, EntityData};
 
 use crate::components::{LevelComponents, Position};
@@ -13,8 +15,11 @@ mod animation;
 mod resource_store;
 
 pub mod events;
+pub mod input_replay;
+pub mod prefabs;
+pub mod room;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct PlayerId(u16);
 
 impl From<u16> for PlayerId {
@@ -23,6 +28,12 @@ impl From<u16> for PlayerId {
     }
 }
 
+impl fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Interaction {
     WarpInRoom { x: f32, y: f32 },
@@ -84,6 +95,8 @@ impl EntityOps for DataHelper<LevelComponents, LevelServices> {
         // especially having to clone `anim` twice is annoying. but it's infrequent code,
         // so we don't care for now.
 
+        let sim_time = self.services.simulation_time;
+
         let ss_handle = eod.with_entity_data(self, |en, comps| {
             comps.sprite_sheet_animation.borrow(&en).and_then(|ssa| {
                 // if the animation is already running, don't restart it
@@ -119,6 +132,7 @@ impl EntityOps for DataHelper<LevelComponents, LevelServices> {
             ssa.current_frame = 0;
             ssa.frame_time_remaining = anim.frame_durations[0];
             ssa.animation = anim.clone();
+            ssa.start_tick = sim_time;
 
             Some(ssa.clone())
         }) {
@@ -152,7 +166,8 @@ impl EntityOps for DataHelper<LevelComponents, LevelServices> {
             new_pos,
             if warp { None } else { last_pos.as_ref() },
             &mut colls,
-        );
+        )
+        .position;
 
         let mut pos_changed = true;
         if let Some(last_pos) = last_pos {
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use std::collections::HashMap;
+
+use ecs::{BuildData, Entity, World};
+use glium::glutin::VirtualKeyCode;
+
+use crate::application::{InputContext, InputContextKey, InputIntent, InputState};
+
+use crate::components::{
+    CollisionLayers, CollisionShape, CollisionType, Facing, Gravity, Intents, Interactor, Jump,
+    KeyboardInput, LevelComponents, Movement, PlayerName, Position, Sprite, SpriteInfo,
+    SpriteLayer, SpriteSheetAnimation, Velocity,
+};
+use crate::na::Vector2;
+use crate::nc::shape::Cuboid;
+use crate::resources::TextureSlug;
+use crate::systems::LevelSystems;
+
+pub const PLAYER_SPAWN: Position = Position { x: 8.0 * 32.0, y: 0.0 };
+
+/// Key bindings of players, for local players as well as for the intents clients send.
+pub fn player_input_context() -> InputContext {
+    let mut inputs = HashMap::new();
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::O, InputState::PressedThisFrame),
+        InputIntent::PrintDebugMessage,
+    );
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::Left, InputState::Pressed),
+        InputIntent::MoveLeft,
+    );
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::Right, InputState::Pressed),
+        InputIntent::MoveRight,
+    );
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::Down, InputState::Pressed),
+        InputIntent::MoveDown,
+    );
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::Space, InputState::Pressed),
+        InputIntent::Jump,
+    );
+    inputs.insert(
+        InputContextKey(VirtualKeyCode::E, InputState::PressedThisFrame),
+        InputIntent::Interact,
+    );
+    inputs
+}
+
+/// Creates a player entity and marks all its components as changed, so it is replicated.
+/// Players controlled by a local keyboard also need `keyboard_input`.
+pub fn create_player(
+    world: &mut World<LevelSystems>,
+    position: Position,
+    name: PlayerName,
+    keyboard_input: Option<KeyboardInput>,
+) -> Entity {
+    let ss_handle = world
+        .services
+        .resource_store
+        .load_sprite_sheet(Path::new("assets/textures/sprites/player/animations.toml"));
+
+    let player_stand_animation = world
+        .services
+        .resource_store
+        .get_sprite_sheet(ss_handle)
+        .get("stand")
+        .unwrap()
+        .clone();
+
+    let velocity = Velocity {
+        vx: 0.0,
+        vy: 0.0,
+        last_pos: position,
+    };
+    let collision_shape = CollisionShape::new_dual(
+        Cuboid::new(Vector2::new(16.0, 5.0)),
+        Vector2::new(16.0, 16.0),
+        Cuboid::new(Vector2::new(5.0, 16.0)),
+        Vector2::new(16.0, 16.0),
+        CollisionType::Solid,
+    )
+    .with_layers(CollisionLayers::PLAYERS)
+    // players walk through each other
+    .with_mask(CollisionLayers::ALL.without(CollisionLayers::PLAYERS))
+    .with_mass(1.0);
+    let movement = Movement::new(Vector2::new(110.0, 0.0), Vector2::new(1000.0, 0.0));
+    let facing = Facing::Right;
+    let jump = Jump::new();
+    let gravity = Gravity::new();
+    let sprite = Sprite {
+        info: SpriteInfo {
+            width: 32.0,
+            height: 32.0,
+            texture_info: TextureSlug::sprites__player__stand__p_stand.texture_info(),
+        },
+        sprite_layer: SpriteLayer::Foreground,
+    };
+    let ss_anim = SpriteSheetAnimation {
+        sheet_handle: ss_handle,
+        animation: player_stand_animation,
+        current_frame: 0,
+        frame_time_remaining: 0.1,
+        start_tick: world.services.simulation_time,
+    };
+    let intents = Intents::new();
+    let interactor = Interactor;
+
+    let player = world.create_entity(
+        |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+            data.position.add(&entity, position);
+            data.velocity.add(&entity, velocity);
+            data.collision_shape.add(&entity, collision_shape.clone());
+            data.movement.add(&entity, movement.clone());
+            data.facing.add(&entity, facing);
+            data.jump.add(&entity, jump);
+            data.gravity.add(&entity, gravity);
+            data.sprite.add(&entity, sprite.clone());
+            data.sprite_sheet_animation.add(&entity, ss_anim.clone());
+            data.intents.add(&entity, intents.clone());
+            data.interactor.add(&entity, interactor);
+            data.player_name.add(&entity, name.clone());
+
+            if let Some(ref keyboard_input) = keyboard_input {
+                data.keyboard_input.add(&entity, keyboard_input.clone());
+            }
+        },
+    );
+
+    let changed_flags = &mut world.services.changed_flags;
+    changed_flags.position.insert(player, position);
+    changed_flags.velocity.insert(player, velocity);
+    changed_flags.collision_shape.insert(player, collision_shape);
+    changed_flags.movement.insert(player, movement);
+    changed_flags.facing.insert(player, facing);
+    changed_flags.jump.insert(player, jump);
+    changed_flags.gravity.insert(player, gravity);
+    changed_flags.sprite.insert(player, sprite);
+    changed_flags.sprite_sheet_animation.insert(player, ss_anim);
+    changed_flags.intents.insert(player, intents);
+    changed_flags.interactor.insert(player, interactor);
+    changed_flags.player_name.insert(player, name);
+
+    if let Some(keyboard_input) = keyboard_input {
+        changed_flags.keyboard_input.insert(player, keyboard_input);
+    }
+
+    player
+}
@@ -41,7 +41,16 @@ impl ResourceStore {
         self.sprite_sheet_store.get_sprite_sheet_handle(path)
     }
 
+    /// The sprite sheet at `path`, if it is loaded or one of the sprite sheets in the assets.
+    pub fn find_sprite_sheet(&mut self, path: &Path) -> Option<SpriteSheetHandle> {
+        self.sprite_sheet_store.find_sprite_sheet_handle(path)
+    }
+
     pub fn get_sprite_sheet(&self, handle: SpriteSheetHandle) -> &SpriteSheet {
         self.sprite_sheet_store.get_sprite_sheet(handle)
     }
+
+    pub fn get_sprite_sheet_path(&self, handle: SpriteSheetHandle) -> &Path {
+        self.sprite_sheet_store.get_sprite_sheet_path(handle)
+    }
 }
@@ -5,12 +5,16 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+// the sprite sheets in the assets, which peers may refer to
+const KNOWN_SPRITE_SHEETS: &[&str] = &["assets/textures/sprites/player/animations.toml"];
+
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub struct SpriteSheetHandle(usize);
 
 #[derive(Default)]
 pub struct SpriteSheetStore {
     sprite_sheets: Vec<SpriteSheet>,
+    paths: Vec<PathBuf>,
     handles: HashMap<PathBuf, SpriteSheetHandle>,
 }
 
@@ -30,14 +34,33 @@ impl SpriteSheetStore {
         *self.handles.get(path).unwrap()
     }
 
+    /// Like `get_sprite_sheet_handle`, but only loads the sprite sheets in the assets. Other paths
+    /// are not opened, so this is safe to call with paths received from peers.
+    pub fn find_sprite_sheet_handle(&mut self, path: &Path) -> Option<SpriteSheetHandle> {
+        if let Some(handle) = self.handles.get(path) {
+            return Some(*handle);
+        }
+
+        if KNOWN_SPRITE_SHEETS.iter().any(|known| Path::new(known) == path) {
+            Some(self.load_sheet(path))
+        } else {
+            None
+        }
+    }
+
     pub fn get_sprite_sheet(&self, handle: SpriteSheetHandle) -> &SpriteSheet {
         self.sprite_sheets.get(handle.0).unwrap()
     }
 
+    pub fn get_sprite_sheet_path(&self, handle: SpriteSheetHandle) -> &Path {
+        self.paths.get(handle.0).unwrap()
+    }
+
     fn load_sheet(&mut self, path: &Path) -> SpriteSheetHandle {
         let sprite_sheet = load_sprite_sheet(path);
 
         self.sprite_sheets.push(sprite_sheet);
+        self.paths.push(path.to_owned());
         let ss_id = self.sprite_sheets.len() - 1;
 
         let previous = self
@@ -119,6 +142,7 @@ fn load_sprite_sheet(path: &Path) -> SpriteSheet {
                 .collect::<Vec<_>>(),
             _ => panic!("durations neither float nor array"),
         };
+        assert!(!vec_durations.is_empty(), "animation {} has no frame durations", name);
 
         // sprite path is relative to current folder of the spritesheet file
         let sprite_path = path.parent().unwrap().join(sprite);
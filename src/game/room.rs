@@ -0,0 +1,1287 @@
+//! Rooms made with the Tiled map editor. Only orthogonal maps with CSV encoded layers and
+//! tilesets embedded into the map are supported.
+//!
+//! The collision of a tile is set with custom properties of the tile in its tileset:
+//! - `solid` (bool): a solid box filling the tile. Adjacent solid tiles on the same layers are
+//!   merged into larger boxes, see `Room::solid_blocks`
+//! - `platform` (bool): a one-way platform along the top of the tile
+//! - `slope` (string): a solid slope, one of `rising-45`, `falling-45`, `rising-22-low`,
+//!   `rising-22-high`, `falling-22-high` or `falling-22-low`, see `Slope`
+//! - `layers` and `mask` (string): comma separated names of the collision layers the tile is on
+//!   and collides with, see `CollisionLayers::by_name`. The world layer and all layers by default.
+//!
+//! Moving platforms are polylines of type `moving-platform` in object layers, through the
+//! positions of the platform's top left corner. Their custom properties are:
+//! - `width` and `height` (float): the size of the platform, one tile by default
+//! - `speed` (float): moves through the points at this speed, and back to the first one
+//! - `period` (float): instead moves back and forth between the first and the last point, in
+//!   this many seconds
+//! - `layers` and `mask` (string): like for tiles
+//!
+//! Crates, which fall and can be pushed around and stood on, are objects of type `crate`, at the
+//! crate's top left corner. Their custom properties are:
+//! - `width` and `height` (float): the size of the crate, one tile by default
+//! - `mass` (float): how hard the crate is to push, a player weighs 1. 2 by default.
+//!
+//! The float properties of platforms and crates have to be positive.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use ecs::{BuildData, Entity, World};
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::{self, EventReader, XmlEvent};
+
+use crate::components::{
+    CollisionLayers, CollisionShape, CollisionType, Gravity, LevelComponents, MovingPlatform,
+    PlatformMotion, Position, Slope, Sprite, SpriteInfo, SpriteLayer, Velocity,
+};
+use crate::na::Vector2;
+use crate::nc::shape::Cuboid;
+use crate::resources::TextureSlug;
+use crate::systems::LevelSystems;
+
+// thickness of one-way platforms, at the top of their tile
+pub const PLATFORM_HEIGHT: f32 = 8.0;
+
+// the upper bits of gids in layers store whether the tile is flipped
+const GID_FLIP_FLAGS: u32 = 0xE000_0000;
+
+#[derive(Debug)]
+pub enum RoomError {
+    Io(io::Error),
+    Xml(reader::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for RoomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RoomError::Io(ref err) => write!(f, "could not read room: {}", err),
+            RoomError::Xml(ref err) => write!(f, "malformed room: {}", err),
+            RoomError::Invalid(ref reason) => write!(f, "invalid room: {}", reason),
+        }
+    }
+}
+
+impl Error for RoomError {}
+
+impl From<io::Error> for RoomError {
+    fn from(err: io::Error) -> RoomError {
+        RoomError::Io(err)
+    }
+}
+
+impl From<reader::Error> for RoomError {
+    fn from(err: reader::Error) -> RoomError {
+        RoomError::Xml(err)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileCollision {
+    Solid,
+    Platform,
+    Slope(Slope),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TileInfo {
+    collision: Option<TileCollision>,
+    layers: Option<CollisionLayers>,
+    mask: Option<CollisionLayers>,
+    image: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct Tileset {
+    first_gid: u32,
+    // by id of the tile in the tileset, tiles without properties or images are left out
+    tiles: HashMap<u32, TileInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    // in tiles, from the bottom left of the room
+    pub column: u32,
+    pub row: u32,
+    pub collision: Option<TileCollision>,
+    pub layers: Option<CollisionLayers>,
+    pub mask: Option<CollisionLayers>,
+    pub image: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Platform {
+    pub width: f32,
+    pub height: f32,
+    pub motion: PlatformMotion,
+    pub layers: Option<CollisionLayers>,
+    pub mask: Option<CollisionLayers>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crate {
+    pub position: Position,
+    pub width: f32,
+    pub height: f32,
+    pub mass: f32,
+}
+
+/// A rectangle of solid tiles on the same collision layers, which collide as one box. Keeps
+/// entities from catching on the seams between the tiles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SolidBlock {
+    // in tiles, from the bottom left of the room
+    pub column: u32,
+    pub row: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub layers: Option<CollisionLayers>,
+    pub mask: Option<CollisionLayers>,
+}
+
+// an object of an object layer, while it is being parsed
+#[derive(Debug, Default)]
+struct Object {
+    x: f32,
+    y: f32,
+    kind: Option<String>,
+    properties: HashMap<String, String>,
+    points: Vec<(f32, f32)>,
+}
+
+impl Object {
+    fn float_property(&self, key: &str, default: Option<f32>) -> Result<Option<f32>, RoomError> {
+        match self.properties.get(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                RoomError::Invalid(format!(
+                    "{} has an invalid {}",
+                    self.kind.as_ref().map_or("object", String::as_str),
+                    key
+                ))
+            }),
+            None => Ok(default),
+        }
+    }
+
+    /// Like `float_property`, but only accepts finite values above zero.
+    fn positive_property(&self, key: &str, default: Option<f32>) -> Result<Option<f32>, RoomError> {
+        match self.float_property(key, default)? {
+            Some(value) if !(value > 0.0 && value.is_finite()) => Err(RoomError::Invalid(format!(
+                "{} needs a positive {}",
+                self.kind.as_ref().map_or("object", String::as_str),
+                key
+            ))),
+            value => Ok(value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Room {
+    // in tiles
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub tiles: Vec<Tile>,
+    pub platforms: Vec<Platform>,
+    pub crates: Vec<Crate>,
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.name.local_name == key)
+        .map(|attribute| attribute.value.as_str())
+}
+
+fn parse_attribute<T: FromStr>(
+    attributes: &[OwnedAttribute],
+    element: &str,
+    key: &str,
+) -> Result<T, RoomError> {
+    let value = attribute(attributes, key)
+        .ok_or_else(|| RoomError::Invalid(format!("<{}> has no {}", element, key)))?;
+
+    value
+        .parse()
+        .map_err(|_| RoomError::Invalid(format!("<{}> has an invalid {}: {}", element, key, value)))
+}
+
+fn parse_points(points: &str) -> Result<Vec<(f32, f32)>, RoomError> {
+    points
+        .split_whitespace()
+        .map(|point| {
+            let mut coords = point.split(',').map(str::parse::<f32>);
+
+            match (coords.next(), coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok((x, y)),
+                _ => Err(RoomError::Invalid(format!("invalid point {}", point))),
+            }
+        })
+        .collect()
+}
+
+fn parse_layers(names: &str) -> Result<CollisionLayers, RoomError> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .try_fold(CollisionLayers::NONE, |layers, name| {
+            let layer = CollisionLayers::by_name(name)
+                .ok_or_else(|| RoomError::Invalid(format!("unknown collision layer {}", name)))?;
+
+            Ok(layers | layer)
+        })
+}
+
+/// Moves `shape` to the layers and mask set in the room, if they are.
+fn with_layers(
+    shape: CollisionShape,
+    layers: Option<CollisionLayers>,
+    mask: Option<CollisionLayers>,
+) -> CollisionShape {
+    let shape = match layers {
+        Some(layers) => shape.with_layers(layers),
+        None => shape,
+    };
+
+    match mask {
+        Some(mask) => shape.with_mask(mask),
+        None => shape,
+    }
+}
+
+fn parse_slope(name: &str) -> Option<Slope> {
+    let slope = match name {
+        "rising-45" => Slope::Rising45,
+        "falling-45" => Slope::Falling45,
+        "rising-22-low" => Slope::Rising22Low,
+        "rising-22-high" => Slope::Rising22High,
+        "falling-22-high" => Slope::Falling22High,
+        "falling-22-low" => Slope::Falling22Low,
+        _ => return None,
+    };
+
+    Some(slope)
+}
+
+/// Resolves `..` in `path`, textures are looked up by their path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+
+    normalized
+}
+
+impl Room {
+    pub fn load(path: &Path) -> Result<Room, RoomError> {
+        let file = File::open(path)?;
+
+        Room::parse(
+            BufReader::new(file),
+            path.parent().unwrap_or_else(|| Path::new("")),
+        )
+    }
+
+    /// Parses a room from the contents of a `.tmx` file, with image paths relative to `dir`.
+    pub fn parse<R: Read>(reader: R, dir: &Path) -> Result<Room, RoomError> {
+        let mut room: Option<Room> = None;
+        let mut tilesets: Vec<Tileset> = Vec::new();
+        // id of the tile whose properties are being parsed, in the last tileset
+        let mut current_tile: Option<u32> = None;
+        let mut layer_size: Option<(u32, u32)> = None;
+        let mut data: Option<String> = None;
+        let mut current_object: Option<Object> = None;
+
+        for event in EventReader::new(reader) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => match name.local_name.as_str() {
+                    "map" => {
+                        let orientation = attribute(&attributes, "orientation");
+                        if orientation != Some("orthogonal") {
+                            return Err(RoomError::Invalid(format!(
+                                "unsupported orientation {:?}",
+                                orientation
+                            )));
+                        }
+
+                        room = Some(Room {
+                            width: parse_attribute(&attributes, "map", "width")?,
+                            height: parse_attribute(&attributes, "map", "height")?,
+                            tile_width: parse_attribute(&attributes, "map", "tilewidth")?,
+                            tile_height: parse_attribute(&attributes, "map", "tileheight")?,
+                            tiles: Vec::new(),
+                            platforms: Vec::new(),
+                            crates: Vec::new(),
+                        });
+                    }
+                    "tileset" => {
+                        if attribute(&attributes, "source").is_some() {
+                            return Err(RoomError::Invalid(
+                                "external tilesets are not supported".to_string(),
+                            ));
+                        }
+
+                        tilesets.push(Tileset {
+                            first_gid: parse_attribute(&attributes, "tileset", "firstgid")?,
+                            tiles: HashMap::new(),
+                        });
+                    }
+                    // tiles can have objects too, for collision shapes
+                    "object" if current_tile.is_none() => {
+                        current_object = Some(Object {
+                            x: parse_attribute(&attributes, "object", "x")?,
+                            y: parse_attribute(&attributes, "object", "y")?,
+                            // renamed to class in newer versions of tiled
+                            kind: attribute(&attributes, "type")
+                                .or_else(|| attribute(&attributes, "class"))
+                                .map(str::to_string),
+                            ..Object::default()
+                        });
+                    }
+                    "polyline" if current_object.is_some() => {
+                        let points: String = parse_attribute(&attributes, "polyline", "points")?;
+                        current_object.as_mut().unwrap().points = parse_points(&points)?;
+                    }
+                    "property" if current_object.is_some() => {
+                        if let Some(key) = attribute(&attributes, "name") {
+                            let value = attribute(&attributes, "value").unwrap_or("");
+                            current_object
+                                .as_mut()
+                                .unwrap()
+                                .properties
+                                .insert(key.to_string(), value.to_string());
+                        }
+                    }
+                    "tile" if !tilesets.is_empty() => {
+                        current_tile = Some(parse_attribute(&attributes, "tile", "id")?);
+                    }
+                    "property" | "image" if current_tile.is_some() => {
+                        let tile = tilesets
+                            .last_mut()
+                            .unwrap()
+                            .tiles
+                            .entry(current_tile.unwrap())
+                            .or_insert_with(TileInfo::default);
+
+                        if name.local_name == "image" {
+                            let source: String = parse_attribute(&attributes, "image", "source")?;
+                            tile.image = Some(normalize(&dir.join(source)));
+                            continue;
+                        }
+
+                        let value = attribute(&attributes, "value").unwrap_or("");
+                        match attribute(&attributes, "name") {
+                            Some("solid") if value == "true" => {
+                                tile.collision = Some(TileCollision::Solid);
+                            }
+                            Some("platform") if value == "true" => {
+                                tile.collision = Some(TileCollision::Platform);
+                            }
+                            Some("slope") => {
+                                let slope = parse_slope(value).ok_or_else(|| {
+                                    RoomError::Invalid(format!("unknown slope {}", value))
+                                })?;
+                                tile.collision = Some(TileCollision::Slope(slope));
+                            }
+                            Some("layers") => tile.layers = Some(parse_layers(value)?),
+                            Some("mask") => tile.mask = Some(parse_layers(value)?),
+                            _ => (),
+                        }
+                    }
+                    "layer" => {
+                        layer_size = Some((
+                            parse_attribute(&attributes, "layer", "width")?,
+                            parse_attribute(&attributes, "layer", "height")?,
+                        ));
+                    }
+                    "data" => {
+                        let encoding = attribute(&attributes, "encoding");
+                        if encoding != Some("csv") {
+                            return Err(RoomError::Invalid(format!(
+                                "unsupported layer encoding {:?}",
+                                encoding
+                            )));
+                        }
+
+                        data = Some(String::new());
+                    }
+                    _ => (),
+                },
+                XmlEvent::Characters(text) => {
+                    if let Some(ref mut data) = data {
+                        data.push_str(&text);
+                    }
+                }
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "tile" => current_tile = None,
+                    "object" => {
+                        let object = match current_object.take() {
+                            Some(object) => object,
+                            None => continue,
+                        };
+
+                        let kind = object.kind.as_ref().map(String::as_str);
+                        if kind == Some("moving-platform") || kind == Some("crate") {
+                            let room = room.as_mut().ok_or_else(|| {
+                                RoomError::Invalid("object outside of <map>".to_string())
+                            })?;
+
+                            if kind == Some("crate") {
+                                room.add_crate(&object)?;
+                            } else {
+                                room.add_platform(&object)?;
+                            }
+                        }
+                    }
+                    "data" => {
+                        let room = room.as_mut().ok_or_else(|| {
+                            RoomError::Invalid("layer outside of <map>".to_string())
+                        })?;
+                        let (width, height) = layer_size.ok_or_else(|| {
+                            RoomError::Invalid("<data> outside of <layer>".to_string())
+                        })?;
+
+                        let data = data.take().unwrap();
+                        room.add_layer(&tilesets, width, height, &data)?;
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+        }
+
+        room.ok_or_else(|| RoomError::Invalid("no <map> found".to_string()))
+    }
+
+    fn add_layer(
+        &mut self,
+        tilesets: &[Tileset],
+        width: u32,
+        height: u32,
+        data: &str,
+    ) -> Result<(), RoomError> {
+        let gids = data
+            .split(',')
+            .map(|gid| gid.trim().parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|err| RoomError::Invalid(format!("invalid layer data: {}", err)))?;
+
+        let tile_count = width.checked_mul(height).ok_or_else(|| {
+            RoomError::Invalid(format!("layer of {}x{} tiles is too large", width, height))
+        })?;
+        if gids.len() != tile_count as usize {
+            return Err(RoomError::Invalid(format!(
+                "layer has {} tiles instead of {}x{}",
+                gids.len(),
+                width,
+                height
+            )));
+        }
+
+        for (idx, gid) in gids.into_iter().enumerate() {
+            let gid = gid & !GID_FLIP_FLAGS;
+
+            // empty
+            if gid == 0 {
+                continue;
+            }
+
+            let tileset = tilesets
+                .iter()
+                .filter(|tileset| tileset.first_gid <= gid)
+                .max_by_key(|tileset| tileset.first_gid)
+                .ok_or_else(|| RoomError::Invalid(format!("no tileset for tile {}", gid)))?;
+            let info = tileset
+                .tiles
+                .get(&(gid - tileset.first_gid))
+                .cloned()
+                .unwrap_or_default();
+
+            let idx = idx as u32;
+            self.tiles.push(Tile {
+                column: idx % width,
+                // tiled counts rows from the top
+                row: height - 1 - idx / width,
+                collision: info.collision,
+                layers: info.layers,
+                mask: info.mask,
+                image: info.image,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn add_platform(&mut self, object: &Object) -> Result<(), RoomError> {
+        let property = |key: &str, default: Option<f32>| object.positive_property(key, default);
+
+        let width = property("width", Some(self.tile_width))?.unwrap();
+        let height = property("height", Some(self.tile_height))?.unwrap();
+
+        if object.points.len() < 2 {
+            return Err(RoomError::Invalid(
+                "moving platform needs at least two points".to_string(),
+            ));
+        }
+
+        // tiled counts y from the top, and the points are the top left corner
+        let room_height = self.height as f32 * self.tile_height;
+        let waypoints: Vec<Position> = object
+            .points
+            .iter()
+            .map(|&(x, y)| Position {
+                x: object.x + x,
+                y: room_height - (object.y + y) - height,
+            })
+            .collect();
+
+        let motion = match (property("speed", None)?, property("period", None)?) {
+            (Some(speed), None) => PlatformMotion::Path { waypoints, speed },
+            (None, Some(period)) => PlatformMotion::Oscillate {
+                from: waypoints[0],
+                to: waypoints[waypoints.len() - 1],
+                period,
+            },
+            _ => {
+                return Err(RoomError::Invalid(
+                    "moving platform needs either a speed or a period".to_string(),
+                ))
+            }
+        };
+
+        let layers = |key: &str| object.properties.get(key).map(|names| parse_layers(names));
+
+        self.platforms.push(Platform {
+            width,
+            height,
+            motion,
+            layers: layers("layers").transpose()?,
+            mask: layers("mask").transpose()?,
+        });
+
+        Ok(())
+    }
+
+    fn add_crate(&mut self, object: &Object) -> Result<(), RoomError> {
+        let width = object
+            .positive_property("width", Some(self.tile_width))?
+            .unwrap();
+        let height = object
+            .positive_property("height", Some(self.tile_height))?
+            .unwrap();
+        let mass = object.positive_property("mass", Some(2.0))?.unwrap();
+
+        // tiled counts y from the top
+        let room_height = self.height as f32 * self.tile_height;
+        self.crates.push(Crate {
+            position: Position {
+                x: object.x,
+                y: room_height - object.y - height,
+            },
+            width,
+            height,
+            mass,
+        });
+
+        Ok(())
+    }
+
+    /// Greedily merges the solid tiles into blocks. Starting at the bottom left, each
+    /// block is as wide as the row of tiles allows and then as high as the rows above do.
+    pub fn solid_blocks(&self) -> Vec<SolidBlock> {
+        let mut solid: HashMap<(u32, u32), (Option<CollisionLayers>, Option<CollisionLayers>)> =
+            self.tiles
+                .iter()
+                .filter(|tile| tile.collision == Some(TileCollision::Solid))
+                .map(|tile| ((tile.column, tile.row), (tile.layers, tile.mask)))
+                .collect();
+
+        let mut cells: Vec<(u32, u32)> = solid.keys().cloned().collect();
+        cells.sort_by_key(|&(column, row)| (row, column));
+
+        let mut blocks = Vec::new();
+        for (column, row) in cells {
+            // already part of a block
+            let layers = match solid.get(&(column, row)) {
+                Some(&layers) => layers,
+                None => continue,
+            };
+
+            let mut columns = 1;
+            while solid.get(&(column + columns, row)) == Some(&layers) {
+                columns += 1;
+            }
+
+            let mut rows = 1;
+            while (column..column + columns).all(|c| solid.get(&(c, row + rows)) == Some(&layers)) {
+                rows += 1;
+            }
+
+            for r in row..row + rows {
+                for c in column..column + columns {
+                    solid.remove(&(c, r));
+                }
+            }
+
+            blocks.push(SolidBlock {
+                column,
+                row,
+                columns,
+                rows,
+                layers: layers.0,
+                mask: layers.1,
+            });
+        }
+
+        blocks
+    }
+
+    /// Creates an entity for every tile, moving platform and crate and marks them as changed, so
+    /// they are replicated. Solid tiles only get sprites, they collide as the entities created
+    /// for their `solid_blocks`.
+    pub fn create_entities(&self, world: &mut World<LevelSystems>) -> Vec<Entity> {
+        let default_tex_info = TextureSlug::tilesets__cave__tile1.texture_info();
+
+        let half_extents = Vector2::new(self.tile_width / 2.0, self.tile_height / 2.0);
+
+        let mut entities: Vec<Entity> = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let mut position = Position {
+                    x: tile.column as f32 * self.tile_width,
+                    y: tile.row as f32 * self.tile_height,
+                };
+                let mut sprite_height = self.tile_height;
+
+                let collision_shape = tile.collision.and_then(|collision| {
+                    let shape = match collision {
+                        TileCollision::Solid => return None,
+                        TileCollision::Slope(slope) => CollisionShape::new_single(
+                            Cuboid::new(half_extents),
+                            half_extents,
+                            CollisionType::Solid,
+                        )
+                        .with_slope(slope),
+                        TileCollision::Platform => {
+                            position.y += self.tile_height - PLATFORM_HEIGHT;
+                            sprite_height = PLATFORM_HEIGHT;
+
+                            let half_extents = Vector2::new(half_extents.x, PLATFORM_HEIGHT / 2.0);
+                            CollisionShape::new_single(
+                                Cuboid::new(half_extents),
+                                half_extents,
+                                CollisionType::OneWayPlatform,
+                            )
+                        }
+                    };
+
+                    Some(with_layers(shape, tile.layers, tile.mask))
+                });
+
+                let texture_info = tile
+                    .image
+                    .as_ref()
+                    .and_then(|image| TextureSlug::from_path(image))
+                    .map(|slug| slug.texture_info())
+                    .unwrap_or(default_tex_info);
+                let sprite = Sprite {
+                    info: SpriteInfo {
+                        width: self.tile_width,
+                        height: sprite_height,
+                        texture_info,
+                    },
+                    sprite_layer: SpriteLayer::Background,
+                };
+
+                let e = world.create_entity(
+                    |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                        data.position.add(&entity, position);
+                        data.sprite.add(&entity, sprite.clone());
+
+                        if let Some(ref collision_shape) = collision_shape {
+                            data.collision_shape.add(&entity, collision_shape.clone());
+                        }
+                    },
+                );
+
+                let changed_flags = &mut world.services.changed_flags;
+                changed_flags.position.insert(e, position);
+                changed_flags.sprite.insert(e, sprite);
+
+                if let Some(collision_shape) = collision_shape {
+                    changed_flags.collision_shape.insert(e, collision_shape);
+                }
+
+                e
+            })
+            .collect();
+
+        for block in self.solid_blocks() {
+            let position = Position {
+                x: block.column as f32 * self.tile_width,
+                y: block.row as f32 * self.tile_height,
+            };
+            let half_extents = Vector2::new(
+                block.columns as f32 * self.tile_width / 2.0,
+                block.rows as f32 * self.tile_height / 2.0,
+            );
+            let collision_shape = with_layers(
+                CollisionShape::new_single(
+                    Cuboid::new(half_extents),
+                    half_extents,
+                    CollisionType::Solid,
+                ),
+                block.layers,
+                block.mask,
+            );
+
+            let e = world.create_entity(
+                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                    data.position.add(&entity, position);
+                    data.collision_shape.add(&entity, collision_shape.clone());
+                },
+            );
+
+            let changed_flags = &mut world.services.changed_flags;
+            changed_flags.position.insert(e, position);
+            changed_flags.collision_shape.insert(e, collision_shape);
+
+            entities.push(e);
+        }
+
+        for platform in &self.platforms {
+            let position = platform.motion.position_at(0.0);
+            let half_extents = Vector2::new(platform.width / 2.0, platform.height / 2.0);
+            let collision_shape = with_layers(
+                CollisionShape::new_single(
+                    Cuboid::new(half_extents),
+                    half_extents,
+                    CollisionType::Solid,
+                ),
+                platform.layers,
+                platform.mask,
+            );
+            let sprite = Sprite {
+                info: SpriteInfo {
+                    width: platform.width,
+                    height: platform.height,
+                    texture_info: default_tex_info,
+                },
+                sprite_layer: SpriteLayer::Background,
+            };
+            let moving_platform = MovingPlatform::new(platform.motion.clone());
+
+            let e = world.create_entity(
+                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                    data.position.add(&entity, position);
+                    data.collision_shape.add(&entity, collision_shape.clone());
+                    data.sprite.add(&entity, sprite.clone());
+                    data.moving_platform.add(&entity, moving_platform.clone());
+                },
+            );
+
+            let changed_flags = &mut world.services.changed_flags;
+            changed_flags.position.insert(e, position);
+            changed_flags.collision_shape.insert(e, collision_shape);
+            changed_flags.sprite.insert(e, sprite);
+            changed_flags.moving_platform.insert(e, moving_platform);
+
+            entities.push(e);
+        }
+
+        for crate_ in &self.crates {
+            let position = crate_.position;
+            let half_extents = Vector2::new(crate_.width / 2.0, crate_.height / 2.0);
+            let collision_shape = CollisionShape::new_single(
+                Cuboid::new(half_extents),
+                half_extents,
+                CollisionType::Solid,
+            )
+            .with_mass(crate_.mass);
+            let velocity = Velocity {
+                vx: 0.0,
+                vy: 0.0,
+                last_pos: position,
+            };
+            let gravity = Gravity::new();
+            let sprite = Sprite {
+                info: SpriteInfo {
+                    width: crate_.width,
+                    height: crate_.height,
+                    texture_info: default_tex_info,
+                },
+                sprite_layer: SpriteLayer::Foreground,
+            };
+
+            let e = world.create_entity(
+                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                    data.position.add(&entity, position);
+                    data.velocity.add(&entity, velocity);
+                    data.gravity.add(&entity, gravity);
+                    data.collision_shape.add(&entity, collision_shape.clone());
+                    data.sprite.add(&entity, sprite.clone());
+                },
+            );
+
+            let changed_flags = &mut world.services.changed_flags;
+            changed_flags.position.insert(e, position);
+            changed_flags.velocity.insert(e, velocity);
+            changed_flags.gravity.insert(e, gravity);
+            changed_flags.collision_shape.insert(e, collision_shape);
+            changed_flags.sprite.insert(e, sprite);
+
+            entities.push(e);
+        }
+
+        entities
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TILESET: &str = r#"
+ <tileset firstgid="1" name="cave" tilewidth="32" tileheight="32" tilecount="3" columns="0">
+  <tile id="0">
+   <properties>
+    <property name="solid" type="bool" value="true"/>
+   </properties>
+   <image width="32" height="32" source="../textures/tilesets/cave/tile1.png"/>
+  </tile>
+  <tile id="1">
+   <properties>
+    <property name="platform" type="bool" value="true"/>
+    <property name="layers" value="world, players"/>
+    <property name="mask" value="players"/>
+   </properties>
+  </tile>
+  <tile id="2">
+   <properties>
+    <property name="slope" value="rising-22-low"/>
+   </properties>
+  </tile>
+ </tileset>"#;
+
+    fn map(layer: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.0" orientation="orthogonal" width="3" height="2" tilewidth="32" tileheight="32">
+{}
+{}
+</map>"#,
+            TILESET, layer
+        )
+    }
+
+    fn parse(tmx: &str) -> Result<Room, RoomError> {
+        Room::parse(tmx.as_bytes(), Path::new("assets/rooms"))
+    }
+
+    #[test]
+    fn tiles_are_parsed() {
+        let room = parse(&map(r#"
+ <layer name="tiles" width="3" height="2">
+  <data encoding="csv">
+0,2,3,
+1,1,2147483649
+</data>
+ </layer>"#))
+        .unwrap();
+
+        assert_eq!((room.width, room.height), (3, 2));
+        assert_eq!((room.tile_width, room.tile_height), (32.0, 32.0));
+
+        let tiles: Vec<_> = room
+            .tiles
+            .iter()
+            .map(|tile| (tile.column, tile.row, tile.collision))
+            .collect();
+
+        assert_eq!(
+            tiles,
+            vec![
+                (1, 1, Some(TileCollision::Platform)),
+                (2, 1, Some(TileCollision::Slope(Slope::Rising22Low))),
+                (0, 0, Some(TileCollision::Solid)),
+                (1, 0, Some(TileCollision::Solid)),
+                // flipped horizontally
+                (2, 0, Some(TileCollision::Solid)),
+            ]
+        );
+
+        assert_eq!(
+            room.tiles[2].image,
+            Some(PathBuf::from("assets/textures/tilesets/cave/tile1.png"))
+        );
+        assert_eq!(room.tiles[0].image, None);
+
+        assert_eq!(
+            (room.tiles[0].layers, room.tiles[0].mask),
+            (
+                Some(CollisionLayers::WORLD | CollisionLayers::PLAYERS),
+                Some(CollisionLayers::PLAYERS)
+            )
+        );
+        assert_eq!((room.tiles[1].layers, room.tiles[1].mask), (None, None));
+    }
+
+    #[test]
+    fn moving_platforms_are_parsed() {
+        let room = parse(&map(r#"
+ <objectgroup id="2" name="platforms">
+  <object id="1" type="moving-platform" x="32" y="16">
+   <properties>
+    <property name="width" type="float" value="64"/>
+    <property name="height" type="float" value="8"/>
+    <property name="speed" type="float" value="20"/>
+   </properties>
+   <polyline points="0,0 32,0 32,32"/>
+  </object>
+  <object id="2" type="moving-platform" x="0" y="0">
+   <properties>
+    <property name="period" type="float" value="4"/>
+    <property name="mask" value="none"/>
+   </properties>
+   <polyline points="0,0 0,32"/>
+  </object>
+  <object id="3" x="0" y="0" width="32" height="32"/>
+ </objectgroup>"#))
+        .unwrap();
+
+        let position = |x, y| Position { x, y };
+
+        assert_eq!(
+            room.platforms,
+            vec![
+                Platform {
+                    width: 64.0,
+                    height: 8.0,
+                    motion: PlatformMotion::Path {
+                        waypoints: vec![
+                            position(32.0, 40.0),
+                            position(64.0, 40.0),
+                            position(64.0, 8.0)
+                        ],
+                        speed: 20.0,
+                    },
+                    layers: None,
+                    mask: None,
+                },
+                Platform {
+                    width: 32.0,
+                    height: 32.0,
+                    motion: PlatformMotion::Oscillate {
+                        from: position(0.0, 32.0),
+                        to: position(0.0, 0.0),
+                        period: 4.0,
+                    },
+                    layers: None,
+                    mask: Some(CollisionLayers::NONE),
+                },
+            ]
+        );
+
+        assert_eq!(
+            room.platforms[0].motion.position_at(1.0),
+            position(52.0, 40.0)
+        );
+        assert_eq!(
+            room.platforms[1].motion.position_at(2.0),
+            position(0.0, 0.0)
+        );
+
+        // waypoints at the same position are passed without stopping
+        let repeated = PlatformMotion::Path {
+            waypoints: vec![position(0.0, 0.0), position(0.0, 0.0), position(32.0, 0.0)],
+            speed: 16.0,
+        };
+        assert_eq!(repeated.position_at(0.0), position(0.0, 0.0));
+        assert_eq!(repeated.position_at(1.0), position(16.0, 0.0));
+        assert_eq!(repeated.position_at(4.0), position(0.0, 0.0));
+    }
+
+    #[test]
+    fn crates_are_parsed() {
+        let room = parse(&map(r#"
+ <objectgroup id="2" name="objects">
+  <object id="1" type="crate" x="32" y="0"/>
+  <object id="2" class="crate" x="0" y="40">
+   <properties>
+    <property name="width" type="float" value="16"/>
+    <property name="height" type="float" value="24"/>
+    <property name="mass" type="float" value="0.5"/>
+   </properties>
+  </object>
+ </objectgroup>"#))
+        .unwrap();
+
+        assert_eq!(
+            room.crates,
+            vec![
+                Crate {
+                    position: Position { x: 32.0, y: 32.0 },
+                    width: 32.0,
+                    height: 32.0,
+                    mass: 2.0,
+                },
+                Crate {
+                    position: Position { x: 0.0, y: 0.0 },
+                    width: 16.0,
+                    height: 24.0,
+                    mass: 0.5,
+                },
+            ]
+        );
+    }
+
+    fn room_with_solid_tiles(tiles: &[(u32, u32, Option<CollisionLayers>)]) -> Room {
+        Room {
+            width: 16,
+            height: 8,
+            tile_width: 32.0,
+            tile_height: 32.0,
+            tiles: tiles
+                .iter()
+                .map(|&(column, row, layers)| Tile {
+                    column,
+                    row,
+                    collision: Some(TileCollision::Solid),
+                    layers,
+                    mask: None,
+                    image: None,
+                })
+                .collect(),
+            platforms: Vec::new(),
+            crates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn solid_tiles_are_merged_into_blocks() {
+        let players = CollisionLayers::by_name("players");
+        let mut tiles = Vec::new();
+        // a floor with a gap, a 2x2 square, and a wall on the floor with a tile on another layer
+        for &column in &[0, 1, 2, 3, 4, 7, 10, 11] {
+            tiles.push((column, 0, None));
+        }
+        tiles.extend(&[(10, 1, None), (11, 1, None)]);
+        tiles.extend(&[(0, 1, None), (1, 1, None), (0, 2, None), (1, 2, players)]);
+
+        let mut room = room_with_solid_tiles(&tiles);
+        room.tiles.push(Tile {
+            column: 2,
+            row: 1,
+            collision: Some(TileCollision::Slope(Slope::Rising45)),
+            layers: None,
+            mask: None,
+            image: None,
+        });
+
+        let block = |column, row, columns, rows, layers| SolidBlock {
+            column,
+            row,
+            columns,
+            rows,
+            layers,
+            mask: None,
+        };
+        assert_eq!(
+            room.solid_blocks(),
+            vec![
+                block(0, 0, 5, 1, None),
+                block(7, 0, 1, 1, None),
+                block(10, 0, 2, 2, None),
+                block(0, 1, 2, 1, None),
+                block(0, 2, 1, 1, None),
+                block(1, 2, 1, 1, players),
+            ]
+        );
+    }
+
+    #[test]
+    fn entities_walk_over_merged_floors() {
+        let tiles: Vec<_> = (0..12).map(|column| (column, 0, None)).collect();
+        let room = room_with_solid_tiles(&tiles);
+
+        let mut world = World::<LevelSystems>::new();
+        world.services.delta_time_s = 0.01;
+        let entities = room.create_entities(&mut world);
+
+        // a sprite for every tile, but a single box for the floor
+        let boxes = entities
+            .iter()
+            .filter(|e| {
+                world
+                    .with_entity_data(e, |en, comps| comps.collision_shape.has(&en))
+                    .unwrap()
+            })
+            .count();
+        assert_eq!((entities.len(), boxes), (13, 1));
+
+        let start = Position { x: 4.0, y: 32.0 };
+        let half_extents = Vector2::new(5.0, 8.0);
+        let walker = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, start);
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_single(
+                        Cuboid::new(half_extents),
+                        half_extents,
+                        CollisionType::Solid,
+                    ),
+                );
+                data.velocity.add(
+                    &entity,
+                    Velocity {
+                        vx: 0.0,
+                        vy: 0.0,
+                        last_pos: start,
+                    },
+                );
+                data.gravity.add(&entity, Gravity::new());
+            },
+        );
+
+        // across all the seams between the tiles
+        for _ in 0..300 {
+            world.with_entity_data(&walker, |en, comps| comps.velocity[en].vx = 1.0);
+            world.update();
+        }
+
+        let end = world
+            .with_entity_data(&walker, |en, comps| comps.position[en])
+            .unwrap();
+        assert!(
+            (end.x - (start.x + 300.0)).abs() < 0.001,
+            "stopped at {:?}",
+            end
+        );
+        assert!(
+            (end.y - start.y).abs() < 0.01,
+            "left the floor to {:?}",
+            end
+        );
+    }
+
+    #[test]
+    fn invalid_rooms_are_rejected() {
+        let base64 = map(r#"
+ <layer name="tiles" width="3" height="2">
+  <data encoding="base64">AAAA</data>
+ </layer>"#);
+        let too_short = map(r#"
+ <layer name="tiles" width="3" height="2">
+  <data encoding="csv">1,1</data>
+ </layer>"#);
+        let too_large = map(r#"
+ <layer name="tiles" width="65536" height="65536">
+  <data encoding="csv">1,1</data>
+ </layer>"#);
+        let unknown_slope = map("").replace("rising-22-low", "sideways");
+        let unknown_layer = map("").replace("world, players", "world, ghosts");
+        let weightless_crate = map(r#"
+ <objectgroup id="2" name="objects">
+  <object id="1" type="crate" x="0" y="0">
+   <properties>
+    <property name="mass" type="float" value="0"/>
+   </properties>
+  </object>
+ </objectgroup>"#);
+        let unmoving_platform = map(r#"
+ <objectgroup id="2" name="platforms">
+  <object id="1" type="moving-platform" x="0" y="0">
+   <polyline points="0,0 32,0"/>
+  </object>
+ </objectgroup>"#);
+
+        let object = |kind: &str, properties: &[(&str, &str)]| {
+            let properties: String = properties
+                .iter()
+                .map(|(name, value)| {
+                    format!(
+                        r#"<property name="{}" type="float" value="{}"/>"#,
+                        name, value
+                    )
+                })
+                .collect();
+
+            map(&format!(
+                r#"
+ <objectgroup id="2" name="objects">
+  <object id="1" type="{}" x="0" y="0">
+   <properties>{}</properties>
+   <polyline points="0,0 32,0"/>
+  </object>
+ </objectgroup>"#,
+                kind, properties
+            ))
+        };
+
+        // the same, but with one invalid property each
+        assert!(parse(&object("moving-platform", &[("speed", "20")])).is_ok());
+        assert!(parse(&object("crate", &[])).is_ok());
+
+        let mut invalid_values = vec![
+            object("moving-platform", &[("period", "0")]),
+            object("moving-platform", &[("period", "-4")]),
+            object("moving-platform", &[("period", "NaN")]),
+            object("moving-platform", &[("period", "inf")]),
+            object("moving-platform", &[("speed", "0")]),
+            object("moving-platform", &[("speed", "NaN")]),
+            object("crate", &[("mass", "NaN")]),
+            object("crate", &[("mass", "-1")]),
+        ];
+        for &size in &["width", "height"] {
+            for &value in &["0", "-32", "NaN"] {
+                invalid_values.push(object("moving-platform", &[("speed", "20"), (size, value)]));
+                invalid_values.push(object("crate", &[(size, value)]));
+            }
+        }
+
+        let invalid_rooms = vec![
+            base64,
+            too_short,
+            too_large,
+            unknown_slope,
+            unknown_layer,
+            unmoving_platform,
+            weightless_crate,
+        ];
+
+        for tmx in invalid_rooms.iter().chain(&invalid_values) {
+            match parse(tmx) {
+                Err(RoomError::Invalid(_)) => (),
+                other => panic!("expected an invalid room, got {:?}", other),
+            }
+        }
+
+        match parse("<map") {
+            Err(RoomError::Xml(_)) => (),
+            other => panic!("expected malformed xml, got {:?}", other),
+        }
+    }
+}
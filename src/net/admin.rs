@@ -0,0 +1,564 @@
+//! A text console for operating a running server, on a local TCP port. Clients send one
+//! command per line and have to `auth <password>` first. Every reply ends with a line
+//! starting with either `ok` or `error:`, so scripts know when it is complete.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+
+use crate::game::PlayerId;
+
+// longer lines close the connection, so a client cannot make us buffer endlessly
+const MAX_LINE_LENGTH: usize = 1024;
+
+const HELP: &[&str] = &[
+    "auth <password>      authenticate, required before any other command",
+    "peers                list connected peers",
+    "kick <player|addr>   disconnect a player, by id or by ip[:port]",
+    "ban <player|addr>    disconnect a player and refuse its address from now on",
+    "say <message>        send a message to all players",
+    "gravity [value]      show or change the gravity",
+    "reload               reload the room from its file",
+    "shutdown             disconnect all players and stop the server",
+    "quit                 close this connection",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Player(PlayerId),
+    // peers with this ip, and this port if given
+    Address(Ipv4Addr, Option<u16>),
+}
+
+impl Target {
+    pub fn matches_address(&self, addr: SocketAddrV4) -> bool {
+        match *self {
+            Target::Player(_) => false,
+            Target::Address(ip, port) => {
+                *addr.ip() == ip && port.map_or(true, |port| port == addr.port())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Target::Player(id) => write!(f, "player {}", id),
+            Target::Address(ip, Some(port)) => write!(f, "{}:{}", ip, port),
+            Target::Address(ip, None) => write!(f, "{}", ip),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminCommand {
+    Peers,
+    Kick(Target),
+    Ban(Target),
+    Say(String),
+    // `None` only shows the current gravity
+    Gravity(Option<f32>),
+    Reload,
+    Shutdown,
+}
+
+/// What the server replies to a command: lines of output, or an error message.
+pub type AdminReply = Result<Vec<String>, String>;
+
+enum Line {
+    Auth(String),
+    Help,
+    Quit,
+    Command(AdminCommand),
+}
+
+fn parse_target(arg: &str) -> Result<Target, String> {
+    if let Ok(id) = arg.parse::<u16>() {
+        Ok(Target::Player(PlayerId::from(id)))
+    } else if let Ok(addr) = arg.parse::<SocketAddrV4>() {
+        Ok(Target::Address(*addr.ip(), Some(addr.port())))
+    } else if let Ok(ip) = arg.parse::<Ipv4Addr>() {
+        Ok(Target::Address(ip, None))
+    } else {
+        Err(format!("{:?} is neither a player id nor an address", arg))
+    }
+}
+
+fn parse_line(line: &str) -> Result<Line, String> {
+    let line = line.trim();
+    let (command, arg) = match line.find(' ') {
+        Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+        None => (line, ""),
+    };
+
+    let expect_no_arg = |parsed: Line| {
+        if arg.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(format!("{} takes no arguments", command))
+        }
+    };
+
+    match command {
+        "auth" => Ok(Line::Auth(arg.to_string())),
+        "help" => expect_no_arg(Line::Help),
+        "quit" => expect_no_arg(Line::Quit),
+        "peers" => expect_no_arg(Line::Command(AdminCommand::Peers)),
+        "kick" => parse_target(arg).map(|target| Line::Command(AdminCommand::Kick(target))),
+        "ban" => parse_target(arg).map(|target| Line::Command(AdminCommand::Ban(target))),
+        "say" if arg.is_empty() => Err("say needs a message".to_string()),
+        "say" => Ok(Line::Command(AdminCommand::Say(arg.to_string()))),
+        "gravity" if arg.is_empty() => Ok(Line::Command(AdminCommand::Gravity(None))),
+        "gravity" => match arg.parse::<f32>() {
+            Ok(gravity) if gravity.is_finite() => {
+                Ok(Line::Command(AdminCommand::Gravity(Some(gravity))))
+            }
+            _ => Err(format!("{:?} is not a valid gravity", arg)),
+        },
+        "reload" => expect_no_arg(Line::Command(AdminCommand::Reload)),
+        "shutdown" => expect_no_arg(Line::Command(AdminCommand::Shutdown)),
+        _ => Err(format!("unknown command {:?}, try help", command)),
+    }
+}
+
+struct AdminConnection {
+    stream: TcpStream,
+    addr: SocketAddr,
+    buffer: Vec<u8>,
+    authenticated: bool,
+    // set once the client went away
+    hung_up: bool,
+    // set if we close the connection after the current reply
+    closed: bool,
+    // set while the reply to a command is deferred, see `AdminConsole::reply`
+    awaiting_reply: bool,
+}
+
+impl AdminConnection {
+    fn send_reply(&mut self, reply: AdminReply) {
+        let mut text = String::new();
+
+        match reply {
+            Ok(lines) => {
+                for line in lines {
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+                text.push_str("ok\n");
+            }
+            Err(message) => {
+                text.push_str("error: ");
+                text.push_str(&message);
+                text.push('\n');
+            }
+        }
+
+        if let Err(err) = self.stream.write_all(text.as_bytes()) {
+            println!("could not reply to admin {}: {}", self.addr, err);
+            self.closed = true;
+        }
+    }
+
+    /// Reads everything available into the buffer.
+    fn receive(&mut self) {
+        let mut buf = [0; 512];
+
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.hung_up = true;
+                    break;
+                }
+                Ok(len) => self.buffer.extend_from_slice(&buf[..len]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.hung_up = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Takes the next complete line from the buffer.
+    fn next_line(&mut self) -> Option<String> {
+        match self.buffer.iter().position(|&b| b == b'\n') {
+            Some(idx) => {
+                let line: Vec<u8> = self.buffer.drain(..=idx).collect();
+                Some(String::from_utf8_lossy(&line).into_owned())
+            }
+            None => {
+                if self.buffer.len() > MAX_LINE_LENGTH {
+                    println!("admin {} sent a too long line, closing", self.addr);
+                    self.closed = true;
+                }
+
+                None
+            }
+        }
+    }
+}
+
+pub struct AdminConsole {
+    listener: TcpListener,
+    password: String,
+    connections: Vec<AdminConnection>,
+}
+
+impl AdminConsole {
+    pub fn bind(addr: SocketAddrV4, password: String) -> io::Result<AdminConsole> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(AdminConsole {
+            listener,
+            password,
+            connections: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts new connections and runs the commands of authenticated ones with `execute`,
+    /// replying with its result. Does not block.
+    ///
+    /// `execute` returns `None` for commands which complete later, their reply is sent with
+    /// `reply`. Until then, further commands of the same connection wait.
+    pub fn poll(
+        &mut self,
+        mut execute: impl FnMut(SocketAddr, AdminCommand) -> Option<AdminReply>,
+    ) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(err) = stream.set_nonblocking(true) {
+                        println!("could not accept admin {}: {}", addr, err);
+                        continue;
+                    }
+
+                    println!("admin connected from {}", addr);
+
+                    self.connections.push(AdminConnection {
+                        stream,
+                        addr,
+                        buffer: Vec::new(),
+                        authenticated: false,
+                        hung_up: false,
+                        closed: false,
+                        awaiting_reply: false,
+                    });
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    println!("could not accept admin connection: {}", err);
+                    break;
+                }
+            }
+        }
+
+        for connection in &mut self.connections {
+            if connection.awaiting_reply {
+                continue;
+            }
+
+            connection.receive();
+
+            while !connection.closed && !connection.awaiting_reply {
+                let line = match connection.next_line() {
+                    Some(line) => line,
+                    None => break,
+                };
+
+                let reply = match parse_line(&line) {
+                    Ok(Line::Auth(ref password)) if *password == self.password => {
+                        println!("admin {} authenticated", connection.addr);
+                        connection.authenticated = true;
+                        Ok(Vec::new())
+                    }
+                    Ok(Line::Auth(_)) => {
+                        println!("admin {} used a wrong password", connection.addr);
+                        connection.closed = true;
+                        Err("wrong password".to_string())
+                    }
+                    Ok(Line::Help) => Ok(HELP.iter().map(|line| line.to_string()).collect()),
+                    Ok(Line::Quit) => {
+                        connection.closed = true;
+                        Ok(Vec::new())
+                    }
+                    Ok(Line::Command(_)) if !connection.authenticated => {
+                        Err("not authenticated, use auth <password>".to_string())
+                    }
+                    Ok(Line::Command(command)) => {
+                        println!("admin {}: {:?}", connection.addr, command);
+
+                        match execute(connection.addr, command) {
+                            Some(reply) => reply,
+                            None => {
+                                connection.awaiting_reply = true;
+                                continue;
+                            }
+                        }
+                    }
+                    Err(message) => Err(message),
+                };
+
+                connection.send_reply(reply);
+            }
+        }
+
+        self.connections.retain(|connection| {
+            let open = !connection.hung_up && !connection.closed;
+
+            if !open {
+                println!("admin {} disconnected", connection.addr);
+            }
+
+            open
+        });
+    }
+
+    /// Sends the deferred reply to a command of the admin connected from `addr`, if it is
+    /// still connected.
+    pub fn reply(&mut self, addr: SocketAddr, reply: AdminReply) {
+        let connection = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.addr == addr && connection.awaiting_reply);
+
+        if let Some(connection) = connection {
+            connection.send_reply(reply);
+            connection.awaiting_reply = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn commands_are_parsed() {
+        let command = |line: &str| match parse_line(line) {
+            Ok(Line::Command(command)) => Ok(command),
+            Ok(_) => panic!("{:?} is not a server command", line),
+            Err(message) => Err(message),
+        };
+
+        assert_eq!(
+            command("kick 3"),
+            Ok(AdminCommand::Kick(Target::Player(PlayerId::from(3))))
+        );
+        assert_eq!(
+            command("ban 10.0.0.1"),
+            Ok(AdminCommand::Ban(Target::Address(
+                Ipv4Addr::new(10, 0, 0, 1),
+                None
+            )))
+        );
+        assert_eq!(
+            command("kick 10.0.0.1:9001\r\n"),
+            Ok(AdminCommand::Kick(Target::Address(
+                Ipv4Addr::new(10, 0, 0, 1),
+                Some(9001)
+            )))
+        );
+        assert_eq!(
+            command("say  hello there "),
+            Ok(AdminCommand::Say("hello there".to_string()))
+        );
+        assert_eq!(command("gravity"), Ok(AdminCommand::Gravity(None)));
+        assert_eq!(
+            command("gravity 75.5"),
+            Ok(AdminCommand::Gravity(Some(75.5)))
+        );
+        assert!(command("gravity NaN").is_err());
+        assert!(command("kick someone").is_err());
+        assert!(command("reload now").is_err());
+        assert!(command("fly").is_err());
+    }
+
+    #[test]
+    fn console_can_be_driven_over_tcp() {
+        let mut console = AdminConsole::bind(
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+            "secret".to_string(),
+        )
+        .unwrap();
+        let addr = console.local_addr().unwrap();
+
+        let (transcript_tx, transcript_rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+
+            // sends a command and returns all lines of the reply
+            let mut send = |command: &str| {
+                writeln!(writer, "{}", command).unwrap();
+
+                let mut reply = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_string();
+                    let done = line == "ok" || line.starts_with("error:");
+                    reply.push(line);
+
+                    if done {
+                        return reply;
+                    }
+                }
+            };
+
+            let transcript = vec![
+                send("peers"),
+                send("auth secret"),
+                send("peers"),
+                send("gravity 50"),
+                send("shutdown"),
+            ];
+
+            transcript_tx.send(transcript).unwrap();
+        });
+
+        let mut executed = Vec::new();
+        let start = Instant::now();
+
+        let transcript = loop {
+            console.poll(|_, command| {
+                executed.push(command.clone());
+
+                match command {
+                    AdminCommand::Peers => Some(Ok(vec!["1 alice 127.0.0.1:1234".to_string()])),
+                    _ => Some(Ok(Vec::new())),
+                }
+            });
+
+            if let Ok(transcript) = transcript_rx.try_recv() {
+                break transcript;
+            }
+
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "client did not finish"
+            );
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        assert_eq!(
+            transcript,
+            vec![
+                vec!["error: not authenticated, use auth <password>".to_string()],
+                vec!["ok".to_string()],
+                vec!["1 alice 127.0.0.1:1234".to_string(), "ok".to_string()],
+                vec!["ok".to_string()],
+                vec!["ok".to_string()],
+            ]
+        );
+        assert_eq!(
+            executed,
+            vec![
+                AdminCommand::Peers,
+                AdminCommand::Gravity(Some(50.0)),
+                AdminCommand::Shutdown,
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_password_closes_connection() {
+        let mut console = AdminConsole::bind(
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+            "secret".to_string(),
+        )
+        .unwrap();
+        let mut stream = TcpStream::connect(console.local_addr().unwrap()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        writeln!(stream, "auth guess").unwrap();
+
+        let start = Instant::now();
+        while !console.connections.is_empty() || start.elapsed() < Duration::from_millis(50) {
+            console.poll(|_, _| panic!("no command may run"));
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "connection was not closed"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "error: wrong password\n");
+    }
+
+    #[test]
+    fn deferred_replies_hold_back_later_commands() {
+        let mut console = AdminConsole::bind(
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0),
+            "secret".to_string(),
+        )
+        .unwrap();
+        let mut stream = TcpStream::connect(console.local_addr().unwrap()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let admin_addr = stream.local_addr().unwrap();
+
+        write!(stream, "auth secret\nreload\npeers\n").unwrap();
+
+        // reloading completes later, everything else right away
+        let poll = |console: &mut AdminConsole, executed: &mut Vec<AdminCommand>| {
+            console.poll(|addr, command| {
+                assert_eq!(addr, admin_addr);
+                executed.push(command.clone());
+
+                match command {
+                    AdminCommand::Reload => None,
+                    _ => Some(Ok(vec!["no peers connected".to_string()])),
+                }
+            });
+        };
+
+        let mut executed = Vec::new();
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(50) {
+            poll(&mut console, &mut executed);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(executed, vec![AdminCommand::Reload]);
+
+        console.reply(admin_addr, Err("could not reload room".to_string()));
+        poll(&mut console, &mut executed);
+        assert_eq!(executed, vec![AdminCommand::Reload, AdminCommand::Peers]);
+
+        let mut reader = BufReader::new(stream);
+        let lines: Vec<String> = (0..4)
+            .map(|_| {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                line
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "ok\n",
+                "error: could not reload room\n",
+                "no peers connected\n",
+                "ok\n"
+            ]
+        );
+    }
+}
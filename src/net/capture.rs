@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use super::chat::{ChatMessage, ChatRequest};
+use super::protocol::{
+    self, Checksum, DecodeError, Hello, JoinRejection, MessageVisitor, NetEvent, Resync, Welcome,
+};
+use super::replication;
+use super::{EVENT_CHANNEL_ID, INPUT_CHANNEL_ID, UPDATE_CHANNEL_ID};
+use crate::components::Intents;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapturedPacket {
+    // nanoseconds since the capture was started
+    pub timestamp_ns: u64,
+    pub direction: Direction,
+    pub peer: String,
+    pub channel_id: u8,
+    pub data: Vec<u8>,
+}
+
+/// Records every packet sent or received by a `Server` or `Client` to a file.
+pub struct CaptureWriter {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    pub fn create(path: &Path) -> io::Result<CaptureWriter> {
+        Ok(CaptureWriter {
+            out: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        direction: Direction,
+        peer: &enet::Address,
+        channel_id: u8,
+        data: &[u8],
+    ) {
+        let elapsed = self.start.elapsed();
+
+        let packet = CapturedPacket {
+            timestamp_ns: elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos()),
+            direction,
+            peer: format!("{}:{}", peer.ip(), peer.port()),
+            channel_id,
+            data: data.to_vec(),
+        };
+
+        if let Err(err) = bincode::serialize_into(&mut self.out, &packet) {
+            println!("could not write captured packet: {}", err);
+        }
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+pub struct CaptureReader {
+    reader: BufReader<File>,
+    // no packet can be larger than the whole capture
+    len: u64,
+}
+
+impl CaptureReader {
+    pub fn open(path: &Path) -> io::Result<CaptureReader> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        Ok(CaptureReader {
+            reader: BufReader::new(file),
+            len,
+        })
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = Result<CapturedPacket, bincode::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.fill_buf() {
+            Ok(buf) if buf.is_empty() => None,
+            Ok(_) => Some(
+                bincode::config()
+                    .limit(self.len)
+                    .deserialize_from(&mut self.reader),
+            ),
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+}
+
+struct DescribeMessage<'a> {
+    lines: &'a mut Vec<String>,
+}
+
+impl MessageVisitor for DescribeMessage<'_> {
+    fn visit_replicated_events(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let events: Vec<(u64, NetEvent)> = protocol::read(data)?;
+
+        for (sim_time, event) in events {
+            self.lines
+                .push(format!("tick {:>8} event {:?}", sim_time, event));
+        }
+
+        Ok(())
+    }
+
+    fn visit_checksum(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let checksum: Checksum = protocol::read(data)?;
+        self.lines.push(format!(
+            "tick {:>8} checksum {:x}",
+            checksum.sim_time, checksum.checksum
+        ));
+
+        Ok(())
+    }
+
+    fn visit_resync_request(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let sim_time: u64 = protocol::read(data)?;
+        self.lines.push(format!("tick {:>8} resync request", sim_time));
+
+        Ok(())
+    }
+
+    fn visit_resync(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let resync: Resync = protocol::read(data)?;
+        self.lines.push(format!(
+            "tick {:>8} resync, server state: {} bytes",
+            resync.sim_time,
+            resync.server_state.map_or(0, |state| state.len())
+        ));
+
+        Ok(())
+    }
+
+    fn visit_hello(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let hello: Hello = protocol::read(data)?;
+        self.lines.push(format!("{:?}", hello));
+
+        Ok(())
+    }
+
+    fn visit_welcome(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let welcome: Welcome = protocol::read(data)?;
+        self.lines.push(format!("{:?}", welcome));
+
+        Ok(())
+    }
+
+    fn visit_entities_removed(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let removed: Vec<u64> = protocol::read(data)?;
+        self.lines.push(format!("entities removed: {:?}", removed));
+
+        Ok(())
+    }
+
+    fn visit_join_rejected(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let rejection: JoinRejection = protocol::read(data)?;
+        self.lines.push(format!("join rejected: {:?}", rejection));
+
+        Ok(())
+    }
+
+    fn visit_intents(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let intents: Intents = protocol::read(data)?;
+        self.lines.push(format!("intents: {:?}", intents));
+
+        Ok(())
+    }
+
+    fn visit_server_message(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let text: String = protocol::read(data)?;
+        self.lines.push(format!("server message: {:?}", text));
+
+        Ok(())
+    }
+
+    fn visit_chat_send(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let request: ChatRequest = protocol::read(data)?;
+        self.lines.push(format!("chat request: {:?}", request));
+
+        Ok(())
+    }
+
+    fn visit_chat(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let message: ChatMessage = protocol::read(data)?;
+        self.lines.push(format!("tick {:>8} chat {:?}", message.sim_time, message));
+
+        Ok(())
+    }
+}
+
+/// Decodes a captured packet into readable lines, using the same decoding as `net::Client`.
+pub fn describe_packet(packet: &CapturedPacket) -> Result<Vec<String>, DecodeError> {
+    let mut lines = Vec::new();
+
+    match packet.channel_id {
+        UPDATE_CHANNEL_ID => {
+            for update in replication::decode_updates(&packet.data)? {
+                lines.push(format!(
+                    "tick {:>8} entity {:>6} {:<24} {:?}",
+                    update.sim_time,
+                    update.entity_id,
+                    update.component.name(),
+                    update.component
+                ));
+            }
+        }
+        EVENT_CHANNEL_ID | INPUT_CHANNEL_ID => protocol::parse_and_visit_message(
+            &packet.data,
+            DescribeMessage { lines: &mut lines },
+        )?,
+        channel_id => return Err(DecodeError::UnknownChannel(channel_id)),
+    }
+
+    Ok(lines)
+}
@@ -0,0 +1,240 @@
+//! Text chat between players. Clients send a `ChatRequest`, the server checks it and relays
+//! it as a `ChatMessage` to everyone, or to the sender's team only.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+// in chars, after trimming
+pub const MAX_CHAT_LENGTH: usize = 160;
+
+// messages kept in a `ChatLog`, older ones are dropped
+const DEFAULT_SCROLLBACK: usize = 200;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Team(pub u8);
+
+pub const NUM_TEAMS: u8 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatScope {
+    All,
+    Team,
+}
+
+/// Sent by clients, the server stamps it with the sender before relaying it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatRequest {
+    pub scope: ChatScope,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    // tick of the server when it relayed the message
+    pub sim_time: u64,
+    pub sender: String,
+    pub team: Team,
+    pub scope: ChatScope,
+    pub text: String,
+}
+
+impl fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope {
+            ChatScope::All => write!(f, "{}: {}", self.sender, self.text),
+            ChatScope::Team => write!(f, "[team] {}: {}", self.sender, self.text),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChatError {
+    Empty,
+    TooLong,
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ChatError::Empty => write!(f, "message is empty"),
+            ChatError::TooLong => {
+                write!(f, "message is longer than {} characters", MAX_CHAT_LENGTH)
+            }
+        }
+    }
+}
+
+/// Returns `text` as it is relayed: trimmed and without control characters, which could
+/// mess with the terminals and logs of other players.
+pub fn sanitize(text: &str) -> Result<String, ChatError> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Err(ChatError::Empty);
+    }
+
+    if text.chars().count() > MAX_CHAT_LENGTH {
+        return Err(ChatError::TooLong);
+    }
+
+    Ok(text.to_string())
+}
+
+/// The received messages, oldest first, with a scroll position for rendering them.
+#[derive(Debug)]
+pub struct ChatLog {
+    messages: VecDeque<ChatMessage>,
+    capacity: usize,
+    // messages hidden below the visible ones, 0 shows the newest
+    scroll: usize,
+}
+
+impl Default for ChatLog {
+    fn default() -> ChatLog {
+        ChatLog::with_capacity(DEFAULT_SCROLLBACK)
+    }
+}
+
+impl ChatLog {
+    pub fn with_capacity(capacity: usize) -> ChatLog {
+        ChatLog {
+            messages: VecDeque::with_capacity(capacity),
+            capacity,
+            scroll: 0,
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(message);
+
+        if self.scroll > 0 {
+            // keeps the visible messages in place while scrolled up, until they are dropped
+            self.scroll = (self.scroll + 1).min(self.messages.len() - 1);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.messages.len().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// Up to `count` messages at the current scroll position, oldest first.
+    pub fn visible(&self, count: usize) -> impl Iterator<Item = &ChatMessage> {
+        let end = self.messages.len() - self.scroll;
+        let start = end.saturating_sub(count);
+
+        self.messages.range(start..end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(text: &str) -> ChatMessage {
+        ChatMessage {
+            sim_time: 0,
+            sender: "alice".to_string(),
+            team: Team(0),
+            scope: ChatScope::All,
+            text: text.to_string(),
+        }
+    }
+
+    fn visible_texts(log: &ChatLog, count: usize) -> Vec<&str> {
+        log.visible(count)
+            .map(|message| message.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn messages_are_sanitized() {
+        assert_eq!(sanitize("  hi there \n"), Ok("hi there".to_string()));
+        assert_eq!(sanitize("a\u{1b}[2Jb"), Ok("a[2Jb".to_string()));
+        assert_eq!(sanitize(" \t "), Err(ChatError::Empty));
+        assert_eq!(
+            sanitize(&"x".repeat(MAX_CHAT_LENGTH)).map(|text| text.len()),
+            Ok(MAX_CHAT_LENGTH)
+        );
+        assert_eq!(
+            sanitize(&"x".repeat(MAX_CHAT_LENGTH + 1)),
+            Err(ChatError::TooLong)
+        );
+    }
+
+    #[test]
+    fn log_drops_oldest_and_scrolls() {
+        let mut log = ChatLog::with_capacity(4);
+
+        for text in &["a", "b", "c", "d", "e"] {
+            log.push(message(text));
+        }
+
+        assert_eq!(log.len(), 4);
+        assert_eq!(visible_texts(&log, 2), vec!["d", "e"]);
+
+        log.scroll_up(1);
+        assert_eq!(visible_texts(&log, 2), vec!["c", "d"]);
+
+        // can not scroll past the oldest message
+        log.scroll_up(10);
+        assert_eq!(visible_texts(&log, 2), vec!["b"]);
+
+        log.scroll_down(10);
+        assert_eq!(visible_texts(&log, 10), vec!["b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn scrolled_log_stays_in_place() {
+        let mut log = ChatLog::with_capacity(10);
+
+        for text in &["a", "b", "c"] {
+            log.push(message(text));
+        }
+
+        log.scroll_up(1);
+        log.push(message("d"));
+
+        assert_eq!(visible_texts(&log, 2), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn scrolled_full_log_stays_in_place() {
+        let mut log = ChatLog::with_capacity(4);
+
+        for text in &["a", "b", "c", "d"] {
+            log.push(message(text));
+        }
+
+        log.scroll_up(1);
+        assert_eq!(visible_texts(&log, 2), vec!["b", "c"]);
+
+        log.push(message("e"));
+        assert_eq!(visible_texts(&log, 2), vec!["b", "c"]);
+
+        // the visible messages are dropped, what remains is the oldest
+        log.push(message("f"));
+        assert_eq!(visible_texts(&log, 2), vec!["c"]);
+        log.push(message("g"));
+        assert_eq!(visible_texts(&log, 2), vec!["d"]);
+
+        log.scroll_down(10);
+        assert_eq!(visible_texts(&log, 10), vec!["d", "e", "f", "g"]);
+    }
+}
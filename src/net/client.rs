@@ -1,48 +1,117 @@
-use std::intrinsics::type_id;
-use std::net::Ipv4Addr;
-use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Cursor};
+use std::net::SocketAddrV4;
+use std::path::Path;
 
-use bincode::deserialize_from;
+use ecs::{World, Entity};
 
-use ecs::{World, Entity, ModifyData};
+use enet::{self, Event, Packet, PacketMode, PeerState};
 
-use enet::{self, Event};
-
-use super::{ENET, PORT, UPDATE_CHANNEL_ID};
-use crate::components::*;
+use super::capture::{CaptureWriter, Direction};
+use super::chat::{self, ChatError, ChatLog, ChatMessage, ChatRequest, ChatScope};
+use super::demo::{DemoPlayback, PlaybackCommand};
+use super::protocol::{
+    self, Checksum, DecodeError, DisconnectReason, Hello, JoinRejection, MessageType,
+    MessageVisitor, NetEvent, Resync, Welcome,
+};
+use super::replication::{self, EntityMapping};
+use super::{ENET, EVENT_CHANNEL_ID, INPUT_CHANNEL_ID, PROTOCOL_VERSION, UPDATE_CHANNEL_ID};
+use crate::components::Intents;
+use crate::game::events::{
+    CollisionEnded, CollisionStarted, InteractionDone, RemoteEventReceiver,
+};
 use crate::systems::LevelSystems;
 
-pub struct Client {
-    enet_host: enet::Host<()>,
-    entity_mapping: HashMap<u64, Entity>
+enum Connection {
+    Live(enet::Host<()>),
+    Demo(DemoPlayback),
 }
 
-const POSITION_ID: u64 = type_id::<Position>();
-const VELOCITY_ID: u64 = type_id::<Velocity>();
-const JUMP_ID: u64 = type_id::<Jump>();
-const GRAVITY_ID: u64 = type_id::<Gravity>();
-const FACING_ID: u64 = type_id::<Facing>();
-const INTENTS_ID: u64 = type_id::<Intents>();
-const INTERACTOR_ID: u64 = type_id::<Interactor>();
-const CAMERA_ID: u64 = type_id::<Camera>();
-const MOVEMENT_ID: u64 = type_id::<Movement>();
-const SPRITE_ID: u64 = type_id::<Sprite>();
-
-macro_rules! deserialize_component {
-    ($component:ident, $component_name:ident, $en:ident, $e_id:ident, $sim_ts:ident, $world:ident, $reader:ident, $do_print:expr) => (
-        {
-            let v: $component = deserialize_from(&mut $reader).unwrap();
-            if $do_print {
-                println!(
-                    "received '{}' update for entity {} (at {}): {:#?}",
-                    stringify!($component), $e_id, $sim_ts, v
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    // connected and said hello, but the world has not arrived yet
+    WaitingForSnapshot,
+    InGame,
+    Disconnected(DisconnectReason),
+}
+
+enum Received {
+    Connected,
+    Disconnected(DisconnectReason),
+    Packet(u8, Vec<u8>),
+}
+
+fn receive_packets(
+    enet_host: &mut enet::Host<()>,
+    capture: &mut Option<CaptureWriter>,
+) -> Vec<Received> {
+    let mut received = Vec::new();
+
+    let mut handle_event = |event: Event<'_, ()>| match event {
+        Event::Connect(_) => received.push(Received::Connected),
+        Event::Disconnect(_, code) => {
+            received.push(Received::Disconnected(DisconnectReason::from_code(code)))
+        }
+        Event::Receive {
+            ref sender,
+            channel_id,
+            ref packet,
+        } => {
+            if let Some(capture) = capture {
+                capture.record(
+                    Direction::Received,
+                    &sender.address(),
+                    channel_id,
+                    packet.data(),
                 );
             }
-            $world.modify_entity($en, move |e: ModifyData<LevelComponents>, data: &mut LevelComponents| {
-                data.$component_name.insert(&e, v);
-            });
+
+            received.push(Received::Packet(channel_id, packet.data().to_vec()));
         }
-    );
+    };
+
+    if let Some(event) = enet_host.service(0).unwrap() {
+        handle_event(event);
+    }
+
+    while let Some(event) = enet_host.check_events().unwrap() {
+        handle_event(event);
+    }
+
+    received
+}
+
+pub struct Client {
+    connection: Connection,
+    entity_mapping: EntityMapping,
+    dropped_packets: u64,
+    capture: Option<CaptureWriter>,
+    // checksum of the server's world, compared once we have received all updates up to it
+    pending_checksum: Option<Checksum>,
+    resync_requested: bool,
+    server_addr: Option<SocketAddrV4>,
+    // identifies us to the server when reconnecting, see `protocol::Hello`
+    session_token: Option<u64>,
+    // server-side id of the entity we control
+    player: Option<u64>,
+    status: ConnectionStatus,
+    // whether we received any updates since connecting
+    received_snapshot: bool,
+    // sent to the server when joining
+    name: String,
+    password: Option<String>,
+    join_rejection: Option<JoinRejection>,
+    // the last intents sent, see `send_intents()`
+    sent_intents: Option<Intents>,
+    chat_log: ChatLog,
+}
+
+fn write_state_dump(path: &str, lines: &[String]) {
+    match fs::write(path, lines.join("\n")) {
+        Ok(()) => println!("wrote state dump to {}", path),
+        Err(err) => println!("could not write state dump to {}: {}", path, err),
+    }
 }
 
 impl Client {
@@ -58,79 +127,515 @@ impl Client {
             .expect("could not create host");
 
         Client {
-            enet_host,
-            entity_mapping: HashMap::new()
-        }
-    }
-
-    fn deserialize_updates(&mut self, data: &[u8], world: &mut World<LevelSystems>) {
-        let mut reader = std::io::Cursor::new(data);
-
-        while reader.get_ref().len() - (reader.position() as usize) > 0 {
-            let tag: u64 = deserialize_from(&mut reader).unwrap();
-
-            loop {
-                let e_id: u64 = deserialize_from(&mut reader).unwrap();
-                let sim_ts: u64 = deserialize_from(&mut reader).unwrap();
-
-                let en = *self.entity_mapping.entry(e_id).or_insert_with(|| {
-                    let e = world.create_entity(());
-                    println!("e_id: {}, e: {:?}", e_id, e);
-                    e
-                });
-
-                match tag {
-                    POSITION_ID => deserialize_component!(Position, position, en, e_id, sim_ts, world, reader, false),
-                    VELOCITY_ID => deserialize_component!(Velocity, velocity, en, e_id, sim_ts, world, reader, false),
-                    JUMP_ID => deserialize_component!(Jump, jump, en, e_id, sim_ts, world, reader, true),
-                    GRAVITY_ID => deserialize_component!(Gravity, gravity, en, e_id, sim_ts, world, reader, true),
-                    FACING_ID => deserialize_component!(Facing, facing, en, e_id, sim_ts, world, reader, true),
-                    INTENTS_ID => deserialize_component!(Intents, intents, en, e_id, sim_ts, world, reader, false),
-                    INTERACTOR_ID => deserialize_component!(Interactor, interactor, en, e_id, sim_ts, world, reader, true),
-                    CAMERA_ID => deserialize_component!(Camera, camera, en, e_id, sim_ts, world, reader, true),
-                    MOVEMENT_ID => deserialize_component!(Movement, movement, en, e_id, sim_ts, world, reader, false),
-                    SPRITE_ID => deserialize_component!(Sprite, sprite, en, e_id, sim_ts, world, reader, false),
-                    _ => panic!("unexpected type_id: {}", tag),
-                }
+            connection: Connection::Live(enet_host),
+            entity_mapping: EntityMapping::default(),
+            dropped_packets: 0,
+            capture: None,
+            pending_checksum: None,
+            resync_requested: false,
+            server_addr: None,
+            session_token: None,
+            player: None,
+            status: ConnectionStatus::Disconnected(DisconnectReason::Unknown),
+            received_snapshot: false,
+            name: "player".to_string(),
+            password: None,
+            join_rejection: None,
+            sent_intents: None,
+            chat_log: ChatLog::default(),
+        }
+    }
+
+    /// Creates a client which plays back the demo at `path` instead of connecting to a server.
+    pub fn from_demo(path: &Path) -> io::Result<Client> {
+        Ok(Client {
+            connection: Connection::Demo(DemoPlayback::open(path)?),
+            entity_mapping: EntityMapping::default(),
+            dropped_packets: 0,
+            capture: None,
+            pending_checksum: None,
+            resync_requested: false,
+            server_addr: None,
+            session_token: None,
+            player: None,
+            status: ConnectionStatus::InGame,
+            received_snapshot: true,
+            name: "player".to_string(),
+            password: None,
+            join_rejection: None,
+            sent_intents: None,
+            chat_log: ChatLog::default(),
+        })
+    }
+
+    /// Starts recording all sent and received packets to `path`, see `net::capture`.
+    pub fn enable_capture(&mut self, path: &Path) -> io::Result<()> {
+        self.capture = Some(CaptureWriter::create(path)?);
+        Ok(())
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+    }
+
+    /// Sends the intents of our player to the server, if they changed since the last call.
+    pub fn send_intents(&mut self, intents: &Intents) {
+        if self.status != ConnectionStatus::InGame || self.sent_intents.as_ref() == Some(intents) {
+            return;
+        }
+
+        let message = protocol::message(MessageType::Intents, intents);
+        self.send_to_server(&message, INPUT_CHANNEL_ID);
+        self.sent_intents = Some(intents.clone());
+    }
 
-                let more: bool = deserialize_from(&mut reader).unwrap();
-                if !more {
-                    break;
+    /// Sends a chat message to everyone, or to our team only.
+    pub fn send_chat(&mut self, scope: ChatScope, text: &str) -> Result<(), ChatError> {
+        let text = chat::sanitize(text)?;
+
+        let message = protocol::message(MessageType::ChatSend, &ChatRequest { scope, text });
+        self.send_to_server(&message, EVENT_CHANNEL_ID);
+
+        Ok(())
+    }
+
+    /// The chat messages received so far.
+    pub fn chat_log(&self) -> &ChatLog {
+        &self.chat_log
+    }
+
+    pub fn chat_log_mut(&mut self) -> &mut ChatLog {
+        &mut self.chat_log
+    }
+
+    /// Why the server did not let us join, if it disconnected us with
+    /// `DisconnectReason::JoinRejected`.
+    pub fn join_rejection(&self) -> Option<JoinRejection> {
+        self.join_rejection
+    }
+
+    /// Sets the name we join with, and the password of the server if it requires one.
+    pub fn set_credentials(&mut self, name: String, password: Option<String>) {
+        self.name = name;
+        self.password = password;
+    }
+
+    /// Number of received packets which were dropped because they could not be decoded.
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped_packets
+    }
+
+    fn map_entity(&mut self, e_id: u64, world: &mut World<LevelSystems>) -> Entity {
+        self.entity_mapping.get_or_create(e_id, world)
+    }
+
+    fn deserialize_updates(
+        &mut self,
+        data: &[u8],
+        world: &mut World<LevelSystems>,
+    ) -> Result<(), DecodeError> {
+        let updates = replication::decode_updates(data)?;
+
+        // the pending checksum can only be compared until newer updates are applied
+        let newest = updates.iter().map(|update| update.sim_time).max();
+        if let (Some(checksum), Some(newest)) = (self.pending_checksum, newest) {
+            if newest > checksum.sim_time {
+                self.check_desync(world);
+            }
+        }
+
+        replication::apply_updates(updates, &mut self.entity_mapping, world);
+        self.received_snapshot = true;
+
+        Ok(())
+    }
+
+    fn deserialize_events(
+        &mut self,
+        reader: &mut Cursor<&[u8]>,
+        world: &mut World<LevelSystems>,
+    ) -> Result<(), DecodeError> {
+        let events: Vec<(u64, NetEvent)> = protocol::read(reader)?;
+
+        for (_sim_time, event) in events {
+            match event {
+                NetEvent::CollisionStarted { collider, collided } => {
+                    let event = CollisionStarted {
+                        collider: self.map_entity(collider, world),
+                        collided: self.map_entity(collided, world),
+                    };
+                    world.receive_remote_event(event);
+                }
+                NetEvent::CollisionEnded { collider, collided } => {
+                    let event = CollisionEnded {
+                        collider: self.map_entity(collider, world),
+                        collided: self.map_entity(collided, world),
+                    };
+                    world.receive_remote_event(event);
+                }
+                NetEvent::InteractionDone {
+                    interactor,
+                    interacted,
+                    interaction,
+                } => {
+                    let event = InteractionDone {
+                        interactor: self.map_entity(interactor, world),
+                        interacted: self.map_entity(interacted, world),
+                        interaction,
+                    };
+                    world.receive_remote_event(event);
                 }
             }
         }
+
+        Ok(())
     }
 
     pub fn maintain(&mut self, world: &mut World<LevelSystems>) {
-        let data = {
-            let maybe_event = self.enet_host.service(0).unwrap();
-            if let Some(event) = maybe_event {
-                if let Event::Receive {
-                    channel_id: UPDATE_CHANNEL_ID,
-                    ref packet,
-                    ..
-                } = event
-                {
-                    let data = packet.data().to_vec();
-                    assert!(!data.is_empty());
-                    Some(data)
-                } else {
-                    dbg!(event);
-                    None
+        let received = match self.connection {
+            Connection::Live(ref mut enet_host) => receive_packets(enet_host, &mut self.capture),
+            Connection::Demo(ref mut demo) => demo
+                .advance(world.services.delta_time_s)
+                .into_iter()
+                .map(|(channel_id, data)| Received::Packet(channel_id, data))
+                .collect(),
+        };
+
+        self.handle_received(received, world);
+    }
+
+    fn handle_received(&mut self, received: Vec<Received>, world: &mut World<LevelSystems>) {
+        for received in received {
+            match received {
+                Received::Connected => {
+                    let hello = Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        session_token: self.session_token,
+                        name: self.name.clone(),
+                        password: self.password.clone(),
+                    };
+                    let hello = protocol::message(MessageType::Hello, &hello);
+                    self.send_to_server(&hello, EVENT_CHANNEL_ID);
+                    self.status = ConnectionStatus::WaitingForSnapshot;
+                }
+                Received::Disconnected(reason) => {
+                    println!("disconnected from server: {:?}", reason);
+
+                    // we receive a fresh snapshot when reconnecting
+                    self.entity_mapping.remove_all(world);
+                    self.pending_checksum = None;
+                    self.resync_requested = false;
+                    self.status = ConnectionStatus::Disconnected(reason);
                 }
-            } else {
-                None
+                Received::Packet(channel_id, data) => self.handle_packet(channel_id, &data, world),
             }
+        }
+
+        // the snapshot might arrive before the welcome, so we wait for both
+        if self.status == ConnectionStatus::WaitingForSnapshot
+            && self.player.is_some()
+            && self.received_snapshot
+        {
+            self.status = ConnectionStatus::InGame;
+        }
+
+        self.check_desync(world);
+    }
+
+    fn handle_packet(&mut self, channel_id: u8, data: &[u8], world: &mut World<LevelSystems>) {
+        let res = match channel_id {
+            UPDATE_CHANNEL_ID => self.deserialize_updates(data, world),
+            EVENT_CHANNEL_ID => {
+                protocol::parse_and_visit_message(data, ReceivedMessage { client: self, world })
+            }
+            _ => Err(DecodeError::UnknownChannel(channel_id)),
         };
 
-        if let Some(data) = data {
-            self.deserialize_updates(&data, world);
+        if let Err(err) = res {
+            self.dropped_packets += 1;
+            println!(
+                "dropped malformed packet on channel {} ({} dropped so far): {}",
+                channel_id, self.dropped_packets, err
+            );
         }
     }
 
-    pub fn start_connect(&mut self, dest_addr: Ipv4Addr) {
-        self.enet_host
-            .connect(&enet::Address::new(dest_addr, PORT), 10, 0)
+    fn check_desync(&mut self, world: &World<LevelSystems>) {
+        let checksum = match self.pending_checksum {
+            Some(checksum) => checksum,
+            None => return,
+        };
+
+        let sim_time = world.services.simulation_time;
+
+        // the updates for this tick have not arrived yet
+        if sim_time < checksum.sim_time {
+            return;
+        }
+
+        self.pending_checksum = None;
+
+        // newer updates were already applied, so our state can't be compared anymore. this only
+        // happens if they arrived before the checksum, see `deserialize_updates`.
+        if sim_time > checksum.sim_time || self.resync_requested {
+            return;
+        }
+
+        let server_ids = self.entity_mapping.server_ids();
+        let id_of = |e: Entity| server_ids.get(&e).cloned();
+
+        if replication::world_checksum(world, id_of) == checksum.checksum {
+            return;
+        }
+
+        println!("desync detected at tick {}, requesting resync", sim_time);
+
+        write_state_dump(
+            &format!("desync_{}_client.txt", sim_time),
+            &replication::describe_world(world, id_of),
+        );
+
+        self.resync_requested = true;
+        let request = protocol::message(MessageType::ResyncRequest, &sim_time);
+        self.send_to_server(&request, EVENT_CHANNEL_ID);
+    }
+
+    fn handle_resync(&mut self, resync: Resync) -> Result<(), DecodeError> {
+        self.resync_requested = false;
+
+        let server_state = match resync.server_state {
+            Some(server_state) => server_state,
+            None => {
+                println!("server no longer had its state of tick {}", resync.sim_time);
+                return Ok(());
+            }
+        };
+
+        let mut updates = replication::decode_updates(&server_state)?;
+        updates.retain(|update| update.component.is_checksummed());
+        updates.sort_by_key(|update| update.entity_id);
+
+        let lines: Vec<String> = updates
+            .iter()
+            .map(|update| replication::describe_component(update.entity_id, &update.component))
+            .collect();
+
+        write_state_dump(&format!("desync_{}_server.txt", resync.sim_time), &lines);
+
+        Ok(())
+    }
+
+    fn send_to_server(&mut self, data: &[u8], channel_id: u8) {
+        let enet_host = match self.connection {
+            Connection::Live(ref mut enet_host) => enet_host,
+            Connection::Demo(_) => return,
+        };
+
+        for mut peer in enet_host.peers() {
+            if peer.state() != PeerState::Connected {
+                continue;
+            }
+
+            if let Some(ref mut capture) = self.capture {
+                capture.record(Direction::Sent, &peer.address(), channel_id, data);
+            }
+
+            peer.send_packet(
+                Packet::new(data, PacketMode::ReliableSequenced).unwrap(),
+                channel_id,
+            )
+            .unwrap();
+        }
+    }
+
+    /// Controls demo playback, does nothing if this client is connected to a server.
+    pub fn control_playback(&mut self, command: PlaybackCommand, world: &mut World<LevelSystems>) {
+        let demo = match self.connection {
+            Connection::Demo(ref mut demo) => demo,
+            Connection::Live(_) => return,
+        };
+
+        if demo.control(command) {
+            self.entity_mapping.remove_all(world);
+            world.services.simulation_time = 0;
+        }
+
+        println!(
+            "demo: tick {} of {}-{}, speed {}{}",
+            demo.position(),
+            demo.first_tick(),
+            demo.last_tick(),
+            demo.speed(),
+            if demo.is_paused() { ", paused" } else { "" }
+        );
+    }
+
+    /// Connects to the server we were last connected to, reattaching to our previous player.
+    pub fn reconnect(&mut self) {
+        if let Some(server_addr) = self.server_addr {
+            self.start_connect(server_addr);
+        }
+    }
+
+    pub fn start_connect(&mut self, dest_addr: SocketAddrV4) {
+        self.server_addr = Some(dest_addr);
+        self.player = None;
+        self.received_snapshot = false;
+        self.join_rejection = None;
+        self.sent_intents = None;
+        self.status = ConnectionStatus::Connecting;
+
+        match self.connection {
+            Connection::Live(ref mut enet_host) => {
+                enet_host
+                    .connect(&enet::Address::new(*dest_addr.ip(), dest_addr.port()), 10, 0)
+                    .unwrap();
+            }
+            Connection::Demo(_) => panic!("cannot connect a client playing back a demo"),
+        }
+    }
+
+    /// Gives up on the current connection, e.g. because connecting took too long.
+    pub fn abort_connect(&mut self, world: &mut World<LevelSystems>) {
+        if let Connection::Live(ref mut enet_host) = self.connection {
+            for peer in enet_host.peers() {
+                if peer.state() != PeerState::Disconnected {
+                    peer.disconnect_now(DisconnectReason::TimedOut.code());
+                }
+            }
+        }
+
+        self.entity_mapping.remove_all(world);
+        self.status = ConnectionStatus::Disconnected(DisconnectReason::TimedOut);
+    }
+}
+
+struct ReceivedMessage<'a> {
+    client: &'a mut Client,
+    world: &'a mut World<LevelSystems>,
+}
+
+impl MessageVisitor for ReceivedMessage<'_> {
+    fn visit_replicated_events(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        self.client.deserialize_events(data, self.world)
+    }
+
+    fn visit_checksum(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        self.client.pending_checksum = Some(protocol::read(data)?);
+        Ok(())
+    }
+
+    fn visit_resync(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let resync = protocol::read(data)?;
+        self.client.handle_resync(resync)
+    }
+
+    fn visit_welcome(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let welcome: Welcome = protocol::read(data)?;
+
+        println!("joined server, controlling entity {}", welcome.player);
+
+        self.client.session_token = Some(welcome.session_token);
+        self.client.player = Some(welcome.player);
+
+        Ok(())
+    }
+
+    fn visit_entities_removed(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let removed: Vec<u64> = protocol::read(data)?;
+
+        for e_id in removed {
+            self.client.entity_mapping.remove(e_id, self.world);
+        }
+
+        self.world.flush_queue();
+
+        Ok(())
+    }
+
+    fn visit_join_rejected(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let rejection: JoinRejection = protocol::read(data)?;
+
+        println!("server rejected us: {}", rejection);
+
+        // the server disconnects us right after, which reports the rejection
+        self.client.join_rejection = Some(rejection);
+
+        Ok(())
+    }
+
+    fn visit_server_message(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let text: String = protocol::read(data)?;
+
+        println!("server: {}", text);
+
+        Ok(())
+    }
+
+    fn visit_chat(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let message: ChatMessage = protocol::read(data)?;
+
+        println!("{}", message);
+        self.client.chat_log.push(message);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::BuildData;
+
+    use super::*;
+    use crate::components::{LevelComponents, Position};
+    use crate::net::replication::ComponentUpdates;
+
+    // what the server sends at the end of tick `sim_time`, with the checksum off by `error`
+    fn server_tick(world: &mut World<LevelSystems>, sim_time: u64, error: u64) -> Vec<Received> {
+        world.services.simulation_time = sim_time;
+
+        let updates = ComponentUpdates::new_from_world(world, None)
+            .serialize_updates()
             .unwrap();
+        let checksum = Checksum {
+            sim_time,
+            checksum: replication::world_checksum(world, |e| Some(e.id())) ^ error,
+        };
+
+        vec![
+            Received::Packet(UPDATE_CHANNEL_ID, updates),
+            Received::Packet(EVENT_CHANNEL_ID, protocol::message(MessageType::Checksum, &checksum)),
+        ]
+    }
+
+    // receives two ticks at once, the first with a checksum off by `error`, and returns
+    // whether the client requested a resync
+    fn receive_two_ticks(error: u64) -> bool {
+        let mut server_world = World::<LevelSystems>::new();
+        let e = server_world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, Position { x: 0.0, y: 0.0 });
+            },
+        );
+
+        let mut received = server_tick(&mut server_world, 10, error);
+        server_world.with_entity_data(&e, |en, comps| {
+            comps.position.borrow(&en).unwrap().x = 5.0;
+        });
+        received.extend(server_tick(&mut server_world, 11, 0));
+
+        let mut client = Client::new();
+        let mut client_world = World::<LevelSystems>::new();
+        client.handle_received(received, &mut client_world);
+
+        let _ = fs::remove_file("desync_10_client.txt");
+
+        client.resync_requested
+    }
+
+    #[test]
+    fn checksums_are_compared_before_newer_updates_apply() {
+        assert!(!receive_two_ticks(0));
+        assert!(receive_two_ticks(1));
     }
 }
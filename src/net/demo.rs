@@ -0,0 +1,307 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use ecs::World;
+
+use super::replication::ComponentUpdates;
+use super::{EVENT_CHANNEL_ID, UPDATE_CHANNEL_ID};
+use crate::systems::LevelSystems;
+
+const DEMO_VERSION: u32 = 1;
+
+// how far `PlaybackCommand::SeekBackward` and `SeekForward` jump, in simulation ticks
+const SEEK_TICKS: u64 = 500;
+
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 8.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DemoFrame {
+    sim_time: u64,
+    channel_id: u8,
+    data: Vec<u8>,
+}
+
+/// Records the update and event stream the server sends, as seen by a peer without a player.
+/// The first recorded frame contains the initial snapshot of the world.
+pub struct DemoRecorder {
+    out: BufWriter<File>,
+    updates: Option<ComponentUpdates>,
+}
+
+impl DemoRecorder {
+    pub fn create(path: &Path) -> io::Result<DemoRecorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(&mut out, &DEMO_VERSION)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        Ok(DemoRecorder { out, updates: None })
+    }
+
+    pub fn record(&mut self, world: &mut World<LevelSystems>, event_data: Option<&Vec<u8>>) {
+        let updates = self
+            .updates
+            .get_or_insert_with(|| ComponentUpdates::new_from_world(world, None));
+        updates.update_from_changes(world, None);
+
+        let sim_time = world.services.simulation_time;
+
+        if let Some(update_data) = updates.serialize_updates() {
+            self.write_frame(sim_time, UPDATE_CHANNEL_ID, update_data);
+        }
+
+        if let Some(event_data) = event_data {
+            self.write_frame(sim_time, EVENT_CHANNEL_ID, event_data.clone());
+        }
+    }
+
+    fn write_frame(&mut self, sim_time: u64, channel_id: u8, data: Vec<u8>) {
+        let frame = DemoFrame {
+            sim_time,
+            channel_id,
+            data,
+        };
+
+        if let Err(err) = bincode::serialize_into(&mut self.out, &frame) {
+            println!("could not write demo frame: {}", err);
+        }
+    }
+}
+
+impl Drop for DemoRecorder {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackCommand {
+    TogglePause,
+    Slower,
+    Faster,
+    SeekBackward,
+    SeekForward,
+}
+
+/// Plays back a demo written by `DemoRecorder`, see `net::Client::from_demo`.
+pub struct DemoPlayback {
+    frames: Vec<DemoFrame>,
+    next_frame: usize,
+    // current playback position, in simulation ticks
+    position: f64,
+    speed: f64,
+    paused: bool,
+    last_advance: Instant,
+}
+
+impl DemoPlayback {
+    pub fn open(path: &Path) -> io::Result<DemoPlayback> {
+        let to_io_error = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let version: u32 = bincode::deserialize_from(&mut reader).map_err(to_io_error)?;
+        if version != DEMO_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported demo version: {}", version),
+            ));
+        }
+
+        let mut frames = Vec::new();
+        while !reader.fill_buf()?.is_empty() {
+            frames.push(bincode::deserialize_from(&mut reader).map_err(to_io_error)?);
+        }
+
+        let position = frames.first().map_or(0, |frame: &DemoFrame| frame.sim_time) as f64;
+
+        Ok(DemoPlayback {
+            frames,
+            next_frame: 0,
+            position,
+            speed: 1.0,
+            paused: false,
+            last_advance: Instant::now(),
+        })
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position as u64
+    }
+
+    pub fn first_tick(&self) -> u64 {
+        self.frames.first().map_or(0, |frame| frame.sim_time)
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.frames.last().map_or(0, |frame| frame.sim_time)
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advances the playback position by the real time passed since the last call,
+    /// and returns the packets which became due as `(channel_id, data)`.
+    pub(super) fn advance(&mut self, tick_duration_s: f32) -> Vec<(u8, Vec<u8>)> {
+        let elapsed = self.last_advance.elapsed();
+        self.last_advance = Instant::now();
+
+        if !self.paused {
+            let elapsed_s = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+            self.position += elapsed_s / f64::from(tick_duration_s) * self.speed;
+        }
+
+        let mut due = Vec::new();
+
+        while let Some(frame) = self.frames.get(self.next_frame) {
+            if frame.sim_time as f64 > self.position {
+                break;
+            }
+
+            due.push((frame.channel_id, frame.data.clone()));
+            self.next_frame += 1;
+        }
+
+        due
+    }
+
+    /// Applies `command`. Returns `true` if playback restarted from the first frame,
+    /// in which case the caller has to throw away everything applied so far.
+    pub(super) fn control(&mut self, command: PlaybackCommand) -> bool {
+        let first_tick = self.first_tick() as f64;
+        let last_tick = self.last_tick() as f64;
+
+        match command {
+            PlaybackCommand::TogglePause => self.paused = !self.paused,
+            PlaybackCommand::Slower => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+            PlaybackCommand::Faster => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            PlaybackCommand::SeekForward => {
+                self.position = (self.position + SEEK_TICKS as f64).min(last_tick)
+            }
+            PlaybackCommand::SeekBackward => {
+                // updates are deltas, so going back means replaying from the initial snapshot
+                self.position = (self.position - SEEK_TICKS as f64).max(first_tick);
+                self.next_frame = 0;
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn playback(ticks: &[u64]) -> DemoPlayback {
+        let frames = ticks
+            .iter()
+            .map(|&sim_time| DemoFrame {
+                sim_time,
+                channel_id: UPDATE_CHANNEL_ID,
+                data: sim_time.to_le_bytes().to_vec(),
+            })
+            .collect::<Vec<_>>();
+
+        DemoPlayback {
+            position: ticks[0] as f64,
+            frames,
+            next_frame: 0,
+            speed: 1.0,
+            paused: false,
+            last_advance: Instant::now(),
+        }
+    }
+
+    // advances as if `seconds` of real time had passed since the last call
+    fn advance_by(playback: &mut DemoPlayback, seconds: f32) -> Vec<u64> {
+        playback.last_advance = Instant::now() - Duration::from_secs_f32(seconds);
+
+        playback
+            .advance(0.01)
+            .into_iter()
+            .map(|(_, data)| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&data);
+                u64::from_le_bytes(bytes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_become_due_with_time() {
+        let mut playback = playback(&[10, 12, 14, 200]);
+
+        assert_eq!(advance_by(&mut playback, 0.0), vec![10]);
+        assert_eq!(advance_by(&mut playback, 0.05), vec![12, 14]);
+        assert!(playback.position() >= 15 && playback.position() < 200);
+        assert_eq!(advance_by(&mut playback, 0.05), vec![]);
+    }
+
+    #[test]
+    fn paused_playback_stands_still() {
+        let mut playback = playback(&[10, 12, 14]);
+        assert_eq!(advance_by(&mut playback, 0.0), vec![10]);
+
+        assert!(!playback.control(PlaybackCommand::TogglePause));
+        assert!(playback.is_paused());
+        assert_eq!(advance_by(&mut playback, 10.0), vec![]);
+        assert_eq!(playback.position(), 10);
+
+        playback.control(PlaybackCommand::TogglePause);
+        assert!(!playback.is_paused());
+        assert_eq!(advance_by(&mut playback, 0.05), vec![12, 14]);
+    }
+
+    #[test]
+    fn seeking_stays_within_the_demo() {
+        let mut playback = playback(&[10, 100, 600, 700]);
+        playback.paused = true;
+        assert_eq!(advance_by(&mut playback, 0.0), vec![10]);
+
+        assert!(!playback.control(PlaybackCommand::SeekForward));
+        assert_eq!(playback.position(), 10 + SEEK_TICKS);
+        assert_eq!(advance_by(&mut playback, 0.0), vec![100]);
+
+        assert!(!playback.control(PlaybackCommand::SeekForward));
+        assert_eq!(playback.position(), 700);
+        assert_eq!(advance_by(&mut playback, 0.0), vec![600, 700]);
+
+        // seeking backward replays every frame up to the new position
+        assert!(playback.control(PlaybackCommand::SeekBackward));
+        assert_eq!(playback.position(), 700 - SEEK_TICKS);
+        assert_eq!(advance_by(&mut playback, 0.0), vec![10, 100]);
+
+        assert!(playback.control(PlaybackCommand::SeekBackward));
+        assert_eq!(playback.position(), 10);
+        assert_eq!(advance_by(&mut playback, 0.0), vec![10]);
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        let mut playback = playback(&[10]);
+
+        for _ in 0..10 {
+            playback.control(PlaybackCommand::Faster);
+        }
+        assert_eq!(playback.speed(), MAX_SPEED);
+
+        for _ in 0..20 {
+            playback.control(PlaybackCommand::Slower);
+        }
+        assert_eq!(playback.speed(), MIN_SPEED);
+
+        playback.control(PlaybackCommand::Faster);
+        assert_eq!(playback.speed(), MIN_SPEED * 2.0);
+    }
+}
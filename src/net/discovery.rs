@@ -0,0 +1,244 @@
+//! Finding servers on the local network. Servers answer queries broadcast to
+//! `DISCOVERY_PORT` with a `ServerInfo`, see `query_lan`.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::{DISCOVERY_PORT, PROTOCOL_VERSION};
+
+// prefixes queries and replies, so unrelated traffic on the port is ignored
+const MAGIC: [u8; 4] = *b"CRFD";
+const MAX_PACKET_SIZE: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub map: String,
+    pub num_players: u32,
+    pub max_peers: u32,
+    pub protocol_version: u32,
+    // the port the server accepts game connections on
+    pub port: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredServer {
+    // address the reply came from
+    pub addr: SocketAddrV4,
+    pub info: ServerInfo,
+}
+
+impl DiscoveredServer {
+    pub fn is_compatible(&self) -> bool {
+        self.info.protocol_version == PROTOCOL_VERSION
+    }
+
+    pub fn game_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(*self.addr.ip(), self.info.port)
+    }
+}
+
+fn encode_reply(info: &ServerInfo) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    bincode::serialize_into(&mut data, info).unwrap();
+    data
+}
+
+fn decode_reply(data: &[u8]) -> Option<ServerInfo> {
+    if data.len() <= MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    bincode::deserialize(&data[MAGIC.len()..]).ok()
+}
+
+// errors caused by ICMP messages from earlier sends, which only concern that send
+fn is_spurious(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => true,
+        _ => false,
+    }
+}
+
+/// Answers discovery queries on behalf of a server.
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+}
+
+impl DiscoveryResponder {
+    pub fn bind(addr: SocketAddrV4) -> io::Result<DiscoveryResponder> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(DiscoveryResponder { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Answers all pending queries with `info`. Does not block.
+    pub fn respond(&self, info: &ServerInfo) -> io::Result<()> {
+        let mut buf = [0; MAX_PACKET_SIZE];
+        let mut reply = None;
+
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Ok(res) => res,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref err) if is_spurious(err) => continue,
+                Err(err) => return Err(err),
+            };
+
+            if buf[..len] != MAGIC {
+                continue;
+            }
+
+            let reply = reply.get_or_insert_with(|| encode_reply(info));
+
+            if let Err(err) = self.socket.send_to(reply, from) {
+                println!("could not answer discovery query from {}: {}", from, err);
+            }
+        }
+    }
+}
+
+/// Sends a discovery query to `target`, which may be a broadcast address, and
+/// collects the replies arriving within `timeout`.
+pub fn query_servers(target: SocketAddrV4, timeout: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&MAGIC, target)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buf = [0; MAX_PACKET_SIZE];
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        socket.set_read_timeout(Some(deadline - now))?;
+
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(ref err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(ref err) if is_spurious(err) => continue,
+            Err(err) => return Err(err),
+        };
+
+        let from = match from {
+            SocketAddr::V4(from) => from,
+            SocketAddr::V6(_) => continue,
+        };
+
+        let info = match decode_reply(&buf[..len]) {
+            Some(info) => info,
+            None => continue,
+        };
+
+        if !servers.iter().any(|server| server.addr == from) {
+            servers.push(DiscoveredServer { addr: from, info });
+        }
+    }
+
+    Ok(servers)
+}
+
+/// Queries all servers on the local network.
+pub fn query_lan(timeout: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    query_servers(
+        SocketAddrV4::new(Ipv4Addr::BROADCAST, DISCOVERY_PORT),
+        timeout,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    fn test_info() -> ServerInfo {
+        ServerInfo {
+            name: "test server".to_string(),
+            map: "start".to_string(),
+            num_players: 3,
+            max_peers: 16,
+            protocol_version: PROTOCOL_VERSION,
+            port: 12345,
+        }
+    }
+
+    #[test]
+    fn query_finds_server_on_loopback() {
+        let responder =
+            DiscoveryResponder::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let responder_addr = match responder.local_addr().unwrap() {
+            SocketAddr::V4(addr) => addr,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let responder_thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    responder.respond(&test_info()).unwrap();
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        let servers = query_servers(responder_addr, Duration::from_millis(300)).unwrap();
+
+        stop.store(true, Ordering::SeqCst);
+        responder_thread.join().unwrap();
+
+        assert_eq!(
+            servers,
+            vec![DiscoveredServer {
+                addr: responder_addr,
+                info: test_info(),
+            }]
+        );
+        assert!(servers[0].is_compatible());
+        assert_eq!(
+            servers[0].game_addr(),
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12345)
+        );
+    }
+
+    #[test]
+    fn query_without_servers_finds_nothing() {
+        // bind and drop a socket, so nobody listens on its port
+        let unused_addr = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let unused_addr = match unused_addr {
+            SocketAddr::V4(addr) => addr,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+
+        let servers = query_servers(unused_addr, Duration::from_millis(100)).unwrap();
+        assert!(servers.is_empty());
+    }
+
+    #[test]
+    fn malformed_replies_are_ignored() {
+        assert_eq!(decode_reply(b"CRFD"), None);
+        assert_eq!(decode_reply(b"XXXXsomething"), None);
+        assert_eq!(decode_reply(&encode_reply(&test_info())), Some(test_info()));
+    }
+}
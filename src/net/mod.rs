@@ -2,18 +2,42 @@ use std::time::Duration;
 
 use enet::Enet;
 
+pub mod capture;
+pub mod chat;
 mod client;
+pub mod demo;
+pub mod discovery;
 pub mod serde_impls;
 mod server;
 mod protocol;
+pub mod replication;
+pub mod validation;
+pub mod admin;
 
-pub use self::client::Client;
-pub use self::server::Server;
+pub use self::client::{Client, ConnectionStatus};
+pub use self::protocol::{DecodeError, DisconnectReason, JoinRejection};
+pub use self::server::{AdminRequest, Server, ServerConfig};
 
 lazy_static! {
     static ref ENET: Enet = Enet::new().unwrap();
 }
 
-const PORT: u16 = 9001;
+pub const PORT: u16 = 9001;
+pub const DISCOVERY_PORT: u16 = 9002;
+pub const ADMIN_PORT: u16 = 9003;
+// servers and clients only talk to each other if their versions match
+pub const PROTOCOL_VERSION: u32 = 11;
 const RESEND_DURATION: Duration = Duration::from_millis(100);
 const UPDATE_CHANNEL_ID: u8 = 1;
+const EVENT_CHANNEL_ID: u8 = 2;
+// intents of the client's player, the only thing the server accepts as input
+const INPUT_CHANNEL_ID: u8 = 3;
+
+/// If set, `Server` and `Client` record all their packets to the capture file at this path.
+pub const CAPTURE_ENV_VAR: &str = "CRUFTY_CAPTURE";
+
+/// If set, `Server` records a demo to the file at this path.
+pub const DEMO_ENV_VAR: &str = "CRUFTY_RECORD_DEMO";
+
+/// Password of the server. The server requires it to join, clients send it when joining.
+pub const PASSWORD_ENV_VAR: &str = "CRUFTY_SERVER_PASSWORD";
@@ -1,10 +1,27 @@
-use std::io::Cursor;
+use std::error::Error;
+use std::fmt;
+use std::io::{Cursor, Write};
 
+use serde::de::DeserializeOwned;
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+use crate::game::Interaction;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum MessageType {
     EntityUpdates,
+    ReplicatedEvents,
+    Checksum,
+    ResyncRequest,
+    Resync,
+    Hello,
+    Welcome,
+    EntitiesRemoved,
+    JoinRejected,
+    Intents,
+    ServerMessage,
+    ChatSend,
+    Chat,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -12,15 +29,279 @@ pub struct MessageHeader {
     message_type: MessageType,
 }
 
+impl MessageHeader {
+    pub fn new(message_type: MessageType) -> MessageHeader {
+        MessageHeader { message_type }
+    }
+
+    pub fn write_into(&self, out: &mut impl Write) {
+        bincode::serialize_into(out, self).unwrap();
+    }
+}
+
+/// Serializes a complete message, consisting of a header and `payload`.
+pub fn message<T: Serialize>(message_type: MessageType, payload: &T) -> Vec<u8> {
+    let mut data = vec![];
+    MessageHeader::new(message_type).write_into(&mut data);
+    bincode::serialize_into(&mut data, payload).unwrap();
+
+    data
+}
+
+/// First message of a client after connecting. Contains the token of its previous session,
+/// if it is reconnecting.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub session_token: Option<u64>,
+    pub name: String,
+    pub password: Option<String>,
+}
+
+// leaves out the password, as hellos end up in captures and logs
+impl fmt::Debug for Hello {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hello")
+            .field("protocol_version", &self.protocol_version)
+            .field("session_token", &self.session_token)
+            .field("name", &self.name)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// Reply to a `Hello` the server does not accept, the server disconnects afterwards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinRejection {
+    IncompatibleVersion,
+    WrongPassword,
+    NameTooShort,
+    NameTooLong,
+    InvalidName,
+    NameTaken,
+}
+
+impl fmt::Display for JoinRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match *self {
+            JoinRejection::IncompatibleVersion => "the server runs an incompatible version",
+            JoinRejection::WrongPassword => "wrong password",
+            JoinRejection::NameTooShort => "name is too short",
+            JoinRejection::NameTooLong => "name is too long",
+            JoinRejection::InvalidName => "name contains invalid characters",
+            JoinRejection::NameTaken => "name is already taken",
+        };
+
+        f.write_str(description)
+    }
+}
+
+/// Reply to `Hello`, followed by a full snapshot of the world.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Welcome {
+    pub session_token: u64,
+    // the server-side id of the entity controlled by the client
+    pub player: u64,
+}
+
+/// Checksum of the replicated world state at `sim_time`, see `replication::world_checksum()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub sim_time: u64,
+    pub checksum: u64,
+}
+
+/// Sent by the server in response to a resync request. `server_state` contains the server's
+/// world at `sim_time` as entity updates, if the server still had it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Resync {
+    pub sim_time: u64,
+    pub server_state: Option<Vec<u8>>,
+}
+
+/// Wire representation of a `game::events::ReplicatedEvent`.
+/// Entities are identified by their server-side `Entity::id()`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum NetEvent {
+    CollisionStarted { collider: u64, collided: u64 },
+    CollisionEnded { collider: u64, collided: u64 },
+    InteractionDone {
+        interactor: u64,
+        interacted: u64,
+        interaction: Interaction,
+    },
+}
+
+/// Sent along with enet disconnects, so the other side knows why it was disconnected.
+/// `Unknown` is also what enet reports when a connection times out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Unknown,
+    MalformedPackets,
+    SessionTakenOver,
+    // also used by clients giving up on connecting
+    TimedOut,
+    // preceded by a `JoinRejected` message with the actual reason
+    JoinRejected,
+    // too many violations, see `net::validation`
+    Cheating,
+    Kicked,
+    Banned,
+    ServerShutdown,
+}
+
+impl DisconnectReason {
+    pub fn code(self) -> u32 {
+        match self {
+            DisconnectReason::Unknown => 0,
+            DisconnectReason::MalformedPackets => 1,
+            DisconnectReason::SessionTakenOver => 2,
+            DisconnectReason::TimedOut => 3,
+            DisconnectReason::JoinRejected => 4,
+            DisconnectReason::Cheating => 5,
+            DisconnectReason::Kicked => 6,
+            DisconnectReason::Banned => 7,
+            DisconnectReason::ServerShutdown => 8,
+        }
+    }
+
+    pub fn from_code(code: u32) -> DisconnectReason {
+        match code {
+            1 => DisconnectReason::MalformedPackets,
+            2 => DisconnectReason::SessionTakenOver,
+            3 => DisconnectReason::TimedOut,
+            4 => DisconnectReason::JoinRejected,
+            5 => DisconnectReason::Cheating,
+            6 => DisconnectReason::Kicked,
+            7 => DisconnectReason::Banned,
+            8 => DisconnectReason::ServerShutdown,
+            _ => DisconnectReason::Unknown,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    EmptyPacket,
+    UnknownChannel(u8),
+    Bincode(bincode::Error),
+    UnknownComponent(u64),
+    UnexpectedMessage(MessageType),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::EmptyPacket => write!(f, "empty packet"),
+            DecodeError::UnknownChannel(channel_id) => write!(f, "unknown channel: {}", channel_id),
+            DecodeError::Bincode(ref err) => write!(f, "malformed data: {}", err),
+            DecodeError::UnknownComponent(tag) => write!(f, "unknown component type_id: {}", tag),
+            DecodeError::UnexpectedMessage(message_type) => {
+                write!(f, "unexpected message: {:?}", message_type)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+impl From<bincode::Error> for DecodeError {
+    fn from(err: bincode::Error) -> DecodeError {
+        DecodeError::Bincode(err)
+    }
+}
+
+/// Deserializes a `T`, never reading (or allocating for) more than the remaining bytes of `reader`.
+pub fn read<T: DeserializeOwned>(reader: &mut Cursor<&[u8]>) -> Result<T, DecodeError> {
+    let remaining = remaining_len(reader);
+
+    Ok(bincode::config()
+        .limit(remaining as u64)
+        .deserialize_from(reader)?)
+}
+
+pub fn remaining_len(reader: &Cursor<&[u8]>) -> usize {
+    reader.get_ref().len().saturating_sub(reader.position() as usize)
+}
+
 pub trait MessageVisitor {
-    fn visit_entity_updates(&mut self, data: &mut Cursor<&[u8]>);
+    fn visit_entity_updates(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::EntityUpdates))
+    }
+
+    fn visit_replicated_events(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::ReplicatedEvents))
+    }
+
+    fn visit_checksum(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Checksum))
+    }
+
+    fn visit_resync_request(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::ResyncRequest))
+    }
+
+    fn visit_resync(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Resync))
+    }
+
+    fn visit_hello(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Hello))
+    }
+
+    fn visit_welcome(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Welcome))
+    }
+
+    fn visit_entities_removed(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::EntitiesRemoved))
+    }
+
+    fn visit_join_rejected(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::JoinRejected))
+    }
+
+    fn visit_intents(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Intents))
+    }
+
+    fn visit_server_message(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::ServerMessage))
+    }
+
+    fn visit_chat_send(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::ChatSend))
+    }
+
+    fn visit_chat(&mut self, _data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        Err(DecodeError::UnexpectedMessage(MessageType::Chat))
+    }
 }
 
-pub fn parse_and_visit_message<V: MessageVisitor>(message: &[u8], mut visitor: V) {
+pub fn parse_and_visit_message<V: MessageVisitor>(
+    message: &[u8],
+    mut visitor: V,
+) -> Result<(), DecodeError> {
+    if message.is_empty() {
+        return Err(DecodeError::EmptyPacket);
+    }
+
     let mut reader = Cursor::new(message);
-    let header: MessageHeader = bincode::deserialize_from(&mut reader).unwrap();
+    let header: MessageHeader = read(&mut reader)?;
 
     match header.message_type {
         MessageType::EntityUpdates => visitor.visit_entity_updates(&mut reader),
+        MessageType::ReplicatedEvents => visitor.visit_replicated_events(&mut reader),
+        MessageType::Checksum => visitor.visit_checksum(&mut reader),
+        MessageType::ResyncRequest => visitor.visit_resync_request(&mut reader),
+        MessageType::Resync => visitor.visit_resync(&mut reader),
+        MessageType::Hello => visitor.visit_hello(&mut reader),
+        MessageType::Welcome => visitor.visit_welcome(&mut reader),
+        MessageType::EntitiesRemoved => visitor.visit_entities_removed(&mut reader),
+        MessageType::JoinRejected => visitor.visit_join_rejected(&mut reader),
+        MessageType::Intents => visitor.visit_intents(&mut reader),
+        MessageType::ServerMessage => visitor.visit_server_message(&mut reader),
+        MessageType::ChatSend => visitor.visit_chat_send(&mut reader),
+        MessageType::Chat => visitor.visit_chat(&mut reader),
     }
 }
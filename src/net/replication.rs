@@ -0,0 +1,802 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hasher;
+use std::intrinsics::type_id;
+use std::io::{Cursor, Write};
+use std::time::Instant;
+
+use bincode::serialize_into;
+use ecs::{Entity, ModifyData, World};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::protocol::{self, DecodeError};
+use super::RESEND_DURATION;
+use crate::components::*;
+use crate::systems::{LevelServices, LevelSystems};
+
+/// Decides which peers a component is sent to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReplicationPolicy {
+    /// Sent to every peer.
+    All,
+    /// Only sent to the peer owning the entity.
+    OwnerOnly,
+    /// Never sent to any peer.
+    ServerOnly,
+}
+
+impl ReplicationPolicy {
+    pub fn replicate_to(self, e: Entity, owned: Option<Entity>) -> bool {
+        match self {
+            ReplicationPolicy::All => true,
+            ReplicationPolicy::OwnerOnly => owned == Some(e),
+            ReplicationPolicy::ServerOnly => false,
+        }
+    }
+}
+
+/// Conversion of a component to and from what is sent over the network.
+pub trait Replicate: Sized {
+    type Repr: 'static + Debug + Serialize + DeserializeOwned;
+
+    fn to_repr(&self, services: &LevelServices) -> Self::Repr;
+
+    fn from_repr(repr: Self::Repr, services: &mut LevelServices) -> Option<Self>;
+}
+
+macro_rules! replicate_as_is {
+    ($($component:ty),*) => {
+        $(
+            impl Replicate for $component {
+                type Repr = $component;
+
+                fn to_repr(&self, _services: &LevelServices) -> $component {
+                    self.clone()
+                }
+
+                fn from_repr(repr: $component, _services: &mut LevelServices) -> Option<$component> {
+                    Some(repr)
+                }
+            }
+        )*
+    };
+}
+
+replicate_as_is!(
+    Position,
+    Camera,
+    Velocity,
+    Jump,
+    Gravity,
+    Facing,
+    Intents,
+    Interactor,
+    Movement,
+    Sprite,
+    CollisionShape,
+    InteractionPossibility,
+    KeyboardInput,
+    PlayerName,
+    MovingPlatform
+);
+
+impl Replicate for SpriteSheetAnimation {
+    type Repr = ReplicatedAnimation;
+
+    fn to_repr(&self, services: &LevelServices) -> ReplicatedAnimation {
+        self.to_replicated(&services.resource_store)
+    }
+
+    fn from_repr(
+        repr: ReplicatedAnimation,
+        services: &mut LevelServices,
+    ) -> Option<SpriteSheetAnimation> {
+        let ssa = repr.resolve(
+            &mut services.resource_store,
+            services.simulation_time,
+            services.delta_time_s,
+        );
+
+        if ssa.is_none() {
+            println!("could not resolve animation: {:?}", repr);
+        }
+
+        ssa
+    }
+}
+
+/// The replication policy of every component in `LevelComponents`.
+/// `position` has to come first, so that systems see the correct position
+/// when an entity is activated on the client.
+macro_rules! replicated_components {
+    ($callback:ident) => {
+        $callback! {
+            position: Position => All,
+            camera: Camera => All,
+            velocity: Velocity => All,
+            jump: Jump => All,
+            gravity: Gravity => All,
+            facing: Facing => All,
+            intents: Intents => OwnerOnly,
+            interactor: Interactor => All,
+            movement: Movement => OwnerOnly,
+            sprite: Sprite => All,
+            sprite_sheet_animation: SpriteSheetAnimation => All,
+            collision_shape: CollisionShape => All,
+            interaction_possibility: InteractionPossibility => All,
+            player_name: PlayerName => All,
+            moving_platform: MovingPlatform => All,
+            // we don't want to transmit keyboard_input
+            keyboard_input: KeyboardInput => ServerOnly,
+        }
+    };
+}
+
+// advanced locally by the client's sprite_sheet_animation_system, so it may differ between ticks
+const NOT_CHECKSUMMED: &[&str] = &["sprite"];
+
+trait UpdateMapFuncs {
+    fn serialize_into(&mut self, out: &mut impl Write);
+}
+
+type UpdateMap<C> = HashMap<Entity, (C, u64, Instant)>;
+
+impl<C> UpdateMapFuncs for UpdateMap<C>
+where
+    C: 'static + serde::Serialize,
+{
+    fn serialize_into(&mut self, mut out: &mut impl Write) {
+        let now = Instant::now();
+
+        let mut tag_written = false;
+
+        // TODO remove drain() and instead send responses from client
+        for (e, mut update) in self.drain() {
+            if update.2 > now {
+                continue;
+            }
+
+            update.2 = now + RESEND_DURATION;
+
+            if !tag_written {
+                tag_written = true;
+                serialize_into(&mut out, &type_id::<C>()).unwrap();
+            } else {
+                serialize_into(&mut out, &true).unwrap();
+            }
+
+            serialize_into(&mut out, &e.id()).unwrap();
+            serialize_into(&mut out, &update.1).unwrap();
+            serialize_into(&mut out, &update.0).unwrap();
+        }
+
+        if tag_written {
+            serialize_into(&mut out, &false).unwrap();
+        }
+    }
+}
+
+macro_rules! define_component_updates {
+    ($($name:ident: $component:ident => $policy:ident,)*) => {
+        /// Component updates which still have to be sent to one peer.
+        #[derive(Debug, Default)]
+        pub struct ComponentUpdates {
+            $($name: UpdateMap<<$component as Replicate>::Repr>,)*
+        }
+
+        impl ComponentUpdates {
+            /// Creates updates for the whole world, as needed by a newly connected peer.
+            pub fn new_from_world(
+                world: &mut World<LevelSystems>,
+                owned: Option<Entity>,
+            ) -> ComponentUpdates {
+                ComponentUpdates::from_world(world, |policy, e| policy.replicate_to(e, owned))
+            }
+
+            /// Creates updates for the whole world regardless of replication policies,
+            /// for snapshots which are never sent to a peer.
+            pub fn new_from_world_unfiltered(world: &mut World<LevelSystems>) -> ComponentUpdates {
+                ComponentUpdates::from_world(world, |_, _| true)
+            }
+
+            fn from_world(
+                world: &mut World<LevelSystems>,
+                include: impl Fn(ReplicationPolicy, Entity) -> bool,
+            ) -> ComponentUpdates {
+                let mut res = ComponentUpdates::default();
+
+                let sim_time = world.services.simulation_time;
+                let now = Instant::now();
+                for en in world.entities() {
+                    $(
+                        if include(ReplicationPolicy::$policy, **en) {
+                            if let Some(c) = world.$name.get(&en) {
+                                let repr = c.to_repr(&world.services);
+                                res.$name.insert(**en, (repr, sim_time, now));
+                            }
+                        }
+                    )*
+                }
+
+                res
+            }
+
+            pub fn update_from_changes(
+                &mut self,
+                world: &mut World<LevelSystems>,
+                owned: Option<Entity>,
+            ) {
+                let sim_time = world.services.simulation_time;
+                let now = Instant::now();
+                $(
+                    for (e, c) in world.services.changed_flags.$name.iter() {
+                        if ReplicationPolicy::$policy.replicate_to(*e, owned) {
+                            let repr = c.to_repr(&world.services);
+                            self.$name.insert(*e, (repr, sim_time, now));
+                        }
+                    }
+                )*
+            }
+
+            /// Drops all pending updates of `e`, e.g. because it was removed.
+            pub fn forget_entity(&mut self, e: Entity) {
+                $(self.$name.remove(&e);)*
+            }
+
+            pub fn serialize_updates(&mut self) -> Option<Vec<u8>> {
+                let mut data = vec![];
+
+                $(self.$name.serialize_into(&mut data);)*
+
+                if data.is_empty() {
+                    None
+                } else {
+                    Some(data)
+                }
+            }
+        }
+
+        /// A single received component, see `decode_updates()`.
+        #[derive(Debug, Serialize)]
+        pub enum ComponentUpdate {
+            $($component(<$component as Replicate>::Repr),)*
+        }
+
+        impl ComponentUpdate {
+            fn decode(tag: u64, reader: &mut Cursor<&[u8]>) -> Result<ComponentUpdate, DecodeError> {
+                $(
+                    if ReplicationPolicy::$policy != ReplicationPolicy::ServerOnly
+                        && tag == type_id::<<$component as Replicate>::Repr>()
+                    {
+                        return Ok(ComponentUpdate::$component(protocol::read(reader)?));
+                    }
+                )*
+
+                Err(DecodeError::UnknownComponent(tag))
+            }
+
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(ComponentUpdate::$component(_) => stringify!($name),)*
+                }
+            }
+
+            /// Whether this component is covered by `world_checksum()`.
+            pub fn is_checksummed(&self) -> bool {
+                match *self {
+                    $(
+                        ComponentUpdate::$component(_) => {
+                            ReplicationPolicy::$policy == ReplicationPolicy::All
+                                && !NOT_CHECKSUMMED.contains(&stringify!($name))
+                        }
+                    )*
+                }
+            }
+
+            fn apply(self, en: Entity, world: &mut World<LevelSystems>) {
+                match self {
+                    $(
+                        ComponentUpdate::$component(repr) => {
+                            let c = match <$component as Replicate>::from_repr(repr, &mut world.services) {
+                                Some(c) => c,
+                                None => return,
+                            };
+
+                            world.modify_entity(en, move |e: ModifyData<LevelComponents>, data: &mut LevelComponents| {
+                                data.$name.insert(&e, c);
+                            });
+                        }
+                    )*
+                }
+            }
+        }
+
+        /// The components covered by `world_checksum()` of all entities `id_of` returns an id for,
+        /// sorted by that id.
+        fn checksummed_components(
+            world: &World<LevelSystems>,
+            id_of: impl Fn(Entity) -> Option<u64>,
+        ) -> Vec<(u64, ComponentUpdate)> {
+            let mut res = Vec::new();
+
+            for en in world.entities() {
+                let e_id = match id_of(**en) {
+                    Some(e_id) => e_id,
+                    None => continue,
+                };
+
+                $(
+                    if let Some(c) = world.$name.get(&en) {
+                        let update = ComponentUpdate::$component(c.to_repr(&world.services));
+                        if update.is_checksummed() {
+                            res.push((e_id, update));
+                        }
+                    }
+                )*
+            }
+
+            // stable, so components stay in the order of `replicated_components!`
+            res.sort_by_key(|&(e_id, _)| e_id);
+
+            res
+        }
+
+        #[cfg(test)]
+        fn assert_world_replicated(
+            server: &mut World<LevelSystems>,
+            client: &mut World<LevelSystems>,
+            mapping: &EntityMapping,
+            owned: Option<Entity>,
+        ) {
+            let server_entities: Vec<Entity> = server.entities().map(|en| **en).collect();
+
+            for e in server_entities {
+                let client_e = mapping.get(e.id()).expect("entity was not replicated");
+
+                $(
+                    if ReplicationPolicy::$policy.replicate_to(e, owned) {
+                        let expected = server
+                            .with_entity_data(&e, |en, comps| comps.$name.get(&en))
+                            .unwrap()
+                            .map(|c| bincode::serialize(&c.to_repr(&server.services)).unwrap());
+                        let actual = client
+                            .with_entity_data(&client_e, |en, comps| comps.$name.get(&en))
+                            .unwrap()
+                            .map(|c| bincode::serialize(&c.to_repr(&client.services)).unwrap());
+
+                        assert_eq!(
+                            expected,
+                            actual,
+                            "'{}' differs for entity {}",
+                            stringify!($name),
+                            e.id()
+                        );
+                    }
+                )*
+            }
+        }
+    };
+}
+
+replicated_components!(define_component_updates);
+
+/// Deterministic hash over the components every peer receives, identifying entities
+/// by the ids `id_of` returns. Entities without an id are left out.
+pub fn world_checksum(world: &World<LevelSystems>, id_of: impl Fn(Entity) -> Option<u64>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for (e_id, update) in checksummed_components(world, id_of) {
+        hasher.write_u64(e_id);
+        hasher.write(&bincode::serialize(&update).unwrap());
+    }
+
+    hasher.finish()
+}
+
+/// Readable version of the components covered by `world_checksum()`.
+pub fn describe_world(
+    world: &World<LevelSystems>,
+    id_of: impl Fn(Entity) -> Option<u64>,
+) -> Vec<String> {
+    checksummed_components(world, id_of)
+        .iter()
+        .map(|(e_id, update)| describe_component(*e_id, update))
+        .collect()
+}
+
+pub fn describe_component(entity_id: u64, update: &ComponentUpdate) -> String {
+    format!("entity {:>6} {:<24} {:?}", entity_id, update.name(), update)
+}
+
+#[derive(Debug)]
+pub struct EntityUpdate {
+    pub entity_id: u64,
+    pub sim_time: u64,
+    pub component: ComponentUpdate,
+}
+
+/// Decodes a whole update packet. Nothing is returned if any part of it is malformed.
+pub fn decode_updates(data: &[u8]) -> Result<Vec<EntityUpdate>, DecodeError> {
+    if data.is_empty() {
+        return Err(DecodeError::EmptyPacket);
+    }
+
+    let mut reader = Cursor::new(data);
+    let mut updates = Vec::new();
+
+    while protocol::remaining_len(&reader) > 0 {
+        let tag: u64 = protocol::read(&mut reader)?;
+
+        loop {
+            let entity_id: u64 = protocol::read(&mut reader)?;
+            let sim_time: u64 = protocol::read(&mut reader)?;
+
+            let component = ComponentUpdate::decode(tag, &mut reader)?;
+
+            updates.push(EntityUpdate {
+                entity_id,
+                sim_time,
+                component,
+            });
+
+            let more: bool = protocol::read(&mut reader)?;
+            if !more {
+                break;
+            }
+        }
+    }
+
+    Ok(updates)
+}
+
+/// Maps the entity ids used by the server to entities in the local world.
+#[derive(Debug, Default)]
+pub struct EntityMapping {
+    entities: HashMap<u64, Entity>,
+}
+
+impl EntityMapping {
+    pub fn get(&self, e_id: u64) -> Option<Entity> {
+        self.entities.get(&e_id).cloned()
+    }
+
+    pub fn get_or_create(&mut self, e_id: u64, world: &mut World<LevelSystems>) -> Entity {
+        *self.entities.entry(e_id).or_insert_with(|| {
+            let e = world.create_entity(());
+            println!("e_id: {}, e: {:?}", e_id, e);
+            e
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, Entity)> + '_ {
+        self.entities.iter().map(|(e_id, e)| (*e_id, *e))
+    }
+
+    /// Maps local entities back to the ids used by the server.
+    pub fn server_ids(&self) -> HashMap<Entity, u64> {
+        self.iter().map(|(e_id, e)| (e, e_id)).collect()
+    }
+
+    /// Removes the entity mapped to `e_id` from `world`, if there is one.
+    pub fn remove(&mut self, e_id: u64, world: &mut World<LevelSystems>) {
+        if let Some(e) = self.entities.remove(&e_id) {
+            world.remove_entity(e);
+        }
+    }
+
+    /// Removes all mapped entities from `world`.
+    pub fn remove_all(&mut self, world: &mut World<LevelSystems>) {
+        for (_, e) in self.entities.drain() {
+            world.remove_entity(e);
+        }
+
+        world.flush_queue();
+    }
+}
+
+pub fn apply_updates(
+    updates: Vec<EntityUpdate>,
+    mapping: &mut EntityMapping,
+    world: &mut World<LevelSystems>,
+) {
+    for update in updates {
+        if update.sim_time > world.services.simulation_time {
+            world.services.simulation_time = update.sim_time;
+        }
+
+        let en = mapping.get_or_create(update.entity_id, world);
+        update.component.apply(en, world);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use ecs::{BuildData, Entity, World};
+
+    use super::*;
+    use crate::application::InputIntent;
+    use crate::game::Interaction;
+    use crate::na::{Point2, Vector2};
+    use crate::nc::bounding_volume::AABB;
+    use crate::nc::shape::Cuboid;
+    use crate::resources::TextureSlug;
+    use crate::systems::WorldViewport;
+
+    fn create_server_world() -> (World<LevelSystems>, Entity) {
+        let mut world = World::<LevelSystems>::new();
+        world.services.simulation_time = 42;
+        world.services.delta_time_s = 0.01;
+
+        let ss_handle = world
+            .services
+            .resource_store
+            .load_sprite_sheet(Path::new("assets/textures/sprites/player/animations.toml"));
+        let walk_animation = world
+            .services
+            .resource_store
+            .get_sprite_sheet(ss_handle)
+            .get("walk")
+            .unwrap()
+            .clone();
+
+        let _camera = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, Position { x: 0.0, y: 0.0 });
+                data.camera.add(
+                    &entity,
+                    Camera::new(
+                        WorldViewport::new(800.0, 600.0),
+                        AABB::new(Point2::new(-1.0, -1.0), Point2::new(1.0, 1.0)),
+                        true,
+                    ),
+                );
+            },
+        );
+
+        let player = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                let position = Position { x: 32.0, y: 64.0 };
+                data.position.add(&entity, position);
+                data.velocity.add(
+                    &entity,
+                    Velocity {
+                        vx: 0.0,
+                        vy: 0.0,
+                        last_pos: position,
+                    },
+                );
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_dual(
+                        Cuboid::new(Vector2::new(16.0, 5.0)),
+                        Vector2::new(16.0, 16.0),
+                        Cuboid::new(Vector2::new(5.0, 16.0)),
+                        Vector2::new(16.0, 16.0),
+                        CollisionType::Solid,
+                    ),
+                );
+                data.movement.add(
+                    &entity,
+                    Movement::new(Vector2::new(110.0, 0.0), Vector2::new(1000.0, 0.0)),
+                );
+                data.facing.add(&entity, Facing::Left);
+                data.jump.add(&entity, Jump::new());
+                data.gravity.add(&entity, Gravity::new());
+                data.sprite.add(
+                    &entity,
+                    Sprite {
+                        info: walk_animation.create_sprite_info(3),
+                        sprite_layer: SpriteLayer::Foreground,
+                    },
+                );
+                data.sprite_sheet_animation.add(
+                    &entity,
+                    SpriteSheetAnimation {
+                        sheet_handle: ss_handle,
+                        animation: walk_animation.clone(),
+                        current_frame: 3,
+                        frame_time_remaining: 0.05,
+                        start_tick: 7,
+                    },
+                );
+                data.intents.add(&entity, {
+                    let mut intents = Intents::new();
+                    intents.insert(InputIntent::MoveLeft);
+                    intents
+                });
+                data.interactor.add(&entity, Interactor);
+                data.keyboard_input.add(
+                    &entity,
+                    KeyboardInput {
+                        input_context: HashMap::new(),
+                    },
+                );
+            },
+        );
+
+        let _warp_block = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, Position { x: 96.0, y: 32.0 });
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_single(
+                        Cuboid::new(Vector2::new(16.0, 16.0)),
+                        Vector2::new(16.0, 16.0),
+                        CollisionType::Trigger,
+                    ),
+                );
+                data.interaction_possibility.add(
+                    &entity,
+                    InteractionPossibility {
+                        interaction: Interaction::WarpInRoom { x: 0.0, y: 500.0 },
+                    },
+                );
+                data.sprite.add(
+                    &entity,
+                    Sprite {
+                        info: SpriteInfo {
+                            width: 32.0,
+                            height: 32.0,
+                            texture_info: TextureSlug::tilesets__cave__tile1.texture_info(),
+                        },
+                        sprite_layer: SpriteLayer::Background,
+                    },
+                );
+            },
+        );
+
+        (world, player)
+    }
+
+    fn join(server_world: &mut World<LevelSystems>, owned: Option<Entity>) -> (World<LevelSystems>, EntityMapping) {
+        let data = ComponentUpdates::new_from_world(server_world, owned)
+            .serialize_updates()
+            .unwrap();
+
+        let mut client_world = World::<LevelSystems>::new();
+        client_world.services.delta_time_s = 0.01;
+
+        let mut mapping = EntityMapping::default();
+        apply_updates(decode_updates(&data).unwrap(), &mut mapping, &mut client_world);
+
+        (client_world, mapping)
+    }
+
+    #[test]
+    fn joining_client_receives_whole_world() {
+        let (mut server_world, _player) = create_server_world();
+        let (mut client_world, mapping) = join(&mut server_world, None);
+
+        assert_world_replicated(&mut server_world, &mut client_world, &mapping, None);
+    }
+
+    #[test]
+    fn owner_receives_owner_only_components() {
+        let (mut server_world, player) = create_server_world();
+
+        let (mut client_world, mapping) = join(&mut server_world, Some(player));
+        assert_world_replicated(&mut server_world, &mut client_world, &mapping, Some(player));
+
+        let (mut client_world, mapping) = join(&mut server_world, None);
+        let client_player = mapping.get(player.id()).unwrap();
+        let has_intents = client_world
+            .with_entity_data(&client_player, |en, comps| comps.intents.has(&en))
+            .unwrap();
+        assert!(!has_intents);
+    }
+
+    #[test]
+    fn checksum_detects_diverged_client() {
+        let (mut server_world, player) = create_server_world();
+        let (mut client_world, mapping) = join(&mut server_world, None);
+
+        let server_ids = mapping.server_ids();
+        let server_checksum = world_checksum(&server_world, |e| Some(e.id()));
+        let client_checksum = |client_world: &World<LevelSystems>| {
+            world_checksum(client_world, |e| server_ids.get(&e).cloned())
+        };
+
+        assert_eq!(server_checksum, client_checksum(&client_world));
+
+        // owner only components are not part of the checksum
+        let (owner_world, owner_mapping) = join(&mut server_world, Some(player));
+        let owner_ids = owner_mapping.server_ids();
+        assert_eq!(
+            server_checksum,
+            world_checksum(&owner_world, |e| owner_ids.get(&e).cloned())
+        );
+
+        let client_player = mapping.get(player.id()).unwrap();
+        client_world.with_entity_data(&client_player, |en, comps| {
+            comps.velocity.borrow(&en).unwrap().vx += 1.0;
+        });
+
+        assert_ne!(server_checksum, client_checksum(&client_world));
+    }
+
+    #[test]
+    fn malformed_updates_are_rejected() {
+        use rand::{Rng, SeedableRng};
+
+        let (mut server_world, player) = create_server_world();
+        let data = ComponentUpdates::new_from_world(&mut server_world, Some(player))
+            .serialize_updates()
+            .unwrap();
+
+        assert!(decode_updates(&[]).is_err());
+
+        // every truncation of a valid packet
+        for len in 1..data.len() {
+            let _ = decode_updates(&data[..len]);
+        }
+
+        // an unknown component
+        let mut unknown = vec![];
+        bincode::serialize_into(&mut unknown, &0xdead_beef_u64).unwrap();
+        match decode_updates(&unknown) {
+            Err(DecodeError::UnknownComponent(0xdead_beef)) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        // a length prefix much larger than the packet
+        let mut huge_string = vec![];
+        bincode::serialize_into(&mut huge_string, &type_id::<ReplicatedAnimation>()).unwrap();
+        bincode::serialize_into(&mut huge_string, &(1u64, 2u64)).unwrap();
+        bincode::serialize_into(&mut huge_string, &u64::max_value()).unwrap();
+        assert!(decode_updates(&huge_string).is_err());
+
+        // random garbage and random corruptions of a valid packet
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+        for _ in 0..2000 {
+            let len = rng.gen_range(1, 256);
+            let garbage: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let _ = decode_updates(&garbage);
+
+            let mut corrupted = data.clone();
+            let idx = rng.gen_range(0, corrupted.len());
+            corrupted[idx] = rng.gen();
+            let _ = decode_updates(&corrupted);
+        }
+    }
+
+    #[test]
+    fn animations_of_unknown_sprite_sheets_are_not_applied() {
+        let (mut server_world, player) = create_server_world();
+        let (mut client_world, mut mapping) = join(&mut server_world, None);
+
+        let animation = |sheet_path: &str| ReplicatedAnimation {
+            sheet_path: sheet_path.into(),
+            animation_name: "walk".to_string(),
+            start_tick: 0,
+        };
+
+        // a well-formed update for a sprite sheet the client does not have, or that is no
+        // sprite sheet at all
+        for sheet_path in &["assets/textures/sprites/enemy/animations.toml", "/etc/passwd"] {
+            let mut data = vec![];
+            bincode::serialize_into(&mut data, &type_id::<ReplicatedAnimation>()).unwrap();
+            bincode::serialize_into(&mut data, &(player.id(), 43u64)).unwrap();
+            bincode::serialize_into(&mut data, &animation(sheet_path)).unwrap();
+            bincode::serialize_into(&mut data, &false).unwrap();
+
+            let client_player = mapping.get(player.id()).unwrap();
+            client_world.with_entity_data(&client_player, |en, comps| {
+                comps.sprite_sheet_animation.remove(&en);
+            });
+
+            apply_updates(decode_updates(&data).unwrap(), &mut mapping, &mut client_world);
+
+            let has_animation = client_world
+                .with_entity_data(&client_player, |en, comps| {
+                    comps.sprite_sheet_animation.has(&en)
+                })
+                .unwrap();
+            assert!(!has_animation, "applied an animation of {}", sheet_path);
+        }
+    }
+}
@@ -1,177 +1,975 @@
-use std::collections::HashMap;
-use std::intrinsics::type_id;
-use std::io::Write;
-use std::net::Ipv4Addr;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Cursor};
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use bincode::serialize_into;
 use ecs::{Entity, World};
 
-use enet::{self, Event, Packet, PacketMode, PeerState};
+use enet::{self, Event, Packet, PacketMode, Peer, PeerState};
 
-use super::{ENET, PORT, RESEND_DURATION, UPDATE_CHANNEL_ID};
-use crate::components::*;
+use super::admin::{AdminCommand, AdminConsole, AdminReply, Target};
+use super::capture::{CaptureWriter, Direction};
+use super::chat::{self, ChatMessage, ChatRequest, ChatScope, Team, NUM_TEAMS};
+use super::demo::DemoRecorder;
+use super::discovery::{DiscoveryResponder, ServerInfo};
+use super::protocol::{
+    self, Checksum, DecodeError, DisconnectReason, Hello, JoinRejection, MessageHeader,
+    MessageType, MessageVisitor, NetEvent, Resync, Welcome,
+};
+use super::replication::{self, ComponentUpdates};
+use super::validation::{
+    self, MovementValidator, RateLimiter, ValidationConfig, Violation, ViolationTracker,
+};
+use super::{
+    ADMIN_PORT, DISCOVERY_PORT, ENET, EVENT_CHANNEL_ID, INPUT_CHANNEL_ID, PORT,
+    PROTOCOL_VERSION, UPDATE_CHANNEL_ID,
+};
+use crate::components::{Intents, PlayerName};
+use crate::game::events::ReplicatedEvent;
+use crate::game::{prefabs, PlayerId};
 use crate::systems::LevelSystems;
 
-trait UpdateMapFuncs {
-    fn serialize_into(&mut self, out: &mut impl Write);
+// peers sending more malformed packets than this are disconnected
+const MAX_MALFORMED_PACKETS: u32 = 10;
+
+// how often peers receive a checksum of the world, so they can detect desyncs
+const CHECKSUM_INTERVAL_TICKS: u64 = 100;
+
+// snapshots of the world at the last checksums, sent to peers requesting a resync
+const MAX_STORED_SNAPSHOTS: usize = 8;
+
+// how long the player of a disconnected peer is kept, so the peer can reconnect to it
+const SESSION_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+const MIN_NAME_LENGTH: usize = 2;
+const MAX_NAME_LENGTH: usize = 16;
+
+// how long `Server::shutdown` waits for peers to acknowledge being disconnected
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the admin console asks of the game, as the server cannot do it on its own.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdminRequest {
+    ReloadRoom,
+    Shutdown,
 }
 
-type UpdateMap<C> = HashMap<Entity, (C, u64, Instant)>;
+#[derive(Debug)]
+struct Session {
+    id: PlayerId,
+    team: Team,
+    player: Entity,
+    // incremented whenever a peer attaches to this session, see `PeerData::session`
+    generation: u32,
+    disconnected_at: Option<Instant>,
+}
 
-impl<C> UpdateMapFuncs for UpdateMap<C>
-where
-    C: 'static + serde::Serialize,
-{
-    fn serialize_into(&mut self, mut out: &mut impl Write) {
-        let now = Instant::now();
+#[derive(Debug)]
+struct PeerData {
+    updates: ComponentUpdates,
+    // the entity controlled by this peer, if any
+    player: Option<Entity>,
+    malformed_packets: u32,
+    // token and generation of the attached session, `None` until the peer said hello
+    session: Option<(u64, u32)>,
+    input_limiter: RateLimiter,
+    chat_limiter: RateLimiter,
+    resync_limiter: RateLimiter,
+    violations: ViolationTracker,
+    // the intents of the last input message
+    held_intents: Intents,
+    // all intents received since they were last applied, so short presses are not lost
+    pending_intents: Intents,
+    connected_at: Instant,
+    packets_received: u64,
+}
+
+impl PeerData {
+    fn new(config: &ValidationConfig) -> PeerData {
+        PeerData {
+            updates: ComponentUpdates::default(),
+            player: None,
+            malformed_packets: 0,
+            session: None,
+            input_limiter: RateLimiter::new(config.max_inputs_per_second, config.max_input_burst),
+            chat_limiter: RateLimiter::new(config.max_chat_per_second, config.max_chat_burst),
+            resync_limiter: RateLimiter::new(
+                config.max_resyncs_per_second,
+                config.max_resync_burst,
+            ),
+            violations: ViolationTracker::default(),
+            held_intents: Intents::new(),
+            pending_intents: Intents::new(),
+            connected_at: Instant::now(),
+            packets_received: 0,
+        }
+    }
 
-        let mut tag_written = false;
+    /// Sets the intents of the peer's player for the next ticks.
+    fn apply_intents(&mut self, world: &mut World<LevelSystems>, player: Entity) {
+        let mut intents = self.held_intents.clone();
+        intents.extend(self.pending_intents.drain());
 
-        // TODO remove drain() and instead send responses from client
-        for (e, mut update) in self.drain() {
-            if update.2 > now {
-                continue;
+        world.with_entity_data(&player, |en, comps| {
+            if let Some(player_intents) = comps.intents.borrow(&en) {
+                *player_intents = intents;
             }
+        });
+    }
 
-            update.2 = now + RESEND_DURATION;
+    fn update_from_changes(&mut self, world: &mut World<LevelSystems>) {
+        self.updates.update_from_changes(world, self.player);
+    }
 
-            if !tag_written {
-                tag_written = true;
-                serialize_into(&mut out, &type_id::<C>()).unwrap();
-            } else {
-                serialize_into(&mut out, &true).unwrap();
-            }
+    fn serialize_updates(&mut self) -> Option<Vec<u8>> {
+        self.updates.serialize_updates()
+    }
+}
 
-            serialize_into(&mut out, &e.id()).unwrap();
-            serialize_into(&mut out, &update.1).unwrap();
-            serialize_into(&mut out, &update.0).unwrap();
+impl From<&ReplicatedEvent> for NetEvent {
+    fn from(event: &ReplicatedEvent) -> NetEvent {
+        match *event {
+            ReplicatedEvent::CollisionStarted(ev) => NetEvent::CollisionStarted {
+                collider: ev.collider.id(),
+                collided: ev.collided.id(),
+            },
+            ReplicatedEvent::CollisionEnded(ev) => NetEvent::CollisionEnded {
+                collider: ev.collider.id(),
+                collided: ev.collided.id(),
+            },
+            ReplicatedEvent::InteractionDone(ev) => NetEvent::InteractionDone {
+                interactor: ev.interactor.id(),
+                interacted: ev.interacted.id(),
+                interaction: ev.interaction,
+            },
         }
+    }
+}
 
-        if tag_written {
-            serialize_into(&mut out, &false).unwrap();
-        }
+/// The message with the events `relevant` accepts, if any.
+fn serialize_events(
+    events: &[(u64, ReplicatedEvent)],
+    relevant: impl Fn(&ReplicatedEvent) -> bool,
+) -> Option<Vec<u8>> {
+    let net_events: Vec<(u64, NetEvent)> = events
+        .iter()
+        .filter(|(_, event)| relevant(event))
+        .map(|(sim_time, event)| (*sim_time, event.into()))
+        .collect();
+
+    if net_events.is_empty() {
+        return None;
     }
+
+    let mut data = vec![];
+    MessageHeader::new(MessageType::ReplicatedEvents).write_into(&mut data);
+    serialize_into(&mut data, &net_events).unwrap();
+
+    Some(data)
 }
 
-#[derive(Debug, Default)]
-struct PeerData {
-    position: UpdateMap<Position>,
-    camera: UpdateMap<Camera>,
-    velocity: UpdateMap<Velocity>,
-    jump: UpdateMap<Jump>,
-    gravity: UpdateMap<Gravity>,
-    facing: UpdateMap<Facing>,
-    intents: UpdateMap<Intents>,
-    interactor: UpdateMap<Interactor>,
-    movement: UpdateMap<Movement>,
-    sprite: UpdateMap<Sprite>,
-}
-
-macro_rules! new_from_world_inner {
-    ($name:ident, $res:ident, $world:ident, $en:ident, $sim_time:ident, $now: ident) => {
-        if let Some(c) = $world.$name.get(&$en) {
-            $res.$name.insert(**$en, (c, $sim_time, $now));
+/// Checks whether the peer sending `hello` may join, and returns the name it joins with.
+/// `own_player` is the player of the session the peer reattaches to, if any.
+fn validate_hello(
+    hello: &Hello,
+    config: &ServerConfig,
+    world: &World<LevelSystems>,
+    own_player: Option<Entity>,
+) -> Result<String, JoinRejection> {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(JoinRejection::IncompatibleVersion);
+    }
+
+    if let Some(ref password) = config.password {
+        if hello.password.as_ref() != Some(password) {
+            return Err(JoinRejection::WrongPassword);
         }
-    };
+    }
+
+    let name = hello.name.trim();
+    let name_length = name.chars().count();
+
+    if name_length < MIN_NAME_LENGTH {
+        return Err(JoinRejection::NameTooShort);
+    }
+
+    if name_length > MAX_NAME_LENGTH {
+        return Err(JoinRejection::NameTooLong);
+    }
+
+    // names are rendered with a bitmap font, which only has these characters
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_';
+    if !name.chars().all(valid_char) {
+        return Err(JoinRejection::InvalidName);
+    }
+
+    let taken = world.entities().any(|en| {
+        Some(**en) != own_player
+            && world
+                .player_name
+                .get(&en)
+                .map_or(false, |other| other.0.eq_ignore_ascii_case(name))
+    });
+
+    if taken {
+        return Err(JoinRejection::NameTaken);
+    }
+
+    Ok(name.to_string())
 }
 
-macro_rules! update_from_changes_inner {
-    ($name:ident, $self:ident, $world:ident, $sim_time:ident, $now: ident) => {
-        for (e, c) in $world.services.changed_flags.$name.iter() {
-            $self.$name.insert(*e, (c.clone(), $sim_time, $now));
-        }
-    };
+/// The team with the fewest players, so teams stay balanced as players join.
+fn smallest_team(sessions: &HashMap<u64, Session>) -> Team {
+    (0..NUM_TEAMS)
+        .map(Team)
+        .min_by_key(|&team| sessions.values().filter(|session| session.team == team).count())
+        .unwrap()
 }
 
-impl PeerData {
-    fn new_from_world(world: &mut World<LevelSystems>) -> PeerData {
-        let mut res = PeerData::default();
+/// The lowest id not used by any session, so ids stay short enough to type in the admin console.
+fn free_player_id(sessions: &HashMap<u64, Session>) -> PlayerId {
+    let used: HashSet<PlayerId> = sessions.values().map(|session| session.id).collect();
 
-        let sim_time = world.services.simulation_time;
-        let now = Instant::now();
-        for en in world.entities() {
-            new_from_world_inner!(position  , res, world, en, sim_time, now);
-            new_from_world_inner!(camera    , res, world, en, sim_time, now);
-            new_from_world_inner!(jump      , res, world, en, sim_time, now);
-            new_from_world_inner!(gravity   , res, world, en, sim_time, now);
-            new_from_world_inner!(facing    , res, world, en, sim_time, now);
-            new_from_world_inner!(intents   , res, world, en, sim_time, now);
-            new_from_world_inner!(interactor, res, world, en, sim_time, now);
-            new_from_world_inner!(movement  , res, world, en, sim_time, now);
-            new_from_world_inner!(sprite    , res, world, en, sim_time, now);
+    (1..=u16::max_value())
+        .map(PlayerId::from)
+        .find(|id| !used.contains(id))
+        .expect("ran out of player ids")
+}
+
+struct PeerMessage<'a> {
+    config: &'a ServerConfig,
+    peer_data: &'a mut PeerData,
+    world: &'a mut World<LevelSystems>,
+    snapshots: &'a VecDeque<(u64, Vec<u8>)>,
+    sessions: &'a mut HashMap<u64, Session>,
+    replies: &'a mut Vec<Vec<u8>>,
+    // set if the peer has to be disconnected after receiving the replies
+    disconnect: &'a mut Option<DisconnectReason>,
+    violations: &'a mut Vec<Violation>,
+    // relayed to the other peers at the end of `Server::maintain`
+    chat: &'a mut Vec<ChatMessage>,
+}
+
+impl MessageVisitor for PeerMessage<'_> {
+    fn visit_hello(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let hello: Hello = protocol::read(data)?;
+
+        // each peer controls one player, saying hello again must not create another
+        if self.peer_data.session.is_some() {
+            return Err(DecodeError::UnexpectedMessage(MessageType::Hello));
         }
 
-        res
+        let known_session = hello
+            .session_token
+            .filter(|token| self.sessions.contains_key(token));
+        let own_player = known_session.map(|token| self.sessions[&token].player);
+
+        let name = match validate_hello(&hello, self.config, self.world, own_player) {
+            Ok(name) => name,
+            Err(rejection) => {
+                println!("rejected peer joining as {:?}: {}", hello.name, rejection);
+
+                self.replies
+                    .push(protocol::message(MessageType::JoinRejected, &rejection));
+                *self.disconnect = Some(DisconnectReason::JoinRejected);
+
+                return Ok(());
+            }
+        };
+
+        let session_token = match known_session {
+            Some(token) => {
+                // the name might have changed since the last time
+                let player = self.sessions[&token].player;
+                self.world.with_entity_data(&player, |en, comps| {
+                    if let Some(player_name) = comps.player_name.borrow(&en) {
+                        player_name.0 = name.clone();
+                    }
+                });
+                self.world
+                    .services
+                    .changed_flags
+                    .player_name
+                    .insert(player, PlayerName(name));
+
+                token
+            }
+            None => {
+                let token = rand::random();
+                let player = prefabs::create_player(
+                    self.world,
+                    prefabs::PLAYER_SPAWN,
+                    PlayerName(name),
+                    None,
+                );
+
+                let id = free_player_id(self.sessions);
+                let team = smallest_team(self.sessions);
+                self.sessions.insert(
+                    token,
+                    Session {
+                        id,
+                        team,
+                        player,
+                        generation: 0,
+                        disconnected_at: None,
+                    },
+                );
+
+                token
+            }
+        };
+
+        let session = self.sessions.get_mut(&session_token).unwrap();
+        session.generation += 1;
+        session.disconnected_at = None;
+
+        println!(
+            "peer attached to session of player {} (entity {}, generation {})",
+            session.id,
+            session.player.id(),
+            session.generation
+        );
+
+        self.peer_data.session = Some((session_token, session.generation));
+        self.peer_data.player = Some(session.player);
+        self.peer_data.updates =
+            ComponentUpdates::new_from_world(self.world, Some(session.player));
+
+        self.replies.push(protocol::message(
+            MessageType::Welcome,
+            &Welcome {
+                session_token,
+                player: session.player.id(),
+            },
+        ));
+
+        Ok(())
     }
 
-    fn update_from_changes(&mut self, world: &mut World<LevelSystems>) {
-        let sim_time = world.services.simulation_time;
-        let now = Instant::now();
-        update_from_changes_inner!(position  , self, world, sim_time, now);
-        update_from_changes_inner!(camera    , self, world, sim_time, now);
-        update_from_changes_inner!(velocity  , self, world, sim_time, now);
-        update_from_changes_inner!(jump      , self, world, sim_time, now);
-        update_from_changes_inner!(gravity   , self, world, sim_time, now);
-        update_from_changes_inner!(facing    , self, world, sim_time, now);
-        update_from_changes_inner!(intents   , self, world, sim_time, now);
-        update_from_changes_inner!(interactor, self, world, sim_time, now);
-        update_from_changes_inner!(movement  , self, world, sim_time, now);
-        update_from_changes_inner!(sprite    , self, world, sim_time, now);
+    fn visit_intents(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let intents: Intents = protocol::read(data)?;
+
+        let within_rate = self.peer_data.input_limiter.try_acquire(Instant::now());
+        if !within_rate {
+            self.violations.push(Violation::InputRateExceeded);
+        }
+
+        let (allowed, forbidden): (Intents, Intents) = intents
+            .into_iter()
+            .partition(|&intent| validation::is_allowed_intent(intent));
+
+        // messages over the limit still replace the held intents, otherwise the player would
+        // keep doing what the last accepted message said, e.g. keep walking after a release
+        if within_rate {
+            for intent in forbidden {
+                self.violations.push(Violation::ForbiddenIntent(intent));
+            }
+
+            self.peer_data.pending_intents.extend(allowed.iter().cloned());
+        }
+
+        self.peer_data.held_intents = allowed;
+
+        Ok(())
     }
 
-    fn serialize_updates(&mut self) -> Option<Vec<u8>> {
-        let mut data = vec![];
-
-        self.position.serialize_into(&mut data);
-        self.camera.serialize_into(&mut data);
-        self.velocity.serialize_into(&mut data);
-        self.jump.serialize_into(&mut data);
-        self.gravity.serialize_into(&mut data);
-        self.facing.serialize_into(&mut data);
-        self.intents.serialize_into(&mut data);
-        self.interactor.serialize_into(&mut data);
-        self.movement.serialize_into(&mut data);
-        self.sprite.serialize_into(&mut data);
-
-        if data.is_empty() {
-            None
-        } else {
-            Some(data)
+    fn visit_chat_send(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let request: ChatRequest = protocol::read(data)?;
+
+        // only players who joined have a name to chat with
+        let sessions = &*self.sessions;
+        let session = self
+            .peer_data
+            .session
+            .and_then(|(token, _)| sessions.get(&token));
+        let (session, player) = match (session, self.peer_data.player) {
+            (Some(session), Some(player)) => (session, player),
+            _ => return Err(DecodeError::UnexpectedMessage(MessageType::ChatSend)),
+        };
+
+        if !self.peer_data.chat_limiter.try_acquire(Instant::now()) {
+            let notice = "you are sending messages too fast".to_string();
+            self.replies
+                .push(protocol::message(MessageType::ServerMessage, &notice));
+            return Ok(());
+        }
+
+        let text = match chat::sanitize(&request.text) {
+            Ok(text) => text,
+            Err(err) => {
+                let notice = format!("message not sent, {}", err);
+                self.replies
+                    .push(protocol::message(MessageType::ServerMessage, &notice));
+                return Ok(());
+            }
+        };
+
+        let sender = self
+            .world
+            .with_entity_data(&player, |en, comps| comps.player_name.get(&en))
+            .and_then(|name| name)
+            .map_or_else(|| session.id.to_string(), |name| name.0);
+
+        let message = ChatMessage {
+            sim_time: self.world.services.simulation_time,
+            sender,
+            team: session.team,
+            scope: request.scope,
+            text,
+        };
+
+        println!("chat: {}", message);
+        self.chat.push(message);
+
+        Ok(())
+    }
+
+    fn visit_resync_request(&mut self, data: &mut Cursor<&[u8]>) -> Result<(), DecodeError> {
+        let sim_time: u64 = protocol::read(data)?;
+
+        // every resync sends the whole world, so they must not be requested all the time
+        if !self.peer_data.resync_limiter.try_acquire(Instant::now()) {
+            self.violations.push(Violation::ResyncRateExceeded);
+            return Ok(());
+        }
+
+        println!("peer requested a resync after desync at tick {}", sim_time);
+
+        let server_state = self
+            .snapshots
+            .iter()
+            .find(|&&(snapshot_time, _)| snapshot_time == sim_time)
+            .map(|(_, snapshot)| snapshot.clone());
+
+        self.replies.push(protocol::message(
+            MessageType::Resync,
+            &Resync {
+                sim_time,
+                server_state,
+            },
+        ));
+
+        // the next updates for this peer contain the whole world again
+        self.peer_data.updates =
+            ComponentUpdates::new_from_world(self.world, self.peer_data.player);
+
+        Ok(())
+    }
+}
+
+fn handle_packet(
+    channel_id: u8,
+    packet: &[u8],
+    message: PeerMessage<'_>,
+) -> Result<(), DecodeError> {
+    message.peer_data.packets_received += 1;
+
+    match channel_id {
+        EVENT_CHANNEL_ID | INPUT_CHANNEL_ID => protocol::parse_and_visit_message(packet, message),
+        _ => Err(DecodeError::UnknownChannel(channel_id)),
+    }
+}
+
+/// Logs `violation`, and kicks the peer if it had too many of them.
+fn report_violation(
+    peer: &mut Peer<'_, PeerData>,
+    violation: &Violation,
+    config: &ValidationConfig,
+) {
+    println!("violation by {:?}: {}", peer.address(), violation);
+
+    let kick = match peer.data_mut() {
+        Some(data) => data.violations.record(Instant::now(), config),
+        None => false,
+    };
+
+    if kick {
+        println!("kicking {:?} for too many violations", peer.address());
+        peer.disconnect(DisconnectReason::Cheating.code());
+    }
+}
+
+fn send(
+    peer: &mut Peer<'_, PeerData>,
+    capture: &mut Option<CaptureWriter>,
+    data: &[u8],
+    mode: PacketMode,
+    channel_id: u8,
+) {
+    if let Some(capture) = capture {
+        capture.record(Direction::Sent, &peer.address(), channel_id, data);
+    }
+
+    peer.send_packet(Packet::new(data, mode).unwrap(), channel_id).unwrap();
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    // shown to players looking for servers, see `net::discovery`
+    pub name: String,
+    pub map: String,
+    pub max_peers: usize,
+    pub port: u16,
+    // required to join if set
+    pub password: Option<String>,
+    pub validation: ValidationConfig,
+    // `None` disables answering discovery queries
+    pub discovery_port: Option<u16>,
+    // the admin console listens on this port on localhost, see `net::admin`
+    pub admin_port: u16,
+    // `None` disables the admin console
+    pub admin_password: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            name: "crufty server".to_string(),
+            map: "start".to_string(),
+            max_peers: 16,
+            port: PORT,
+            password: None,
+            validation: ValidationConfig::default(),
+            discovery_port: Some(DISCOVERY_PORT),
+            admin_port: ADMIN_PORT,
+            admin_password: None,
         }
     }
 }
 
 pub struct Server {
+    config: ServerConfig,
     enet_host: enet::Host<PeerData>,
+    discovery: Option<DiscoveryResponder>,
     last_maintain: Instant,
+    capture: Option<CaptureWriter>,
+    demo: Option<DemoRecorder>,
+    snapshots: VecDeque<(u64, Vec<u8>)>,
+    sessions: HashMap<u64, Session>,
+    movement_validator: MovementValidator,
+    admin: Option<AdminConsole>,
+    admin_requests: Vec<AdminRequest>,
+    // admins waiting for the game to complete their request, see `finish_admin_request`
+    awaiting_admins: Vec<(AdminRequest, SocketAddr)>,
+    banned: HashSet<Ipv4Addr>,
+    // removed by `remove_entity`, peers are told about them in the next `maintain`
+    removed_entities: Vec<Entity>,
+    chat_outbox: Vec<ChatMessage>,
 }
 
 impl Server {
-    pub fn new() -> Server {
+    pub fn new(config: ServerConfig) -> Server {
         let enet_host = ENET
             .create_host(
-                Some(&enet::Address::new(Ipv4Addr::LOCALHOST, PORT)),
-                255,
+                Some(&enet::Address::new(Ipv4Addr::UNSPECIFIED, config.port)),
+                config.max_peers,
                 enet::ChannelLimit::Maximum,
                 enet::BandwidthLimit::Unlimited,
                 enet::BandwidthLimit::Unlimited,
             )
             .unwrap();
 
+        // another server on this machine might already answer queries, so this is not fatal
+        let discovery = config.discovery_port.and_then(|port| {
+            DiscoveryResponder::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+                .map_err(|err| println!("could not start answering discovery queries: {}", err))
+                .ok()
+        });
+
+        let admin = config.admin_password.clone().and_then(|password| {
+            AdminConsole::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, config.admin_port), password)
+                .map_err(|err| println!("could not start the admin console: {}", err))
+                .ok()
+        });
+
         Server {
+            config,
             enet_host,
+            discovery,
             last_maintain: Instant::now(),
+            capture: None,
+            demo: None,
+            snapshots: VecDeque::new(),
+            sessions: HashMap::new(),
+            movement_validator: MovementValidator::default(),
+            admin,
+            admin_requests: Vec::new(),
+            awaiting_admins: Vec::new(),
+            banned: HashSet::new(),
+            removed_entities: Vec::new(),
+            chat_outbox: Vec::new(),
+        }
+    }
+
+    pub fn info(&self) -> ServerInfo {
+        let num_players = self
+            .sessions
+            .values()
+            .filter(|session| session.disconnected_at.is_none())
+            .count();
+
+        ServerInfo {
+            name: self.config.name.clone(),
+            map: self.config.map.clone(),
+            num_players: num_players as u32,
+            max_peers: self.config.max_peers as u32,
+            protocol_version: PROTOCOL_VERSION,
+            port: self.config.port,
+        }
+    }
+
+    /// Starts recording all sent and received packets to `path`, see `net::capture`.
+    pub fn enable_capture(&mut self, path: &Path) -> io::Result<()> {
+        self.capture = Some(CaptureWriter::create(path)?);
+        Ok(())
+    }
+
+    /// Starts recording a demo to `path`, which `Client::from_demo` can play back.
+    pub fn enable_demo_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.demo = Some(DemoRecorder::create(path)?);
+        Ok(())
+    }
+
+    /// Requests from the admin console which the game has to handle, see `AdminRequest`.
+    pub fn take_admin_requests(&mut self) -> Vec<AdminRequest> {
+        mem::replace(&mut self.admin_requests, Vec::new())
+    }
+
+    /// Tells the admins who asked for `request` how it went.
+    pub fn finish_admin_request(&mut self, request: AdminRequest, reply: AdminReply) {
+        let awaiting = mem::replace(&mut self.awaiting_admins, Vec::new());
+        let (finished, awaiting): (Vec<_>, Vec<_>) = awaiting
+            .into_iter()
+            .partition(|&(awaited, _)| awaited == request);
+        self.awaiting_admins = awaiting;
+
+        if let Some(ref mut admin) = self.admin {
+            for (_, addr) in finished {
+                admin.reply(addr, reply.clone());
+            }
+        }
+    }
+
+    /// Removes `e` from the world, and from the worlds of all peers.
+    pub fn remove_entity(&mut self, world: &mut World<LevelSystems>, e: Entity) {
+        world.remove_entity(e);
+        self.removed_entities.push(e);
+    }
+
+    /// Disconnects all peers, waiting up to `SHUTDOWN_TIMEOUT` for them to acknowledge it,
+    /// so they learn why instead of timing out.
+    pub fn shutdown(&mut self) {
+        for mut peer in self.enet_host.peers() {
+            if peer.state() == PeerState::Connected {
+                peer.disconnect_later(DisconnectReason::ServerShutdown.code());
+            }
+        }
+
+        let start = Instant::now();
+
+        while start.elapsed() < SHUTDOWN_TIMEOUT {
+            let all_disconnected = self
+                .enet_host
+                .peers()
+                .all(|peer| peer.state() == PeerState::Disconnected);
+
+            if all_disconnected {
+                break;
+            }
+
+            // only acknowledgements matter now, so events are dropped
+            let _ = self.enet_host.service(10);
+        }
+    }
+
+    fn describe_peers(&mut self, world: &mut World<LevelSystems>) -> Vec<String> {
+        let sessions = &self.sessions;
+        let mut lines = Vec::new();
+
+        for mut peer in self.enet_host.peers() {
+            if peer.state() != PeerState::Connected {
+                continue;
+            }
+
+            let address = peer.address();
+            let data = match peer.data_mut() {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let id = data
+                .session
+                .and_then(|(token, _)| sessions.get(&token))
+                .map_or("-".to_string(), |session| session.id.to_string());
+            let name = data
+                .player
+                .and_then(|player| {
+                    world.with_entity_data(&player, |en, comps| comps.player_name.get(&en))
+                })
+                .and_then(|name| name)
+                .map_or("-".to_string(), |name| name.0);
+
+            lines.push(format!(
+                "{:>5} {:<16} {}:{} connected {}s, {} packets, {} malformed, {} violations",
+                id,
+                name,
+                address.ip(),
+                address.port(),
+                data.connected_at.elapsed().as_secs(),
+                data.packets_received,
+                data.malformed_packets,
+                data.violations.recent_count(),
+            ));
+        }
+
+        if lines.is_empty() {
+            lines.push("no peers connected".to_string());
+        }
+
+        lines
+    }
+
+    /// Disconnects all peers matching `target`, and returns their addresses. Their sessions
+    /// end, so they can not reconnect to their players, which are removed.
+    fn kick(
+        &mut self,
+        world: &mut World<LevelSystems>,
+        target: Target,
+        reason: DisconnectReason,
+    ) -> Vec<Ipv4Addr> {
+        let sessions = &mut self.sessions;
+        let mut kicked = Vec::new();
+        let mut players = Vec::new();
+
+        for mut peer in self.enet_host.peers() {
+            if peer.state() != PeerState::Connected {
+                continue;
+            }
+
+            let address = peer.address();
+            let id = peer
+                .data_mut()
+                .and_then(|data| data.session)
+                .and_then(|(token, _)| sessions.get(&token))
+                .map(|session| session.id);
+
+            let matches = match target {
+                Target::Player(target_id) => id == Some(target_id),
+                _ => target.matches_address(SocketAddrV4::new(*address.ip(), address.port())),
+            };
+
+            if matches {
+                println!("disconnecting {:?}: {:?}", address, reason);
+                peer.disconnect_later(reason.code());
+                kicked.push(*address.ip());
+
+                if let Some(data) = peer.data_mut() {
+                    let session = data.session.take();
+                    data.player = None;
+
+                    if let Some(session) = session.and_then(|(token, _)| sessions.remove(&token)) {
+                        players.push(session.player);
+                    }
+                }
+            }
         }
+
+        for player in players {
+            self.remove_entity(world, player);
+            self.movement_validator.forget(world, player);
+        }
+
+        kicked
+    }
+
+    fn execute_admin_command(
+        &mut self,
+        world: &mut World<LevelSystems>,
+        admin: SocketAddr,
+        command: AdminCommand,
+    ) -> Option<AdminReply> {
+        let reply = match command {
+            AdminCommand::Peers => Ok(self.describe_peers(world)),
+            AdminCommand::Kick(target) => {
+                if self.kick(world, target, DisconnectReason::Kicked).is_empty() {
+                    return Some(Err(format!("no connected peer matches {}", target)));
+                }
+
+                Ok(Vec::new())
+            }
+            AdminCommand::Ban(target) => {
+                let mut banned = self.kick(world, target, DisconnectReason::Banned);
+
+                // addresses can be banned before anyone connects from them
+                if let Target::Address(ip, None) = target {
+                    banned.push(ip);
+                }
+
+                if banned.is_empty() {
+                    return Some(Err(format!("no connected peer matches {}", target)));
+                }
+
+                let lines = banned.iter().map(|ip| format!("banned {}", ip)).collect();
+                self.banned.extend(banned);
+
+                Ok(lines)
+            }
+            AdminCommand::Say(text) => {
+                let message = protocol::message(MessageType::ServerMessage, &text);
+
+                for mut peer in self.enet_host.peers() {
+                    let joined = peer.data_mut().map_or(false, |data| data.session.is_some());
+
+                    if peer.state() == PeerState::Connected && joined {
+                        send(
+                            &mut peer,
+                            &mut self.capture,
+                            &message,
+                            PacketMode::ReliableSequenced,
+                            EVENT_CHANNEL_ID,
+                        );
+                    }
+                }
+
+                Ok(Vec::new())
+            }
+            AdminCommand::Gravity(gravity) => {
+                if let Some(gravity) = gravity {
+                    world.services.gravity = gravity;
+                }
+
+                Ok(vec![format!("gravity is {}", world.services.gravity)])
+            }
+            AdminCommand::Reload => {
+                // the game reloads the room, and replies once it knows whether that worked
+                self.admin_requests.push(AdminRequest::ReloadRoom);
+                self.awaiting_admins.push((AdminRequest::ReloadRoom, admin));
+                return None;
+            }
+            AdminCommand::Shutdown => {
+                self.admin_requests.push(AdminRequest::Shutdown);
+                Ok(Vec::new())
+            }
+        };
+
+        Some(reply)
     }
 
     pub fn maintain(&mut self, world: &mut World<LevelSystems>) {
-        fn loop_body(mut event: Event<'_, PeerData>, world: &mut World<LevelSystems>) {
+        fn loop_body(
+            mut event: Event<'_, PeerData>,
+            config: &ServerConfig,
+            world: &mut World<LevelSystems>,
+            capture: &mut Option<CaptureWriter>,
+            snapshots: &VecDeque<(u64, Vec<u8>)>,
+            sessions: &mut HashMap<u64, Session>,
+            banned: &HashSet<Ipv4Addr>,
+            chat: &mut Vec<ChatMessage>,
+        ) {
             dbg!(&event);
 
             match event {
                 Event::Connect(ref mut peer) => {
-                    peer.set_data(Some(PeerData::new_from_world(world)))
+                    if banned.contains(peer.address().ip()) {
+                        println!("refused banned peer {:?}", peer.address());
+                        peer.disconnect_now(DisconnectReason::Banned.code());
+                        return;
+                    }
+
+                    peer.set_data(Some(PeerData::new(&config.validation)))
+                }
+                Event::Disconnect(ref mut peer, _) => {
+                    let session = peer.data_mut().and_then(|data| data.session);
+
+                    if let Some((token, generation)) = session {
+                        if let Some(session) = sessions.get_mut(&token) {
+                            // otherwise another peer already took over the session
+                            if session.generation == generation {
+                                session.disconnected_at = Some(Instant::now());
+                            }
+                        }
+                    }
+                }
+                Event::Receive {
+                    ref mut sender,
+                    channel_id,
+                    ref packet,
+                } => {
+                    if let Some(capture) = capture {
+                        capture.record(
+                            Direction::Received,
+                            &sender.address(),
+                            channel_id,
+                            packet.data(),
+                        );
+                    }
+
+                    let mut replies = Vec::new();
+                    let mut disconnect = None;
+                    let mut violations = Vec::new();
+
+                    let res = match sender.data_mut() {
+                        Some(peer_data) => handle_packet(
+                            channel_id,
+                            packet.data(),
+                            PeerMessage {
+                                config,
+                                peer_data,
+                                world,
+                                snapshots,
+                                sessions,
+                                replies: &mut replies,
+                                disconnect: &mut disconnect,
+                                violations: &mut violations,
+                                chat,
+                            },
+                        ),
+                        None => return,
+                    };
+
+                    for reply in replies {
+                        send(
+                            sender,
+                            capture,
+                            &reply,
+                            PacketMode::ReliableSequenced,
+                            EVENT_CHANNEL_ID,
+                        );
+                    }
+
+                    // lets the replies go out first
+                    if let Some(reason) = disconnect {
+                        sender.disconnect_later(reason.code());
+                    }
+
+                    for violation in &violations {
+                        report_violation(sender, violation, &config.validation);
+                    }
+
+                    if let Err(err) = res {
+                        let malformed_packets = match sender.data_mut() {
+                            Some(data) => {
+                                data.malformed_packets += 1;
+                                data.malformed_packets
+                            }
+                            None => return,
+                        };
+
+                        println!(
+                            "malformed packet from {:?} ({} so far): {}",
+                            sender.address(),
+                            malformed_packets,
+                            err
+                        );
+
+                        if malformed_packets >= MAX_MALFORMED_PACKETS {
+                            sender.disconnect(DisconnectReason::MalformedPackets.code());
+                        }
+                    }
                 }
                 _ => (),
             }
@@ -180,28 +978,489 @@ impl Server {
         if let Some(event) = self.enet_host.service(0).unwrap() {
             self.last_maintain = Instant::now();
 
-            loop_body(event, world);
+            loop_body(
+                event,
+                &self.config,
+                world,
+                &mut self.capture,
+                &self.snapshots,
+                &mut self.sessions,
+                &self.banned,
+                &mut self.chat_outbox,
+            );
         };
 
         while let Some(event) = self.enet_host.check_events().unwrap() {
-            loop_body(event, world);
+            loop_body(
+                event,
+                &self.config,
+                world,
+                &mut self.capture,
+                &self.snapshots,
+                &mut self.sessions,
+                &self.banned,
+                &mut self.chat_outbox,
+            );
         }
 
+        let validation = &self.config.validation;
+
+        for mut peer in self.enet_host.peers() {
+            if peer.state() != PeerState::Connected {
+                continue;
+            }
+
+            let data = match peer.data_mut() {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let player = match data.player {
+                Some(player) => player,
+                None => continue,
+            };
+
+            data.apply_intents(world, player);
+
+            let violation = self.movement_validator.check_player(
+                world,
+                player,
+                validation.displacement_tolerance,
+            );
+
+            if let Some(violation) = violation {
+                report_violation(&mut peer, &violation, validation);
+            }
+        }
+
+        if let Some(ref discovery) = self.discovery {
+            if let Err(err) = discovery.respond(&self.info()) {
+                println!("could not answer discovery queries: {}", err);
+            }
+        }
+
+        if let Some(mut admin) = self.admin.take() {
+            admin.poll(|addr, command| self.execute_admin_command(world, addr, command));
+            self.admin = Some(admin);
+        }
+
+        let now = Instant::now();
+        let mut removed = mem::replace(&mut self.removed_entities, Vec::new());
+        let expired_from = removed.len();
+
+        self.sessions.retain(|_, session| match session.disconnected_at {
+            Some(disconnected_at)
+                if now.duration_since(disconnected_at) >= SESSION_GRACE_PERIOD =>
+            {
+                removed.push(session.player);
+                false
+            }
+            _ => true,
+        });
+
+        for &e in &removed[expired_from..] {
+            println!("session of player {} expired", e.id());
+            world.remove_entity(e);
+            self.movement_validator.forget(world, e);
+        }
+
+        let removed_data = if removed.is_empty() {
+            None
+        } else {
+            let removed_ids: Vec<u64> = removed.iter().map(|e| e.id()).collect();
+            Some(protocol::message(MessageType::EntitiesRemoved, &removed_ids))
+        };
+
+        // demos show everything, so they record all events
+        if let Some(ref mut demo) = self.demo {
+            let event_data = serialize_events(&world.services.replicated_events, |_| true);
+            demo.record(world, event_data.as_ref());
+        }
+
+        let sim_time = world.services.simulation_time;
+        let checksum_due = self.snapshots.back().map_or(true, |&(last_time, _)| {
+            sim_time >= last_time + CHECKSUM_INTERVAL_TICKS
+        });
+
+        let checksum_data = if checksum_due {
+            let checksum = replication::world_checksum(world, |e| Some(e.id()));
+
+            let snapshot = ComponentUpdates::new_from_world(world, None)
+                .serialize_updates()
+                .unwrap_or_default();
+            self.snapshots.push_back((sim_time, snapshot));
+            if self.snapshots.len() > MAX_STORED_SNAPSHOTS {
+                self.snapshots.pop_front();
+            }
+
+            Some(protocol::message(
+                MessageType::Checksum,
+                &Checksum { sim_time, checksum },
+            ))
+        } else {
+            None
+        };
+
+        let chat_data: Vec<(ChatScope, Team, Vec<u8>)> = self
+            .chat_outbox
+            .drain(..)
+            .map(|message| {
+                let data = protocol::message(MessageType::Chat, &message);
+                (message.scope, message.team, data)
+            })
+            .collect();
+
         for mut peer in self.enet_host.peers() {
             if peer.state() != PeerState::Connected {
                 continue;
             }
 
             let data = peer.data_mut().unwrap();
+
+            // peers only receive the world after saying hello
+            let (token, generation) = match data.session {
+                Some(session) => session,
+                None => continue,
+            };
+
+            let team = match self.sessions.get(&token) {
+                Some(session) if session.generation == generation => session.team,
+                _ => {
+                    peer.disconnect(DisconnectReason::SessionTakenOver.code());
+                    continue;
+                }
+            };
+
             data.update_from_changes(world);
+            for &e in &removed {
+                data.updates.forget_entity(e);
+            }
+
+            let player = data.player;
+            let event_data = serialize_events(&world.services.replicated_events, |event| {
+                event.relevant_to(player)
+            });
 
             if let Some(update_data) = data.serialize_updates() {
-                peer.send_packet(
-                    Packet::new(&update_data, PacketMode::UnreliableUnsequenced).unwrap(),
+                send(
+                    &mut peer,
+                    &mut self.capture,
+                    &update_data,
+                    PacketMode::UnreliableUnsequenced,
                     UPDATE_CHANNEL_ID,
-                )
-                .unwrap();
+                );
+            }
+
+            if let Some(ref removed_data) = removed_data {
+                send(
+                    &mut peer,
+                    &mut self.capture,
+                    removed_data,
+                    PacketMode::ReliableSequenced,
+                    EVENT_CHANNEL_ID,
+                );
+            }
+
+            if let Some(ref event_data) = event_data {
+                send(
+                    &mut peer,
+                    &mut self.capture,
+                    event_data,
+                    PacketMode::ReliableSequenced,
+                    EVENT_CHANNEL_ID,
+                );
+            }
+
+            if let Some(ref checksum_data) = checksum_data {
+                send(
+                    &mut peer,
+                    &mut self.capture,
+                    checksum_data,
+                    PacketMode::ReliableSequenced,
+                    EVENT_CHANNEL_ID,
+                );
+            }
+
+            for (scope, sender_team, chat_message) in &chat_data {
+                if *scope == ChatScope::Team && *sender_team != team {
+                    continue;
+                }
+
+                send(
+                    &mut peer,
+                    &mut self.capture,
+                    chat_message,
+                    PacketMode::ReliableSequenced,
+                    EVENT_CHANNEL_ID,
+                );
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ecs::{BuildData, World};
+
+    use super::*;
+    use crate::application::InputIntent;
+    use crate::components::{InteractionPossibility, Interactor, LevelComponents};
+    use crate::game::events::{CollisionStarted, EventReceiver, InteractionDone};
+    use crate::game::Interaction;
+
+    fn hello(name: &str, password: Option<&str>) -> Hello {
+        Hello {
+            protocol_version: PROTOCOL_VERSION,
+            session_token: None,
+            name: name.to_string(),
+            password: password.map(str::to_string),
+        }
+    }
+
+    fn create_named_entity(world: &mut World<LevelSystems>, name: &str) -> Entity {
+        world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.player_name.add(&entity, PlayerName(name.to_string()));
+            },
+        )
+    }
+
+    #[test]
+    fn join_validates_name_and_password() {
+        let world = World::<LevelSystems>::new();
+        let config = ServerConfig {
+            password: Some("secret".to_string()),
+            ..ServerConfig::default()
+        };
+
+        let validate = |hello: &Hello| validate_hello(hello, &config, &world, None);
+
+        assert_eq!(validate(&hello(" alice ", Some("secret"))), Ok("alice".to_string()));
+        assert_eq!(validate(&hello("alice", None)), Err(JoinRejection::WrongPassword));
+        assert_eq!(
+            validate(&hello("alice", Some("guess"))),
+            Err(JoinRejection::WrongPassword)
+        );
+        assert_eq!(validate(&hello("a", Some("secret"))), Err(JoinRejection::NameTooShort));
+        assert_eq!(
+            validate(&hello("abcdefghijklmnopq", Some("secret"))),
+            Err(JoinRejection::NameTooLong)
+        );
+        assert_eq!(
+            validate(&hello("al!ce", Some("secret"))),
+            Err(JoinRejection::InvalidName)
+        );
+
+        let mut old_client = hello("alice", Some("secret"));
+        old_client.protocol_version = PROTOCOL_VERSION - 1;
+        assert_eq!(validate(&old_client), Err(JoinRejection::IncompatibleVersion));
+    }
+
+    #[test]
+    fn join_rejects_names_of_other_players() {
+        let mut world = World::<LevelSystems>::new();
+        let config = ServerConfig::default();
+
+        let alice = create_named_entity(&mut world, "alice");
+
+        assert_eq!(
+            validate_hello(&hello("Alice", None), &config, &world, None),
+            Err(JoinRejection::NameTaken)
+        );
+
+        // reattaching to the player named alice keeps the name
+        assert_eq!(
+            validate_hello(&hello("alice", None), &config, &world, Some(alice)),
+            Ok("alice".to_string())
+        );
+    }
+
+    // the events a peer controlling `player` is sent
+    fn sent_events(world: &World<LevelSystems>, player: Option<Entity>) -> Vec<NetEvent> {
+        let data = match serialize_events(&world.services.replicated_events, |event| {
+            event.relevant_to(player)
+        }) {
+            Some(data) => data,
+            None => return Vec::new(),
+        };
+
+        let mut reader = Cursor::new(&data[..]);
+        let header: MessageHeader = protocol::read(&mut reader).unwrap();
+        assert_eq!(header, MessageHeader::new(MessageType::ReplicatedEvents));
+
+        let events: Vec<(u64, NetEvent)> = protocol::read(&mut reader).unwrap();
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+
+    #[test]
+    fn events_are_sent_to_the_peers_they_are_relevant_to() {
+        let mut world = World::<LevelSystems>::new();
+        let interaction = Interaction::WarpInRoom { x: 0.0, y: 0.0 };
+
+        let create_player = |world: &mut World<LevelSystems>| {
+            world.create_entity(
+                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                    data.interactor.add(&entity, Interactor);
+                },
+            )
+        };
+        let (alice, bob) = (create_player(&mut world), create_player(&mut world));
+        let door = world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                let possibility = InteractionPossibility { interaction };
+                data.interaction_possibility.add(&entity, possibility);
+            },
+        );
+        let wall =
+            world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {});
+
+        // colliding with the wall can not lead to an interaction, so it is not replicated
+        world.receive_event(CollisionStarted {
+            collider: alice,
+            collided: wall,
+        });
+        world.receive_event(CollisionStarted {
+            collider: alice,
+            collided: door,
+        });
+        world.receive_event(InteractionDone {
+            interactor: alice,
+            interacted: door,
+            interaction,
+        });
+        assert_eq!(world.services.replicated_events.len(), 2);
+
+        let collision = NetEvent::CollisionStarted {
+            collider: alice.id(),
+            collided: door.id(),
+        };
+        let interaction_done = || NetEvent::InteractionDone {
+            interactor: alice.id(),
+            interacted: door.id(),
+            interaction,
+        };
+
+        assert_eq!(
+            sent_events(&world, Some(alice)),
+            vec![collision, interaction_done()]
+        );
+        // everyone sees the interaction, but not who touched what
+        assert_eq!(sent_events(&world, Some(bob)), vec![interaction_done()]);
+        assert_eq!(sent_events(&world, None), vec![interaction_done()]);
+
+        world.services.replicated_events.clear();
+        assert!(sent_events(&world, Some(alice)).is_empty());
+    }
+
+    /// What the server answered to a message of a peer.
+    #[derive(Debug, Default)]
+    struct Received {
+        replies: Vec<Vec<u8>>,
+        disconnect: Option<DisconnectReason>,
+        violations: Vec<Violation>,
+    }
+
+    fn receive(
+        world: &mut World<LevelSystems>,
+        sessions: &mut HashMap<u64, Session>,
+        peer_data: &mut PeerData,
+        message: &[u8],
+    ) -> Result<Received, DecodeError> {
+        let config = ServerConfig::default();
+        let mut received = Received::default();
+
+        protocol::parse_and_visit_message(
+            message,
+            PeerMessage {
+                config: &config,
+                peer_data,
+                world,
+                snapshots: &VecDeque::new(),
+                sessions,
+                replies: &mut received.replies,
+                disconnect: &mut received.disconnect,
+                violations: &mut received.violations,
+                chat: &mut Vec::new(),
+            },
+        )?;
+
+        Ok(received)
+    }
+
+    #[test]
+    fn resync_requests_are_rate_limited() {
+        let mut world = World::<LevelSystems>::new();
+        let mut sessions = HashMap::new();
+        let validation = ValidationConfig::default();
+        let mut peer_data = PeerData::new(&validation);
+
+        let request = protocol::message(MessageType::ResyncRequest, &0u64);
+
+        for _ in 0..validation.max_resync_burst as usize {
+            let received = receive(&mut world, &mut sessions, &mut peer_data, &request).unwrap();
+            assert_eq!(received.replies.len(), 1);
+            assert!(received.violations.is_empty());
+        }
+
+        let received = receive(&mut world, &mut sessions, &mut peer_data, &request).unwrap();
+        assert!(received.replies.is_empty());
+        assert_eq!(received.violations, vec![Violation::ResyncRateExceeded]);
+    }
+
+    #[test]
+    fn peers_join_only_once() {
+        let mut world = World::<LevelSystems>::new();
+        let mut sessions = HashMap::new();
+        let mut peer_data = PeerData::new(&ValidationConfig::default());
+
+        let message = protocol::message(MessageType::Hello, &hello("alice", None));
+
+        let received = receive(&mut world, &mut sessions, &mut peer_data, &message).unwrap();
+        assert_eq!(received.replies.len(), 1);
+        assert_eq!(received.disconnect, None);
+        assert_eq!(sessions.len(), 1);
+        let player = peer_data.player;
+
+        match receive(&mut world, &mut sessions, &mut peer_data, &message) {
+            Err(DecodeError::UnexpectedMessage(MessageType::Hello)) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(peer_data.player, player);
+    }
+
+    #[test]
+    fn intents_over_the_rate_limit_replace_held_intents() {
+        let mut world = World::<LevelSystems>::new();
+        let mut sessions = HashMap::new();
+        // no refills, so the limit is reached after the burst however slow the test runs
+        let validation = ValidationConfig {
+            max_inputs_per_second: 0.0,
+            ..ValidationConfig::default()
+        };
+        let mut peer_data = PeerData::new(&validation);
+
+        let intents = |intents: &[InputIntent]| -> Intents { intents.iter().cloned().collect() };
+        let walking = protocol::message(MessageType::Intents, &intents(&[InputIntent::MoveLeft]));
+
+        for _ in 0..validation.max_input_burst as usize {
+            let received = receive(&mut world, &mut sessions, &mut peer_data, &walking).unwrap();
+            assert!(received.violations.is_empty());
+        }
+        peer_data.pending_intents.clear();
+
+        let jumping = protocol::message(
+            MessageType::Intents,
+            &intents(&[InputIntent::Jump, InputIntent::PrintDebugMessage]),
+        );
+        let received = receive(&mut world, &mut sessions, &mut peer_data, &jumping).unwrap();
+
+        assert_eq!(received.violations, vec![Violation::InputRateExceeded]);
+        assert_eq!(peer_data.held_intents, intents(&[InputIntent::Jump]));
+        assert!(peer_data.pending_intents.is_empty());
+
+        let stopped = protocol::message(MessageType::Intents, &intents(&[]));
+        receive(&mut world, &mut sessions, &mut peer_data, &stopped).unwrap();
+        assert!(peer_data.held_intents.is_empty());
+    }
+}
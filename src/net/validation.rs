@@ -0,0 +1,426 @@
+//! Sanity checks of what peers send, and of how their players move. Peers only send their
+//! intents, but a bug or a future message type (e.g. positions reported for prediction)
+//! must not let a player move further than its components allow.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use ecs::{Entity, World};
+
+use crate::application::InputIntent;
+use crate::components::Position;
+use crate::game::events::ReplicatedEvent;
+use crate::game::{EntityOps, EntityOrData, Interaction};
+use crate::systems::{LevelSystems, JUMP_RISE_VEL};
+
+use crate::na::Vector2;
+
+use num::traits::Zero;
+
+#[derive(Clone, Debug)]
+pub struct ValidationConfig {
+    // input messages a peer may send per second on average
+    pub max_inputs_per_second: f32,
+    // input messages a peer may send at once, after not sending any for a while
+    pub max_input_burst: f32,
+    // chat messages a peer may send per second on average, and at once
+    pub max_chat_per_second: f32,
+    pub max_chat_burst: f32,
+    // resync requests a peer may send per second on average, and at once
+    pub max_resyncs_per_second: f32,
+    pub max_resync_burst: f32,
+    // added to the allowed displacement, as resolving collisions may push players around
+    pub displacement_tolerance: f32,
+    // peers with more violations than this within `violation_window` are kicked
+    pub max_violations: u32,
+    pub violation_window: Duration,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> ValidationConfig {
+        ValidationConfig {
+            max_inputs_per_second: 60.0,
+            max_input_burst: 30.0,
+            max_chat_per_second: 0.5,
+            max_chat_burst: 5.0,
+            max_resyncs_per_second: 0.2,
+            max_resync_burst: 2.0,
+            displacement_tolerance: 2.0,
+            max_violations: 20,
+            violation_window: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    InputRateExceeded,
+    ResyncRateExceeded,
+    ForbiddenIntent(InputIntent),
+    ImpossibleDisplacement { dx: f32, dy: f32, ticks: u64 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Violation::InputRateExceeded => write!(f, "sent input too fast"),
+            Violation::ResyncRateExceeded => write!(f, "requested resyncs too fast"),
+            Violation::ForbiddenIntent(intent) => write!(f, "sent forbidden intent {:?}", intent),
+            Violation::ImpossibleDisplacement { dx, dy, ticks } => write!(
+                f,
+                "moved ({:.1}, {:.1}) within {} ticks, which is impossible",
+                dx, dy, ticks
+            ),
+        }
+    }
+}
+
+/// Whether peers may send `intent`, the others are only meant for local players.
+pub fn is_allowed_intent(intent: InputIntent) -> bool {
+    match intent {
+        InputIntent::MoveLeft | InputIntent::MoveRight | InputIntent::MoveDown => true,
+        InputIntent::Jump => true,
+        InputIntent::Interact => true,
+        InputIntent::PrintDebugMessage => false,
+    }
+}
+
+/// A token bucket, allowing `rate` messages per second on average and `burst` at once.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f32,
+    burst: f32,
+    tokens: f32,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f32, burst: f32) -> RateLimiter {
+        RateLimiter {
+            rate,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Returns whether another message may be sent at `now`.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_s = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+
+        self.tokens = (self.tokens + elapsed_s * self.rate).min(self.burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Recent violations of a peer.
+#[derive(Debug, Default)]
+pub struct ViolationTracker {
+    recent: VecDeque<Instant>,
+}
+
+impl ViolationTracker {
+    /// Records a violation at `now`, and returns whether the peer has to be kicked.
+    pub fn record(&mut self, now: Instant, config: &ValidationConfig) -> bool {
+        while let Some(&oldest) = self.recent.front() {
+            if now.duration_since(oldest) < config.violation_window {
+                break;
+            }
+
+            self.recent.pop_front();
+        }
+
+        self.recent.push_back(now);
+
+        self.recent.len() > config.max_violations as usize
+    }
+
+    /// Number of violations within the window at the last `record`.
+    pub fn recent_count(&self) -> usize {
+        self.recent.len()
+    }
+}
+
+/// How far an entity can move within a single tick.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MovementLimits {
+    pub max_dx: f32,
+    pub max_up: f32,
+    pub max_down: f32,
+}
+
+impl MovementLimits {
+    /// Derives the limits from `Movement`, `Jump` and `Gravity` of `e`.
+    /// Entities without `Movement` are not limited.
+    pub fn of_entity(world: &mut World<LevelSystems>, e: Entity) -> Option<MovementLimits> {
+        let delta = world.services.delta_time_s;
+        let g = world.services.gravity;
+
+        let components = world.with_entity_data(&e, |en, comps| {
+            let movement = comps.movement.get(&en)?;
+            let gravity = comps.gravity.get(&en).map_or(0.0, |gravity| gravity.f);
+            let can_jump = comps.jump.has(&en);
+
+            Some((movement.max_vel, gravity, can_jump))
+        });
+
+        let (max_vel, gravity, can_jump) = components??;
+        let jump_vel = if can_jump { JUMP_RISE_VEL.y } else { 0.0 };
+
+        Some(MovementLimits {
+            max_dx: max_vel.x * delta,
+            max_up: (max_vel.y + jump_vel) * delta,
+            max_down: (max_vel.y + g * gravity) * delta,
+        })
+    }
+
+    /// Checks moving from `from` to `to` within `ticks` ticks. If that is impossible,
+    /// returns the position closest to `to` which could have been reached.
+    pub fn check(
+        &self,
+        from: Position,
+        to: Position,
+        ticks: u64,
+        tolerance: f32,
+    ) -> Result<(), Position> {
+        let ticks = ticks as f32;
+        let max_dx = self.max_dx * ticks + tolerance;
+        let max_up = self.max_up * ticks + tolerance;
+        let max_down = self.max_down * ticks + tolerance;
+
+        let dx = to.x - from.x;
+        let dy = to.y - from.y;
+
+        if dx.abs() <= max_dx && dy <= max_up && -dy <= max_down {
+            return Ok(());
+        }
+
+        Err(Position {
+            x: from.x + dx.max(-max_dx).min(max_dx),
+            y: from.y + dy.max(-max_down).min(max_up),
+        })
+    }
+}
+
+/// Remembers where players were at the last check, see `check_player`.
+#[derive(Debug, Default)]
+pub struct MovementValidator {
+    last_positions: HashMap<Entity, (Position, u64)>,
+}
+
+impl MovementValidator {
+    /// Checks how far `player` moved since the last check, and moves it back to the
+    /// furthest reachable position if it moved too far. Warps are not checked.
+    pub fn check_player(
+        &mut self,
+        world: &mut World<LevelSystems>,
+        player: Entity,
+        tolerance: f32,
+    ) -> Option<Violation> {
+        let sim_time = world.services.simulation_time;
+        let position = world.with_entity_data(&player, |en, comps| comps.position.get(&en))??;
+
+        // moving platforms and other bodies may carry players further than they can move on
+        // their own, in all ticks since the last check
+        let carried = world
+            .services
+            .carried_since_check
+            .remove(&player)
+            .unwrap_or_else(Vector2::zero);
+
+        let warped = world
+            .services
+            .replicated_events
+            .iter()
+            .any(|(_, event)| match event {
+                ReplicatedEvent::InteractionDone(event) => {
+                    let is_warp = match event.interaction {
+                        Interaction::WarpInRoom { .. } => true,
+                    };
+
+                    event.interactor == player && is_warp
+                }
+                _ => false,
+            });
+
+        let (last_position, last_time) =
+            match self.last_positions.insert(player, (position, sim_time)) {
+                Some(last) if !warped => last,
+                _ => return None,
+            };
+
+        let ticks = sim_time - last_time;
+        let limits = MovementLimits::of_entity(world, player)?;
+
+        let moved_to = Position {
+            x: position.x - carried.x,
+            y: position.y - carried.y,
+        };
+
+        let allowed = match limits.check(last_position, moved_to, ticks, tolerance) {
+            Ok(()) => return None,
+            Err(allowed) => Position {
+                x: allowed.x + carried.x,
+                y: allowed.y + carried.y,
+            },
+        };
+
+        world.move_entity(EntityOrData::Entity(player), allowed, true);
+        self.last_positions.insert(player, (allowed, sim_time));
+
+        Some(Violation::ImpossibleDisplacement {
+            dx: position.x - last_position.x,
+            dy: position.y - last_position.y,
+            ticks,
+        })
+    }
+
+    pub fn forget(&mut self, world: &mut World<LevelSystems>, player: Entity) {
+        self.last_positions.remove(&player);
+        world.services.carried_since_check.remove(&player);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::BuildData;
+
+    use super::*;
+    use crate::components::{
+        CollisionShape, CollisionType, LevelComponents, MovingPlatform, PlatformMotion, PlayerName,
+    };
+    use crate::game::prefabs;
+    use crate::nc::shape::Cuboid;
+
+    const LIMITS: MovementLimits = MovementLimits {
+        max_dx: 1.0,
+        max_up: 1.5,
+        max_down: 2.0,
+    };
+
+    #[test]
+    fn displacement_within_limits_is_allowed() {
+        let from = Position { x: 10.0, y: 10.0 };
+
+        assert_eq!(
+            LIMITS.check(from, Position { x: 14.0, y: 16.0 }, 4, 0.0),
+            Ok(())
+        );
+        assert_eq!(
+            LIMITS.check(from, Position { x: 6.0, y: 2.0 }, 4, 0.0),
+            Ok(())
+        );
+        assert_eq!(
+            LIMITS.check(from, Position { x: 10.5, y: 10.0 }, 0, 0.5),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn impossible_displacement_is_clamped() {
+        let from = Position { x: 10.0, y: 10.0 };
+
+        assert_eq!(
+            LIMITS.check(from, Position { x: 30.0, y: 10.0 }, 4, 0.0),
+            Err(Position { x: 14.0, y: 10.0 })
+        );
+        assert_eq!(
+            LIMITS.check(from, Position { x: 9.0, y: 100.0 }, 2, 1.0),
+            Err(Position { x: 9.0, y: 14.0 })
+        );
+        assert_eq!(
+            LIMITS.check(from, Position { x: 10.0, y: -50.0 }, 1, 0.0),
+            Err(Position { x: 10.0, y: 8.0 })
+        );
+    }
+
+    #[test]
+    fn rate_limiter_allows_bursts_and_refills() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(10.0, 3.0);
+
+        assert!(limiter.try_acquire(start));
+        assert!(limiter.try_acquire(start));
+        assert!(limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start));
+
+        let later = start + Duration::from_millis(150);
+        assert!(limiter.try_acquire(later));
+        assert!(!limiter.try_acquire(later));
+    }
+
+    #[test]
+    fn violations_lead_to_kick_within_window() {
+        let config = ValidationConfig {
+            max_violations: 2,
+            violation_window: Duration::from_secs(10),
+            ..ValidationConfig::default()
+        };
+        let start = Instant::now();
+        let mut tracker = ViolationTracker::default();
+
+        assert!(!tracker.record(start, &config));
+        assert!(!tracker.record(start + Duration::from_secs(5), &config));
+        // the first violation is outside of the window by now
+        assert!(!tracker.record(start + Duration::from_secs(11), &config));
+        assert!(tracker.record(start + Duration::from_secs(12), &config));
+    }
+    #[test]
+    fn riders_are_not_reported_over_several_ticks() {
+        let mut world = World::<LevelSystems>::new();
+        world.services.delta_time_s = 0.01;
+
+        // much faster than the player can walk, the top is at y = 16
+        let half_extents = Vector2::new(64.0, 8.0);
+        let motion = PlatformMotion::Oscillate {
+            from: Position { x: 0.0, y: 0.0 },
+            to: Position { x: 256.0, y: 0.0 },
+            period: 2.0,
+        };
+        world.create_entity(
+            |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                data.position.add(&entity, motion.position_at(0.0));
+                data.collision_shape.add(
+                    &entity,
+                    CollisionShape::new_single(
+                        Cuboid::new(half_extents),
+                        half_extents,
+                        CollisionType::Solid,
+                    ),
+                );
+                data.moving_platform
+                    .add(&entity, MovingPlatform::new(motion.clone()));
+            },
+        );
+
+        let start = Position { x: 48.0, y: 16.0 };
+        let player = prefabs::create_player(&mut world, start, PlayerName("rider".into()), None);
+
+        let tolerance = ValidationConfig::default().displacement_tolerance;
+        let mut validator = MovementValidator::default();
+
+        // the server runs several ticks between checks when it falls behind
+        for _ in 0..40 {
+            for _ in 0..3 {
+                world.update();
+                world.services.simulation_time += 1;
+            }
+
+            assert_eq!(validator.check_player(&mut world, player, tolerance), None);
+        }
+
+        let end = world
+            .with_entity_data(&player, |en, comps| comps.position[en])
+            .unwrap();
+        assert!(end.x - start.x > 100.0, "was not carried, at {:?}", end);
+    }
+}
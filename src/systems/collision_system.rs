@@ -25,9 +25,14 @@ impl System for CollisionSystem {
         services: &mut Self::Services,
     ) {
         // TODO `&data.collision[*e]` causes a clone, find a way which doesn't
-        services
-            .collision_world
-            .add(***e, &data.collision_shape[*e], data.position[*e]);
+        let (shape, position) = (&data.collision_shape[*e], data.position[*e]);
+
+        // nothing moves entities without velocity, except when they are moving platforms
+        if data.velocity.has(e) || data.moving_platform.has(e) {
+            services.collision_world.add(***e, shape, position);
+        } else {
+            services.collision_world.add_static(***e, shape, position);
+        }
     }
 
     fn deactivated(
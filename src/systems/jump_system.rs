@@ -7,6 +7,7 @@ use crate::application::InputIntent;
 use crate::components::LevelComponents;
 use crate::components::{Jump, JumpState};
 use crate::game::EntityOps;
+use crate::util::collision_world::Side;
 
 use crate::na::Vector2;
 
@@ -21,9 +22,12 @@ impl System for JumpSystem {
 
 const JUMP_RISE_TIME_S: f32 = 0.5;
 lazy_static! {
-    static ref JUMP_RISE_VEL: Vector2<f32> = Vector2::new(0.0, 150.0);
+    pub static ref JUMP_RISE_VEL: Vector2<f32> = Vector2::new(0.0, 150.0);
 }
 
+// share of gravity that is cancelled while sliding down a wall
+const WALL_SLIDE_ANTIGRAVITY: f32 = 0.6;
+
 impl EntityProcess for JumpSystem {
     fn process(
         &mut self,
@@ -37,27 +41,67 @@ impl EntityProcess for JumpSystem {
             let do_jump = data.intents[e].contains(&InputIntent::Jump);
             let mut jump: Jump = data.jump[e];
 
+            let on_ground = data.services.collision_world.on_ground(**e);
+
+            // the wall an entity in the air pushes against, it slides down that one
+            let pushed_wall = if on_ground {
+                None
+            } else {
+                let intents = &data.intents[e];
+                match data.services.collision_world.touching_wall(**e) {
+                    Some(Side::Left) if intents.contains(&InputIntent::MoveLeft) => {
+                        Some(Side::Left)
+                    }
+                    Some(Side::Right) if intents.contains(&InputIntent::MoveRight) => {
+                        Some(Side::Right)
+                    }
+                    _ => None,
+                }
+            };
+
             match jump.state {
                 JumpState::Idle => {
                     if !do_jump {
-                        continue;
-                    }
-
-                    if !data.services.collision_world.on_ground(**e) {
-                        continue;
+                        if pushed_wall.is_none() {
+                            continue;
+                        }
+                    } else {
+                        let drop_down = data.intents[e].contains(&InputIntent::MoveDown);
+                        if drop_down && data.services.collision_world.drop_through_platforms(**e) {
+                            continue;
+                        }
+
+                        if !on_ground && pushed_wall.is_none() {
+                            continue;
+                        }
+
+                        jump.state = JumpState::Rising;
+                        jump.jump_time_remaining = JUMP_RISE_TIME_S;
+
+                        // jumping off a wall kicks away from it
+                        if let Some(side) = pushed_wall {
+                            if let Some(movement) = data.movement.borrow(&e) {
+                                movement.vel.x = match side {
+                                    Side::Left => movement.max_vel.x,
+                                    Side::Right => -movement.max_vel.x,
+                                };
+
+                                let movement = movement.clone();
+                                data.services.changed_flags.movement.insert(**e, movement);
+                            }
+                        }
+
+                        data.play_animation(e.into(), "jump");
                     }
-
-                    jump.state = JumpState::Rising;
-                    jump.jump_time_remaining = JUMP_RISE_TIME_S;
-
-                    data.play_animation(e.into(), "jump");
                 }
                 s @ JumpState::Rising | s @ JumpState::MidairIdle => {
                     jump.jump_time_remaining -= delta;
                     if jump.jump_time_remaining <= 0.0 {
                         jump.state = JumpState::Idle;
                     }
-                    if s == JumpState::Rising && !do_jump {
+                    // hitting the ceiling ends the jump like releasing the key
+                    let on_ceiling = data.services.collision_world.on_ceiling(**e);
+                    if s == JumpState::Rising && (!do_jump || on_ceiling) {
                         jump.state = JumpState::MidairIdle;
                         data.play_animation(e.into(), "stand");
                     }
@@ -81,6 +125,7 @@ impl EntityProcess for JumpSystem {
 
                 match jump.state {
                     JumpState::Rising => *JUMP_RISE_VEL + get_antigrav_vel(),
+                    _ if pushed_wall.is_some() => get_antigrav_vel() * WALL_SLIDE_ANTIGRAVITY,
                     JumpState::MidairIdle if do_jump => get_antigrav_vel() / 2.0,
                     JumpState::MidairIdle | JumpState::Idle => Vector2::zero(),
                 }
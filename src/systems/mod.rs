@@ -3,21 +3,29 @@ pub use self::collision_system::CollisionSystem;
 pub use self::gravity_system::GravitySystem;
 pub use self::intent_system::IntentSystem;
 pub use self::interaction_system::InteractionSystem;
-pub use self::jump_system::JumpSystem;
+pub use self::jump_system::{JumpSystem, JUMP_RISE_VEL};
 pub use self::keyboard_system::KeyboardSystem;
 pub use self::movement_system::MovementSystem;
+pub use self::platform_system::PlatformSystem;
 pub use self::render_system::{RenderSystem, WorldViewport};
 pub use self::sprite_sheet_animation_system::SpriteSheetAnimationSystem;
 pub use self::velocity_system::VelocitySystem;
 
+use std::collections::HashMap;
+
 use ecs::system::{EntitySystem, InteractSystem, LazySystem};
-use ecs::ServiceManager;
+use ecs::{Entity, ServiceManager};
 
 use crate::components::{LevelChangedFlags, LevelComponents};
 
+use crate::game::events::ReplicatedEvent;
 use crate::game::ResourceStore;
+use crate::na::Vector2;
 use crate::util::CollisionWorld;
 
+use num::traits::Zero;
+
+mod bitmap_font;
 mod camera_system;
 mod collision_system;
 mod gravity_system;
@@ -26,6 +34,7 @@ pub mod interaction_system;
 mod jump_system;
 mod keyboard_system;
 mod movement_system;
+mod platform_system;
 mod render_system;
 mod sprite_sheet_animation_system;
 mod velocity_system;
@@ -36,7 +45,12 @@ pub struct LevelServices {
     pub gravity: f32,
     pub collision_world: CollisionWorld,
     pub changed_flags: LevelChangedFlags,
+    pub replicated_events: Vec<(u64, ReplicatedEvent)>,
     pub simulation_time: u64,
+    // how far moving platforms and other bodies carried or pushed entities in the last tick
+    pub carried: HashMap<Entity, Vector2<f32>>,
+    // the same, added up since `MovementValidator` last checked the entity
+    pub carried_since_check: HashMap<Entity, Vector2<f32>>,
 }
 
 impl Default for LevelServices {
@@ -47,7 +61,25 @@ impl Default for LevelServices {
             gravity: 150.0,
             collision_world: CollisionWorld::new(),
             changed_flags: Default::default(),
+            replicated_events: Vec::new(),
             simulation_time: 0,
+            carried: HashMap::new(),
+            carried_since_check: HashMap::new(),
+        }
+    }
+}
+
+impl LevelServices {
+    /// Records that `e` was carried or pushed by `by` this tick. Only the movement of entities
+    /// with `Movement` is validated, so only theirs is added up until the next check.
+    pub fn add_carried(&mut self, e: Entity, by: Vector2<f32>, has_movement: bool) {
+        *self.carried.entry(e).or_insert_with(Vector2::zero) += by;
+
+        if has_movement {
+            *self
+                .carried_since_check
+                .entry(e)
+                .or_insert_with(Vector2::zero) += by;
         }
     }
 }
@@ -60,6 +92,11 @@ systems! {
             keyboard_system: EntitySystem<KeyboardSystem> = EntitySystem::new(
                 KeyboardSystem::new(),
                 aspect!(<LevelComponents> all: [keyboard_input])),
+            // riders are carried along before they move on their own
+            platform_system: EntitySystem<PlatformSystem> = EntitySystem::new(
+                PlatformSystem,
+                aspect!(<LevelComponents> all: [moving_platform, position, collision_shape]),
+            ),
             gravity_system: EntitySystem<GravitySystem> = EntitySystem::new(
                 GravitySystem,
                 aspect!(<LevelComponents> all: [gravity, velocity]),
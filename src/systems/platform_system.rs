@@ -0,0 +1,86 @@
+use ecs::system::EntityProcess;
+use ecs::{DataHelper, EntityIter, System};
+
+use super::LevelServices;
+
+use crate::components::{LevelComponents, Position};
+use crate::game::EntityOps;
+
+pub struct PlatformSystem;
+
+impl System for PlatformSystem {
+    type Components = LevelComponents;
+    type Services = LevelServices;
+}
+
+impl EntityProcess for PlatformSystem {
+    fn process(
+        &mut self,
+        entities: EntityIter<'_, LevelComponents>,
+        data: &mut DataHelper<LevelComponents, LevelServices>,
+    ) {
+        let delta = data.services.delta_time_s;
+
+        data.services.carried.clear();
+
+        for e in entities {
+            let platform = {
+                let platform = &mut data.moving_platform[e];
+                platform.time += delta;
+                platform.clone()
+            };
+            data.services
+                .changed_flags
+                .moving_platform
+                .insert(**e, platform.clone());
+
+            let last_pos = data.position[e];
+            let new_pos = platform.position();
+            if new_pos == last_pos {
+                continue;
+            }
+
+            // only entities that move on their own are carried and pushed, not the room's tiles
+            let mut riders = data.services.collision_world.riders(**e);
+            riders.retain(|rider| {
+                data.with_entity_data(rider, |en, comps| comps.velocity.has(&en))
+                    .unwrap_or(false)
+            });
+
+            let coll_shape = data.collision_shape[e].clone();
+            let pushes = data
+                .services
+                .collision_world
+                .move_kinematic(**e, &coll_shape, new_pos);
+
+            data.position[e] = new_pos;
+            data.services.changed_flags.position.insert(**e, new_pos);
+
+            let displacement = new_pos.as_vec() - last_pos.as_vec();
+            let moves = riders.iter().map(|rider| (*rider, displacement)).chain(
+                pushes
+                    .into_iter()
+                    .filter(|(pushed, _)| !riders.contains(pushed)),
+            );
+
+            for (other, by) in moves {
+                let moved = data.with_entity_data(&other, |en, comps| {
+                    let velocity = comps.velocity.borrow(&en)?;
+                    let position = comps.position[en];
+                    velocity.last_pos = position;
+
+                    let position = Position {
+                        x: position.x + by.x,
+                        y: position.y + by.y,
+                    };
+                    Some((position, comps.movement.has(&en)))
+                });
+
+                if let Some(Some((position, has_movement))) = moved {
+                    data.move_entity(other.into(), position, false);
+                    data.services.add_carried(other, by, has_movement);
+                }
+            }
+        }
+    }
+}
@@ -14,8 +14,14 @@ use crate::components::{Facing, SpriteLayer};
 
 use hprof;
 
+use super::bitmap_font;
 use super::LevelServices;
 
+// size of one pixel of the name font, in world units
+const NAME_PIXEL_SIZE: f32 = 2.0;
+// space between the top of a sprite and its name
+const NAME_MARGIN: f32 = 4.0;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 struct Vertex {
     position: [f32; 2],
@@ -52,6 +58,16 @@ pub struct RenderSystem {
     render_physics_debug: bool,
 }
 
+// two triangles covering the square with the bottom left corner at `(x, y)`
+fn push_square(vertices: &mut Vec<Vertex>, x: f32, y: f32, size: f32) {
+    for &(dx, dy) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+        vertices.push(Vertex {
+            position: [x + dx * size, y + dy * size],
+            tex_coords: [dx, dy],
+        });
+    }
+}
+
 impl RenderSystem {
     pub fn new(display: glium::Display) -> RenderSystem {
         let vertex_buffer = {
@@ -258,6 +274,9 @@ impl InteractProcess for RenderSystem {
                                 let color = match cs.collision_type() {
                                     CollisionType::Solid => Vector4::new(1.0f32, 0.0, 0.0, 1.0),
                                     CollisionType::Trigger => Vector4::new(0.0f32, 1.0, 0.0, 1.0),
+                                    CollisionType::OneWayPlatform => {
+                                        Vector4::new(0.0f32, 0.0, 1.0, 1.0)
+                                    }
                                 };
 
                                 let uniforms = uniform! {
@@ -292,6 +311,73 @@ impl InteractProcess for RenderSystem {
                         }
                     }
                 }
+
+                let _g = hprof::enter("names");
+
+                let mut name_vertices = Vec::new();
+
+                for e in &sprites {
+                    let name = match data.player_name.get(e) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+
+                    let position = data.position[*e];
+                    let (width, height) = data
+                        .sprite
+                        .get(e)
+                        .map_or((0.0, 0.0), |sprite| (sprite.info.width, sprite.info.height));
+
+                    let text_width = bitmap_font::text_width(&name.0) as f32 * NAME_PIXEL_SIZE;
+                    let left = (position.x + width / 2.0 - text_width / 2.0).round();
+                    let bottom = (position.y + height + NAME_MARGIN).round();
+
+                    bitmap_font::for_each_pixel(&name.0, |x, y| {
+                        push_square(
+                            &mut name_vertices,
+                            left + x as f32 * NAME_PIXEL_SIZE,
+                            bottom + y as f32 * NAME_PIXEL_SIZE,
+                            NAME_PIXEL_SIZE,
+                        );
+                    });
+                }
+
+                if !name_vertices.is_empty() {
+                    let vertex_buffer =
+                        glium::VertexBuffer::new(&self.display, &name_vertices).unwrap();
+
+                    // the vertices are in world coordinates already
+                    let view_pos = Vector2::new(
+                        -(cpos.x - camera.world_viewport.width / 2.0),
+                        -(cpos.y - camera.world_viewport.height / 2.0),
+                    );
+                    let scale = Vector2::new(1.0f32, 1.0);
+                    let color = Vector4::new(1.0f32, 1.0, 1.0, 1.0);
+
+                    let uniforms = uniform! {
+                        view_pos: *view_pos.as_ref(),
+                        scale: *scale.as_ref(),
+                        proj: *ortho_proj.as_ref(),
+                        invert_tex_x: false,
+                        win_scale: *screen_size.as_ref(),
+                        win_trans: *camera.screen_viewport.mins().coords.as_ref(),
+                        depth: SpriteLayer::MAX_DEPTH,
+                        color: *color.as_ref(),
+                    };
+
+                    // drawn last and without depth test, so names are never hidden
+                    target
+                        .draw(
+                            &vertex_buffer,
+                            glium::index::NoIndices(PrimitiveType::TrianglesList),
+                            &self.physics_program,
+                            &uniforms,
+                            &Default::default(),
+                        )
+                        .unwrap();
+                }
+
+                drop(_g);
             }
         }
 
@@ -29,8 +29,6 @@ impl EntityProcess for SpriteSheetAnimationSystem {
         let delta_s = data.services.delta_time_s;
 
         for e in entities {
-            let mut changed = false;
-
             let new_si = {
                 let ssa: &mut SpriteSheetAnimation = &mut data.sprite_sheet_animation[e];
 
@@ -40,20 +38,13 @@ impl EntityProcess for SpriteSheetAnimationSystem {
                     ssa.current_frame = (ssa.current_frame + 1) % ssa.animation.num_frames;
                     ssa.frame_time_remaining +=
                         ssa.animation.frame_durations[ssa.current_frame as usize];
-                    changed = true;
                 }
 
                 ssa.animation.create_sprite_info(ssa.current_frame)
             };
 
+            // not marked as changed, clients advance their animations locally
             data.sprite[e].info = new_si;
-
-            if changed {
-                data.services
-                    .changed_flags
-                    .sprite
-                    .insert(**e, data.sprite[e].clone());
-            }
         }
     }
 }
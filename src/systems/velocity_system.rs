@@ -1,11 +1,22 @@
+use std::collections::HashMap;
+
 use ecs::system::EntityProcess;
-use ecs::{DataHelper, EntityIter, System};
+use ecs::{DataHelper, Entity, EntityIter, System};
 
 use super::LevelServices;
 
-use crate::components::LevelComponents;
+use crate::components::{LevelComponents, Position};
 use crate::game::EntityOps;
 
+use crate::na::Vector2;
+
+// how often overlapping dynamic bodies are pushed apart per tick, pushing one body into the next
+// needs another pass
+const SEPARATION_PASSES: usize = 4;
+
+// how many bodies standing on each other carry the ones on top
+const MAX_STACK_HEIGHT: usize = 8;
+
 pub struct VelocitySystem;
 
 impl System for VelocitySystem {
@@ -13,14 +24,92 @@ impl System for VelocitySystem {
     type Services = LevelServices;
 }
 
+/// How far an entity standing on `supports` is carried sideways, the average of how far those
+/// moved this tick and are carried themselves. What this system does not move, like the room or
+/// moving platforms, does not carry it.
+fn carried_x(
+    supports: &HashMap<Entity, Vec<Entity>>,
+    moved_x: &HashMap<Entity, f32>,
+    standing_on: &[Entity],
+    depth: usize,
+) -> f32 {
+    if depth == 0 || standing_on.is_empty() {
+        return 0.0;
+    }
+
+    let total: f32 = standing_on
+        .iter()
+        .map(|support| match moved_x.get(support) {
+            Some(moved) => moved + carried_x(supports, moved_x, &supports[support], depth - 1),
+            None => 0.0,
+        })
+        .sum();
+
+    total / standing_on.len() as f32
+}
+
+/// Moves `e` by `by`, as something else pushed or carried it.
+fn push(data: &mut DataHelper<LevelComponents, LevelServices>, e: Entity, by: Vector2<f32>) {
+    let moved = data.with_entity_data(&e, |en, comps| {
+        let position = comps.position[en];
+        if let Some(velocity) = comps.velocity.borrow(&en) {
+            velocity.last_pos = position;
+        }
+
+        let position = Position {
+            x: position.x + by.x,
+            y: position.y + by.y,
+        };
+        (position, comps.movement.has(&en))
+    });
+
+    if let Some((position, has_movement)) = moved {
+        data.move_entity(e.into(), position, false);
+        data.services.add_carried(e, by, has_movement);
+    }
+}
+
+/// Pushes apart the dynamic bodies which moved into each other, as they do not block each other
+/// while moving.
+fn separate_bodies(data: &mut DataHelper<LevelComponents, LevelServices>) {
+    for _ in 0..SEPARATION_PASSES {
+        let corrections = data.services.collision_world.separate_bodies();
+
+        if corrections.is_empty() {
+            break;
+        }
+
+        for (e, by) in corrections {
+            push(data, e, by);
+        }
+    }
+}
+
 impl EntityProcess for VelocitySystem {
     fn process(
         &mut self,
         entities: EntityIter<'_, LevelComponents>,
         data: &mut DataHelper<LevelComponents, LevelServices>,
     ) {
+        // what the entities stand on is looked up before any of them moves, so the order they
+        // move in does not matter
+        let mut supports = HashMap::new();
+        // where they were on the x axis at the start of the tick, before moving platforms
+        // carried them
+        let mut starts_x = HashMap::new();
+        let mut moves = Vec::new();
+
         for e in entities {
+            supports.insert(**e, data.services.collision_world.standing_on(**e));
+
+            let by_platform = data.services.carried.get(&**e).map_or(0.0, |by| by.x);
+            if let Some(position) = data.position.get(&e) {
+                starts_x.insert(**e, position.x - by_platform);
+            }
+
             let velocity = data.velocity[e];
+            data.velocity[e].vx = 0.0;
+            data.velocity[e].vy = 0.0;
 
             if velocity.vx == 0.0 && velocity.vy == 0.0 {
                 continue;
@@ -32,11 +121,157 @@ impl EntityProcess for VelocitySystem {
                 position.x += velocity.vx;
                 position.y += velocity.vy;
 
-                data.move_entity(e.into(), position, false);
+                moves.push((**e, position));
             }
+        }
 
-            data.velocity[e].vx = 0.0;
-            data.velocity[e].vy = 0.0;
+        for (e, position) in moves {
+            data.move_entity(e.into(), position, false);
+        }
+
+        separate_bodies(data);
+
+        // how far the entities moved on their own, were pushed and were carried by moving
+        // platforms this tick
+        let mut moved_x = HashMap::new();
+        for (e, start_x) in starts_x {
+            if let Some(position) = data.with_entity_data(&e, |en, comps| comps.position[en]) {
+                moved_x.insert(e, position.x - start_x);
+            }
+        }
+
+        let mut carried: Vec<(Entity, f32)> = supports
+            .iter()
+            .map(|(e, standing_on)| {
+                let by = carried_x(&supports, &moved_x, standing_on, MAX_STACK_HEIGHT);
+                (*e, by)
+            })
+            .filter(|&(_, by)| by != 0.0)
+            .collect();
+
+        if carried.is_empty() {
+            return;
+        }
+
+        carried.sort_by_key(|(e, _)| e.id());
+        for (e, by) in carried {
+            push(data, e, Vector2::new(by, 0.0));
+        }
+
+        separate_bodies(data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::{BuildData, World};
+
+    use super::*;
+    use crate::components::{CollisionShape, CollisionType, Gravity, Velocity};
+    use crate::nc::shape::Cuboid;
+    use crate::systems::LevelSystems;
+
+    // a floor, a body walking right, a crate in its way and a box on the crate. the entities are
+    // created in `order`, so the systems process them in that order.
+    fn push_crate(order: &[usize], ticks: u32) -> Vec<Position> {
+        let mut world = World::<LevelSystems>::new();
+        world.services.delta_time_s = 0.01;
+
+        let bodies = [
+            (Position { x: 0.0, y: 0.0 }, Vector2::new(128.0, 16.0), None),
+            (
+                Position { x: 40.0, y: 32.0 },
+                Vector2::new(5.0, 5.0),
+                Some(1.0),
+            ),
+            (
+                Position { x: 60.0, y: 32.0 },
+                Vector2::new(10.0, 10.0),
+                Some(2.0),
+            ),
+            (
+                Position { x: 65.0, y: 52.0 },
+                Vector2::new(5.0, 5.0),
+                Some(1.0),
+            ),
+        ];
+
+        let mut entities = vec![None; bodies.len()];
+        for &i in order {
+            let (position, half_extents, mass) = bodies[i];
+            let shape = CollisionShape::new_single(
+                Cuboid::new(half_extents),
+                half_extents,
+                CollisionType::Solid,
+            );
+
+            entities[i] = Some(world.create_entity(
+                |entity: BuildData<'_, LevelComponents>, data: &mut LevelComponents| {
+                    data.position.add(&entity, position);
+
+                    match mass {
+                        Some(mass) => {
+                            data.collision_shape.add(&entity, shape.with_mass(mass));
+                            data.velocity.add(
+                                &entity,
+                                Velocity {
+                                    vx: 0.0,
+                                    vy: 0.0,
+                                    last_pos: position,
+                                },
+                            );
+                            data.gravity.add(&entity, Gravity::new());
+                        }
+                        None => data.collision_shape.add(&entity, shape),
+                    }
+                },
+            ));
+        }
+
+        let entities: Vec<Entity> = entities.into_iter().map(Option::unwrap).collect();
+        for _ in 0..ticks {
+            world.with_entity_data(&entities[1], |en, comps| comps.velocity[en].vx = 1.0);
+            world.update();
+        }
+
+        entities
+            .iter()
+            .map(|e| {
+                world
+                    .with_entity_data(e, |en, comps| comps.position[en])
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn bodies_push_and_carry_each_other_in_any_order() {
+        let positions = push_crate(&[0, 1, 2, 3], 40);
+        let (walker, crate_, on_crate) = (positions[1], positions[2], positions[3]);
+
+        // it walked 10 until it reached the crate, then pushed the twice as heavy crate for 30
+        assert_near(crate_.x, 70.0);
+        assert_near(walker.x, crate_.x - 10.0);
+        // the box stayed on the crate
+        assert_near(on_crate.x, crate_.x + 5.0);
+        assert_near(on_crate.y, crate_.y + 20.0);
+        for body in &positions[1..] {
+            assert!(
+                body.y >= 32.0 - 0.001,
+                "fell through the floor to {:?}",
+                body
+            );
+        }
+
+        for order in &[[3, 2, 1, 0], [2, 0, 3, 1]] {
+            for (a, b) in positions.iter().zip(push_crate(order, 40)) {
+                assert_near(a.x, b.x);
+                assert_near(a.y, b.y);
+            }
         }
     }
 }
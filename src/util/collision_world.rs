@@ -12,35 +12,110 @@ use crate::nc::math::{Point, Vector};
 use crate::nc::partitioning::BVH;
 use crate::nc::partitioning::{DBVTLeaf, DBVTLeafId, DBVT};
 use crate::nc::query::visitors::BoundingVolumeInterferencesCollector;
-use crate::nc::query::visitors::RayInterferencesCollector;
-use crate::nc::query::Ray;
 
-use crate::components::{self, CollisionType, Position};
+use crate::components::{self, CollisionLayers, CollisionType, Position, Slope};
+
+use super::tile_grid::TileGrid;
 
 use ordered_float::NotNan;
 
 type CollisionTreeLeafId = DBVTLeafId;
-type CollisionTreeLeaf = DBVTLeaf<f32, Entity, AABB<f32>>;
 
 struct CollisionTreeLeafs {
-    x: CollisionTreeLeafId,
-    y: CollisionTreeLeafId,
+    // in `dbvt_x` and `dbvt_y`, static entities are in the `grid` instead
+    leafs: Option<(CollisionTreeLeafId, CollisionTreeLeafId)>,
+    aabb_x: AABB<f32>,
+    aabb_y: AABB<f32>,
     coll_type: CollisionType,
+    slope: Option<Slope>,
+    layers: CollisionLayers,
+    mask: CollisionLayers,
+    mass: Option<f32>,
+}
+
+impl CollisionTreeLeafs {
+    // whether these collide with an entity on `layers` with `mask`, both have to agree
+    fn collides_with(&self, layers: CollisionLayers, mask: CollisionLayers) -> bool {
+        self.layers.intersects(mask) && self.mask.intersects(layers)
+    }
+
+    fn aabb(&self, axis: Axis) -> &AABB<f32> {
+        match axis {
+            Axis::X => &self.aabb_x,
+            Axis::Y => &self.aabb_y,
+        }
+    }
 }
 
 pub struct CollisionWorld {
     dbvt_x: DBVT<f32, Entity, AABB<f32>>,
     dbvt_y: DBVT<f32, Entity, AABB<f32>>,
+    // static entities, which most of the room is made of
+    grid: TileGrid,
     mapping: HashMap<Entity, CollisionTreeLeafs>,
-    on_ground_cache: RefCell<HashMap<Entity, bool>>,
+    contacts_cache: RefCell<HashMap<Entity, CachedContacts>>,
+    // one-way platforms entities are dropping through, until they stop overlapping them
+    dropping_through: HashMap<Entity, SmallVec<[Entity; 2]>>,
 }
 
+// how far apart the faces of entities may be to still be in contact, resolving collisions
+// leaves small errors
+const CONTACT_DISTANCE: f32 = 0.001;
+
+// how far below the top of a one-way platform the bottom of an entity may have been before
+// moving to still land on it, resolving collisions leaves small errors
+const PLATFORM_TOLERANCE: f32 = 0.01;
+
+// same for standing on slopes, the surface is interpolated so it is not exact either
+const SLOPE_TOLERANCE: f32 = 0.01;
+
+// how far an entity walking down a slope is snapped down onto it, on top of its movement along
+// the x axis. keeps it from hopping down the slope.
+const SLOPE_SNAP_DISTANCE: f32 = 1.0;
+
+// entities overlapping by less than this only touch, they are not in each other's way. also how
+// far they may already overlap at the start of a sweep, from errors of earlier resolutions.
+const TOUCH_TOLERANCE: f32 = 0.001;
+
+// size of the cells of the grid static entities are kept in, in pixels. the size of a tile, so
+// each tile is in a single cell and entities the size of the player only look at a few cells.
+const GRID_CELL_SIZE: f32 = 32.0;
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 enum Axis {
     X,
     Y,
 }
 
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Face {
+    Bottom,
+    Top,
+    Left,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+// results of the contact queries, until an entity is moved
+#[derive(Debug, Default, Copy, Clone)]
+struct CachedContacts {
+    on_ground: Option<bool>,
+    on_ceiling: Option<bool>,
+    touching_wall: Option<Option<Side>>,
+}
+
+fn axis_normal(axis: Axis, sign: f32) -> Vector<f32> {
+    match axis {
+        Axis::X => Vector::new(sign.signum(), 0.0),
+        Axis::Y => Vector::new(0.0, sign.signum()),
+    }
+}
+
 fn find_depth(
     dyn_ent: &AABB<f32>,
     dyn_last: Point<f32>,
@@ -53,6 +128,15 @@ fn find_depth(
         return None;
     }
 
+    // only touching on the other axis, e.g. sliding along a wall
+    let perpendicular = match axis {
+        X => Y,
+        Y => X,
+    };
+    if overlap(dyn_ent, stat, perpendicular) <= TOUCH_TOLERANCE {
+        return None;
+    }
+
     let min_dist = match axis {
         X => dyn_ent.half_extents().x + stat.half_extents().x,
         Y => dyn_ent.half_extents().y + stat.half_extents().y,
@@ -79,16 +163,154 @@ fn find_depth(
     Some(dir * depth)
 }
 
+/// How far the extents of `a` and `b` on `axis` overlap, negative if they are apart.
+fn overlap(a: &AABB<f32>, b: &AABB<f32>, axis: Axis) -> f32 {
+    match axis {
+        Axis::X => a.maxs().x.min(b.maxs().x) - a.mins().x.max(b.mins().x),
+        Axis::Y => a.maxs().y.min(b.maxs().y) - a.mins().y.max(b.mins().y),
+    }
+}
+
+/// Height of the surface of the sloped tile `stat` at `x`, if `x` is above the tile.
+fn slope_surface(stat: &AABB<f32>, slope: Slope, x: f32) -> Option<f32> {
+    if x < stat.mins().x || x > stat.maxs().x {
+        return None;
+    }
+
+    let t = (x - stat.mins().x) / (stat.maxs().x - stat.mins().x);
+
+    Some(stat.mins().y + slope.height_at(t) * (stat.maxs().y - stat.mins().y))
+}
+
+/// Normal of the surface of the sloped tile `stat`.
+fn slope_normal(stat: &AABB<f32>, slope: Slope) -> Vector<f32> {
+    let rise = (slope.height_at(1.0) - slope.height_at(0.0)) * (stat.maxs().y - stat.mins().y);
+
+    Vector::new(-rise, stat.maxs().x - stat.mins().x).normalize()
+}
+
+/// The half-planes `normal · p <= offset` bounding `aabb`, or the part of it below `slope`,
+/// grown by `half_extents` on every side. Testing a point against them tests a box of that
+/// size against the shape.
+fn shape_planes(
+    aabb: &AABB<f32>,
+    slope: Option<Slope>,
+    half_extents: &Vector<f32>,
+) -> SmallVec<[(Vector<f32>, f32); 5]> {
+    let (mins, maxs) = (aabb.mins(), aabb.maxs());
+
+    let mut planes = SmallVec::new();
+    planes.push((Vector::new(-1.0, 0.0), half_extents.x - mins.x));
+    planes.push((Vector::new(1.0, 0.0), maxs.x + half_extents.x));
+    planes.push((Vector::new(0.0, -1.0), half_extents.y - mins.y));
+    planes.push((Vector::new(0.0, 1.0), maxs.y + half_extents.y));
+
+    if let Some(slope) = slope {
+        let normal = slope_normal(aabb, slope);
+        let on_surface = Vector::new(mins.x, mins.y + slope.height_at(0.0) * (maxs.y - mins.y));
+        // how far the box reaches in the direction of the normal
+        let support = normal.x.abs() * half_extents.x + normal.y.abs() * half_extents.y;
+
+        planes.push((normal, normal.dot(&on_surface) + support));
+    }
+
+    planes
+}
+
+/// Where the ray from `origin` along `dir` enters the area bounded by `planes`, see
+/// `shape_planes`, and the normal of the plane it enters through. The normal is zero if the ray
+/// starts inside.
+fn ray_enters(
+    origin: &Point<f32>,
+    dir: &Vector<f32>,
+    planes: &[(Vector<f32>, f32)],
+) -> Option<(f32, Vector<f32>)> {
+    let mut enter = 0.0;
+    let mut exit = std::f32::INFINITY;
+    let mut normal = Vector::zeros();
+
+    for (plane_normal, offset) in planes {
+        let distance = offset - plane_normal.dot(&origin.coords);
+        let speed = plane_normal.dot(dir);
+
+        if speed == 0.0 {
+            // parallel to the plane, on its outside
+            if distance < 0.0 {
+                return None;
+            }
+
+            continue;
+        }
+
+        let toi = distance / speed;
+        if speed < 0.0 {
+            if toi > enter {
+                enter = toi;
+                normal = *plane_normal;
+            }
+        } else {
+            exit = exit.min(toi);
+        }
+    }
+
+    if enter <= exit {
+        Some((enter, normal))
+    } else {
+        None
+    }
+}
+
+/// Like `find_depth` on the y axis, but for the sloped tile `stat`: pushes `dyn_ent` onto the
+/// surface, and snaps it down onto it if it walks down the slope by at most `snap`. Also
+/// returns the normal of the side of the tile that was hit.
+fn find_slope_depth(
+    dyn_ent: &AABB<f32>,
+    dyn_last: Option<&AABB<f32>>,
+    stat: &AABB<f32>,
+    slope: Slope,
+    snap: f32,
+) -> Option<(f32, Vector<f32>)> {
+    // entities stand on slopes with the middle of their bottom
+    let surface = slope_surface(stat, slope, dyn_ent.center().x)?;
+    let bottom = dyn_ent.mins().y;
+
+    match dyn_last {
+        // the bottom of the tile is flat, like for solid tiles
+        Some(last) if last.maxs().y <= stat.mins().y + SLOPE_TOLERANCE => {
+            let depth = stat.mins().y - dyn_ent.maxs().y;
+
+            if depth < 0.0 {
+                Some((depth, Vector::new(0.0, -1.0)))
+            } else {
+                None
+            }
+        }
+        _ if bottom < surface => Some((surface - bottom, slope_normal(stat, slope))),
+        Some(last) if bottom <= last.mins().y && bottom - surface <= snap => {
+            Some((surface - bottom, slope_normal(stat, slope)))
+        }
+        _ => None,
+    }
+}
+
 pub struct CollisionResult {
     depth: f32,
+    // of the surface of `other` that was hit
+    normal: Vector<f32>,
     other: Entity,
     other_coll_type: CollisionType,
 }
 
 impl CollisionResult {
-    fn new(depth: f32, other: Entity, other_coll_type: CollisionType) -> CollisionResult {
+    fn new(
+        depth: f32,
+        normal: Vector<f32>,
+        other: Entity,
+        other_coll_type: CollisionType,
+    ) -> CollisionResult {
         CollisionResult {
             depth,
+            normal,
             other,
             other_coll_type,
         }
@@ -101,6 +323,86 @@ pub struct Collision {
     pub collided: Entity,
 }
 
+/// A blocking entity that stopped a moving one, and the normal of its surface where it did.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Contact {
+    pub other: Entity,
+    pub normal: Vector<f32>,
+}
+
+/// Where `CollisionWorld::move_entity` moved an entity to, and what stopped it on the way.
+#[derive(Clone, Debug)]
+pub struct MoveResult {
+    pub position: Position,
+    pub contacts: SmallVec<[Contact; 2]>,
+}
+
+/// Which entities the queries of a `CollisionWorld` find.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QueryFilter {
+    // one bit per accepted `CollisionType`
+    types: u8,
+    layers: CollisionLayers,
+    excluded: Option<Entity>,
+}
+
+impl Default for QueryFilter {
+    fn default() -> QueryFilter {
+        QueryFilter::new()
+    }
+}
+
+impl QueryFilter {
+    /// Finds every entity.
+    pub fn new() -> QueryFilter {
+        QueryFilter {
+            types: !0,
+            layers: CollisionLayers::ALL,
+            excluded: None,
+        }
+    }
+
+    /// Only finds entities of one of `types`.
+    pub fn types(mut self, types: &[CollisionType]) -> QueryFilter {
+        self.types = types.iter().fold(0, |bits, t| bits | type_bit(*t));
+        self
+    }
+
+    /// Only finds entities on at least one of `layers`.
+    pub fn layers(mut self, layers: CollisionLayers) -> QueryFilter {
+        self.layers = layers;
+        self
+    }
+
+    /// Never finds `e`, e.g. the entity asking.
+    pub fn excluding(mut self, e: Entity) -> QueryFilter {
+        self.excluded = Some(e);
+        self
+    }
+
+    fn accepts(&self, e: Entity, leafs: &CollisionTreeLeafs) -> bool {
+        self.excluded != Some(e)
+            && self.types & type_bit(leafs.coll_type) != 0
+            && self.layers.intersects(leafs.layers)
+    }
+}
+
+fn type_bit(coll_type: CollisionType) -> u8 {
+    1 << coll_type as u8
+}
+
+/// Where a ray or a cast box first touches an entity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub entity: Entity,
+    // distance travelled until the hit
+    pub toi: f32,
+    // where the ray, or the center of the box, is at the hit
+    pub point: Point<f32>,
+    // of the surface that was hit, zero if it started inside the entity
+    pub normal: Vector<f32>,
+}
+
 impl Default for CollisionWorld {
     fn default() -> Self {
         Self::new()
@@ -112,102 +414,208 @@ impl CollisionWorld {
         CollisionWorld {
             dbvt_x: DBVT::new(),
             dbvt_y: DBVT::new(),
+            grid: TileGrid::new(GRID_CELL_SIZE),
             mapping: HashMap::new(),
-            on_ground_cache: RefCell::new(HashMap::new()),
+            contacts_cache: RefCell::new(HashMap::new()),
+            dropping_through: HashMap::new(),
         }
     }
 
     pub fn add(&mut self, e: Entity, coll: &components::CollisionShape, pos: Position) {
+        self.add_leafs(e, coll, pos, false);
+    }
+
+    /// Like `add`, for entities that do not move, like the tiles of the room. They are kept in
+    /// a grid instead of the trees, which is faster to search and to move the other entities
+    /// through. Should they be moved anyway, they are moved to the trees.
+    pub fn add_static(&mut self, e: Entity, coll: &components::CollisionShape, pos: Position) {
+        self.add_leafs(e, coll, pos, true);
+    }
+
+    fn add_leafs(
+        &mut self,
+        e: Entity,
+        coll: &components::CollisionShape,
+        pos: Position,
+        is_static: bool,
+    ) {
         // if it already existed, just remove it
         self.remove(e);
 
-        let x_leaf = DBVTLeaf::new(coll.aabb_x(Vector::new(pos.x, pos.y)), e);
-        let y_leaf = DBVTLeaf::new(coll.aabb_y(Vector::new(pos.x, pos.y)), e);
+        let mut leafs = CollisionTreeLeafs {
+            leafs: None,
+            aabb_x: coll.aabb_x(pos.as_vec()),
+            aabb_y: coll.aabb_y(pos.as_vec()),
+            coll_type: coll.collision_type(),
+            slope: coll.slope(),
+            layers: coll.layers(),
+            mask: coll.mask(),
+            mass: coll.mass(),
+        };
 
-        // then add leafs for both trees
-        self.mapping.insert(
-            e,
-            CollisionTreeLeafs {
-                x: self.dbvt_x.insert(x_leaf),
-                y: self.dbvt_y.insert(y_leaf),
-                coll_type: coll.collision_type(),
-            },
-        );
+        if is_static {
+            self.grid.insert(e, &leafs.aabb_x.merged(&leafs.aabb_y));
+        } else {
+            self.insert_leafs(e, &mut leafs);
+        }
+
+        self.mapping.insert(e, leafs);
+    }
+
+    // adds `leafs` to both trees
+    fn insert_leafs(&mut self, e: Entity, leafs: &mut CollisionTreeLeafs) {
+        leafs.leafs = Some((
+            self.dbvt_x.insert(DBVTLeaf::new(leafs.aabb_x, e)),
+            self.dbvt_y.insert(DBVTLeaf::new(leafs.aabb_y, e)),
+        ));
+    }
+
+    // takes `leafs` out of the trees, or the grid
+    fn remove_leafs(&mut self, e: Entity, leafs: &mut CollisionTreeLeafs) {
+        match leafs.leafs.take() {
+            Some((leaf_x, leaf_y)) => {
+                self.dbvt_x.remove(leaf_x);
+                self.dbvt_y.remove(leaf_y);
+            }
+            None => self.grid.remove(e, &leafs.aabb_x.merged(&leafs.aabb_y)),
+        }
+    }
+
+    // adds the entities in the trees and the grid whose box on `axis` intersects `aabb` to
+    // `colls`, like visiting one of the trees
+    fn interferences(&self, axis: Axis, aabb: &AABB<f32>, colls: &mut Vec<Entity>) {
+        let dbvt = match axis {
+            Axis::X => &self.dbvt_x,
+            Axis::Y => &self.dbvt_y,
+        };
+        dbvt.visit(&mut BoundingVolumeInterferencesCollector::new(aabb, colls));
+
+        let from_trees = colls.len();
+        self.grid.visit(aabb, colls);
+
+        let mapping = &self.mapping;
+        let mut idx = 0;
+        colls.retain(|other| {
+            idx += 1;
+            idx <= from_trees || mapping[other].aabb(axis).intersects(aabb)
+        });
+    }
+
+    /// Whether `platform` stops `e`, which moves to `aabb` on the y axis. One-way platforms
+    /// only stop entities coming from above, that did not start dropping through them.
+    fn lands_on_platform(
+        &self,
+        e: Entity,
+        coll: &components::CollisionShape,
+        aabb: &AABB<f32>,
+        last_pos: Option<&Position>,
+        platform: Entity,
+    ) -> bool {
+        let dropping = self
+            .dropping_through
+            .get(&e)
+            .map(|platforms| platforms.contains(&platform))
+            .unwrap_or(false);
+
+        if dropping {
+            return false;
+        }
+
+        let last_pos = match last_pos {
+            Some(last_pos) => last_pos,
+            None => return false,
+        };
+
+        let platform_top = self.mapping[&platform].aabb_y.maxs().y;
+        let last_bottom = coll.aabb_y(last_pos.as_vec()).mins().y;
+
+        aabb.mins().y <= last_bottom && last_bottom >= platform_top - PLATFORM_TOLERANCE
     }
 
     // move an entity along one exis, return collision depth if a collision occured
     fn move_axis(
         &mut self,
-        leafs: (&mut CollisionTreeLeaf, &mut CollisionTreeLeaf),
+        e: Entity,
+        last_aabbs: (&AABB<f32>, &AABB<f32>),
         coll: &components::CollisionShape,
         new_pos: Position,
-        _last_pos: Option<&Position>, // currently unused, see comment below
+        last_pos: Option<&Position>,
         axis: Axis,
     ) -> SmallVec<[self::CollisionResult; 4]> {
         let aabb = match axis {
             Axis::X => coll.aabb_x(new_pos.as_vec()),
             Axis::Y => coll.aabb_y(new_pos.as_vec()),
             // this caused problems, but was taken from the cavestory tutorial.
-            // keeping it to not forget about it!
+            // keeping it to not forget about it! fast entities are handled by `sweep_axis` now.
             // Axis::X => coll.aabb_x(new_pos.as_vec()).merged(&coll.aabb_x(last_pos.as_vec())),
             // Axis::Y => coll.aabb_y(new_pos.as_vec()).merged(&coll.aabb_y(last_pos.as_vec())),
         };
 
-        let leaf = match axis {
-            Axis::X => leafs.0,
-            Axis::Y => leafs.1,
-            // Axis::X => self.dbvt_x[leafs.x],
-            // Axis::Y => self.dbvt_y[leafs.y],
+        let last_center = match axis {
+            Axis::X => last_aabbs.0.center(),
+            Axis::Y => last_aabbs.1.center(),
+        };
+
+        // slopes an entity walks down are below it, so they have to be found too
+        let snap = match (axis, last_pos) {
+            (Axis::Y, Some(last_pos)) => (new_pos.x - last_pos.x).abs() + SLOPE_SNAP_DISTANCE,
+            _ => 0.0,
         };
+        let query_aabb = AABB::new(
+            Point::new(aabb.mins().x, aabb.mins().y - snap),
+            *aabb.maxs(),
+        );
+        let last_aabb = last_pos.map(|last_pos| coll.aabb_y(last_pos.as_vec()));
 
         // find closest colliding entity
         let mut colls: Vec<Entity> = Vec::new();
-        // let closest: Option<&Entity> =
-        match axis {
-            Axis::X => {
-                self.dbvt_x
-                    .visit(&mut BoundingVolumeInterferencesCollector::new(
-                        &aabb, &mut colls,
-                    ));
-
-                // let center_x = leaf.center;
-
-                // colls.iter().min_by_key(|other| {
-                //     let other_leafs = self.mapping.get(other).unwrap();
-                //     NotNan::new((self.dbvt_x[other_leafs.x].center.x - center_x.x).abs()).unwrap()
-                // })
-            }
-            Axis::Y => {
-                self.dbvt_y
-                    .visit(&mut BoundingVolumeInterferencesCollector::new(
-                        &aabb, &mut colls,
-                    ));
-
-                // let center_y = leaf.center;
-
-                // colls.iter().min_by_key(|other| {
-                //     let other_leafs = self.mapping.get(other).unwrap();
-                //     NotNan::new((self.dbvt_y[other_leafs.y].center.y - center_y.y).abs()).unwrap()
-                // })
-            }
-        };
+        self.interferences(axis, &query_aabb, &mut colls);
 
         colls
             .into_iter()
             .filter_map(|other| {
                 let other_leafs = self.mapping.get(&other).unwrap();
 
-                let other_leaf = match axis {
-                    Axis::X => &self.dbvt_x[other_leafs.x],
-                    Axis::Y => &self.dbvt_y[other_leafs.y],
-                };
+                if !other_leafs.collides_with(coll.layers(), coll.mask()) {
+                    return None;
+                }
+
+                // dynamic bodies are pushed apart once all of them moved, see `separate_bodies`
+                if coll.mass().is_some() && other_leafs.mass.is_some() {
+                    return None;
+                }
+
+                let other_aabb = other_leafs.aabb(axis);
+
+                if let Some(slope) = other_leafs.slope {
+                    if axis == Axis::X {
+                        return None;
+                    }
+
+                    let depth =
+                        find_slope_depth(&aabb, last_aabb.as_ref(), other_aabb, slope, snap);
+
+                    return depth.map(|(depth, normal)| {
+                        CollisionResult::new(depth, normal, other, other_leafs.coll_type)
+                    });
+                }
 
-                let depth = find_depth(&aabb, leaf.center, &other_leaf.bounding_volume, axis);
+                let depth = if other_leafs.coll_type == CollisionType::OneWayPlatform {
+                    if axis == Axis::X || !self.lands_on_platform(e, coll, &aabb, last_pos, other) {
+                        return None;
+                    }
+
+                    // always pushes up, even if the center already passed the platform's
+                    find_depth(&aabb, last_center, other_aabb, axis).map(f32::abs)
+                } else {
+                    find_depth(&aabb, last_center, other_aabb, axis)
+                };
 
                 depth.map(|depth| {
                     // println!("other.coll_type({:?}): {:?}", axis, other_leafs.coll_type);
                     // println!("depth: {:?}", depth);
-                    CollisionResult::new(depth, other, other_leafs.coll_type)
+                    let normal = axis_normal(axis, depth);
+                    CollisionResult::new(depth, normal, other, other_leafs.coll_type)
                 })
             })
             .collect()
@@ -236,6 +644,133 @@ impl CollisionWorld {
         // None
     }
 
+    /// Moves `pos` back along `axis`, to where `e` first touches a blocking entity on its way
+    /// from `start`, the coordinate on `axis` it moves from. Keeps fast entities from passing
+    /// through others, as `move_axis` only looks at where they end up.
+    fn sweep_axis(
+        &self,
+        e: Entity,
+        coll: &components::CollisionShape,
+        start: f32,
+        pos: Position,
+        axis: Axis,
+    ) -> (Position, Option<Contact>) {
+        let mut start_pos = pos;
+        let delta = match axis {
+            Axis::X => {
+                start_pos.x = start;
+                pos.x - start
+            }
+            Axis::Y => {
+                start_pos.y = start;
+                pos.y - start
+            }
+        };
+
+        if delta == 0.0 {
+            return (pos, None);
+        }
+
+        let (start_aabb, end_aabb) = match axis {
+            Axis::X => (coll.aabb_x(start_pos.as_vec()), coll.aabb_x(pos.as_vec())),
+            Axis::Y => (coll.aabb_y(start_pos.as_vec()), coll.aabb_y(pos.as_vec())),
+        };
+        let swept_aabb = start_aabb.merged(&end_aabb);
+
+        let mut colls = Vec::new();
+        self.interferences(axis, &swept_aabb, &mut colls);
+
+        let dropping_through = self.dropping_through.get(&e);
+        let perpendicular = match axis {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::X,
+        };
+
+        let mut distance = delta.abs();
+        let mut contact = None;
+
+        for other in colls {
+            let other_leafs = &self.mapping[&other];
+            let is_platform = other_leafs.coll_type == CollisionType::OneWayPlatform;
+
+            if !other_leafs.coll_type.is_blocking()
+                || !other_leafs.collides_with(coll.layers(), coll.mask())
+                || (coll.mass().is_some() && other_leafs.mass.is_some())
+            {
+                continue;
+            }
+
+            if dropping_through
+                .map(|platforms| platforms.contains(&other))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            let other_aabb = other_leafs.aabb(axis);
+
+            // only entities in the way, not the ones it slides along
+            if overlap(&start_aabb, other_aabb, perpendicular) <= TOUCH_TOLERANCE {
+                continue;
+            }
+
+            let mut normal = axis_normal(axis, -delta);
+            let gap = match (axis, delta > 0.0) {
+                // neither slopes nor platforms block on the x axis, see `move_axis`
+                (Axis::X, _) if is_platform || other_leafs.slope.is_some() => continue,
+                (Axis::X, true) => other_aabb.mins().x - start_aabb.maxs().x,
+                (Axis::X, false) => start_aabb.mins().x - other_aabb.maxs().x,
+                (Axis::Y, true) if is_platform => continue,
+                (Axis::Y, true) => other_aabb.mins().y - start_aabb.maxs().y,
+                (Axis::Y, false) => {
+                    let top = match other_leafs.slope {
+                        Some(slope) => {
+                            normal = slope_normal(other_aabb, slope);
+
+                            match slope_surface(other_aabb, slope, start_aabb.center().x) {
+                                Some(surface) => surface,
+                                None => continue,
+                            }
+                        }
+                        None => other_aabb.maxs().y,
+                    };
+
+                    let gap = start_aabb.mins().y - top;
+
+                    // platforms only stop entities coming from above, like in `move_axis`
+                    if is_platform && gap < -PLATFORM_TOLERANCE {
+                        continue;
+                    }
+
+                    gap
+                }
+            };
+
+            // already overlapping, which `move_axis` resolves
+            if gap < -TOUCH_TOLERANCE {
+                continue;
+            }
+
+            let gap = gap.max(0.0);
+            if gap < distance {
+                distance = gap;
+                contact = Some(Contact { other, normal });
+            }
+        }
+
+        if contact.is_none() {
+            return (pos, None);
+        }
+
+        let mut swept_pos = pos;
+        match axis {
+            Axis::X => swept_pos.x = start + distance * delta.signum(),
+            Axis::Y => swept_pos.y = start + distance * delta.signum(),
+        }
+
+        (swept_pos, contact)
+    }
+
     pub fn move_entity<E: Extend<Collision>>(
         &mut self,
         e: Entity,
@@ -243,19 +778,43 @@ impl CollisionWorld {
         new_pos: Position,
         last_pos: Option<&Position>,
         collision_collector: &mut E,
-    ) -> Position {
-        // 1. remove both leafs
+    ) -> MoveResult {
+        // 1. remove both leafs, static entities that move are not static anymore
         let mut leafs: CollisionTreeLeafs = self.mapping.remove(&e).unwrap();
+        self.remove_leafs(e, &mut leafs);
 
-        let mut leaf_x = self.dbvt_x.remove(leafs.x);
-        let mut leaf_y = self.dbvt_y.remove(leafs.y);
+        // moving along x keeps the last y, so both moves start at a position that was resolved
+        let mut updated_pos = match last_pos {
+            Some(last_pos) => Position {
+                x: new_pos.x,
+                y: last_pos.y,
+            },
+            None => new_pos,
+        };
 
-        let mut updated_pos = new_pos;
+        let mut contacts = SmallVec::new();
 
-        // 2. call move_axis for both axes, X first
+        // 2. sweep and call move_axis for both axes, X first
         for axis in &[Axis::X, Axis::Y] {
+            if *axis == Axis::Y {
+                updated_pos.y = new_pos.y;
+            }
+
+            if let (Some(last_pos), CollisionType::Solid) = (last_pos, leafs.coll_type) {
+                let start = match axis {
+                    Axis::X => last_pos.x,
+                    Axis::Y => last_pos.y,
+                };
+
+                let (swept_pos, contact) = self.sweep_axis(e, coll, start, updated_pos, *axis);
+
+                updated_pos = swept_pos;
+                contacts.extend(contact);
+            }
+
             let coll_ress = self.move_axis(
-                (&mut leaf_x, &mut leaf_y),
+                e,
+                (&leafs.aabb_x, &leafs.aabb_y),
                 coll,
                 updated_pos,
                 last_pos,
@@ -265,28 +824,30 @@ impl CollisionWorld {
             let mut min_depth = None;
             if leafs.coll_type == CollisionType::Solid {
                 // find deepest collision with solid entity
-                min_depth = coll_ress
+                let resolved = coll_ress
                     .iter()
-                    .filter_map(|cr| match cr.other_coll_type {
-                        CollisionType::Solid => Some(NotNan::new(cr.depth).unwrap()),
-                        CollisionType::Trigger => None,
-                    })
-                    .min();
+                    .filter(|cr| cr.other_coll_type.is_blocking())
+                    .min_by_key(|cr| NotNan::new(cr.depth).unwrap());
 
                 // update position
-                if let Some(min_depth) = min_depth {
+                if let Some(cr) = resolved {
                     match axis {
-                        Axis::X => updated_pos.x += min_depth.into_inner(),
-                        Axis::Y => updated_pos.y += min_depth.into_inner(),
+                        Axis::X => updated_pos.x += cr.depth,
+                        Axis::Y => updated_pos.y += cr.depth,
                     }
+
+                    contacts.push(Contact {
+                        other: cr.other,
+                        normal: cr.normal,
+                    });
                 }
+
+                min_depth = resolved.map(|cr| NotNan::new(cr.depth).unwrap());
             }
 
             // report all still existing collisions if one of them is not solid
             collision_collector.extend(coll_ress.into_iter().filter_map(|cr| {
-                if leafs.coll_type != CollisionType::Solid
-                    || cr.other_coll_type != CollisionType::Solid
-                {
+                if leafs.coll_type != CollisionType::Solid || !cr.other_coll_type.is_blocking() {
                     // if we resolved a collision, check if that didn't resolve this collision too.
                     // otherwise, this collision is still going on.
                     if min_depth
@@ -305,70 +866,1346 @@ impl CollisionWorld {
         }
 
         let new_center = updated_pos.as_pnt();
-        leaf_x = DBVTLeaf::new(coll.aabb_x(new_center.coords), e);
+        leafs.aabb_x = coll.aabb_x(new_center.coords);
 
         let new_center = updated_pos.as_pnt(); // + *coll.off_y();
-        leaf_y = DBVTLeaf::new(coll.aabb_y(new_center.coords), e);
+        leafs.aabb_y = coll.aabb_y(new_center.coords);
 
         // 3. re-insert into trees
-        leafs.x = self.dbvt_x.insert(leaf_x);
-        leafs.y = self.dbvt_y.insert(leaf_y);
+        self.insert_leafs(e, &mut leafs);
 
         // 4. update mapping
         self.mapping.insert(e, leafs);
 
-        // clear on_ground cache
-        self.on_ground_cache.borrow_mut().clear();
+        // 5. stop dropping through the platforms that were left
+        if let Some(mut platforms) = self.dropping_through.remove(&e) {
+            let aabb_y = coll.aabb_y(updated_pos.as_vec());
+            let mapping = &self.mapping;
+            platforms.retain(|platform| match mapping.get(platform) {
+                Some(platform_leafs) => aabb_y.intersects(&platform_leafs.aabb_y),
+                None => false,
+            });
+
+            if !platforms.is_empty() {
+                self.dropping_through.insert(e, platforms);
+            }
+        }
+
+        // clear contacts cache
+        self.contacts_cache.borrow_mut().clear();
 
         // return new position after collisions have been resolved
-        updated_pos
+        MoveResult {
+            position: updated_pos,
+            contacts,
+        }
+    }
+
+    fn cached_contacts(&self, e: Entity) -> CachedContacts {
+        self.contacts_cache
+            .borrow()
+            .get(&e)
+            .cloned()
+            .unwrap_or_default()
     }
 
     pub fn on_ground(&self, e: Entity) -> bool {
-        if let Some(on_ground) = self.on_ground_cache.borrow().get(&e) {
-            return *on_ground;
+        if let Some(on_ground) = self.cached_contacts(e).on_ground {
+            return on_ground;
         }
 
-        let leafs: &CollisionTreeLeafs = self.mapping.get(&e).unwrap();
-        let leaf_y = &self.dbvt_y[leafs.y];
+        let on_ground = !self.touching(e, Face::Bottom).is_empty();
 
-        let mut colls = Vec::new();
-        self.dbvt_y.visit(&mut RayInterferencesCollector::new(
-            &Ray::new(leaf_y.center, Vector::new(0.0, -1.0)),
-            &mut colls,
-        ));
+        self.contacts_cache
+            .borrow_mut()
+            .entry(e)
+            .or_default()
+            .on_ground = Some(on_ground);
 
-        let bot_y = leaf_y.bounding_volume.mins().y;
+        on_ground
+    }
 
-        const ON_GROUND_THRESHOLD: f32 = 0.000_015; // chosen through experiments
+    pub fn on_ceiling(&self, e: Entity) -> bool {
+        if let Some(on_ceiling) = self.cached_contacts(e).on_ceiling {
+            return on_ceiling;
+        }
 
-        let on_ground = colls
-            .iter()
-            .filter(|other| e != **other) // no self-collisions
-            .filter_map(|other| {
-                let other_leafs = self.mapping.get(other).unwrap();
-                if other_leafs.coll_type != CollisionType::Solid {
-                    return None;
-                }
-                let other_leaf_y = &self.dbvt_y[other_leafs.y];
-                let other_top_y = other_leaf_y.bounding_volume.maxs().y;
-                let dist = (other_top_y - bot_y).abs(); // maybe remove the abs(), but shouldn't matter too much
-                Some(dist)
-            })
-            .any(|dist| dist < ON_GROUND_THRESHOLD);
+        let on_ceiling = !self.touching(e, Face::Top).is_empty();
 
-        self.on_ground_cache.borrow_mut().insert(e, on_ground);
+        self.contacts_cache
+            .borrow_mut()
+            .entry(e)
+            .or_default()
+            .on_ceiling = Some(on_ceiling);
 
-        on_ground
+        on_ceiling
     }
 
-    pub fn remove(&mut self, e: Entity) {
-        let leafs = match self.mapping.remove(&e) {
-            Some(l) => l,
-            None => return,
+    /// The side on which `e` touches a wall, the left one if it touches walls on both sides.
+    pub fn touching_wall(&self, e: Entity) -> Option<Side> {
+        if let Some(touching_wall) = self.cached_contacts(e).touching_wall {
+            return touching_wall;
+        }
+
+        let touching_wall = if !self.touching(e, Face::Left).is_empty() {
+            Some(Side::Left)
+        } else if !self.touching(e, Face::Right).is_empty() {
+            Some(Side::Right)
+        } else {
+            None
         };
 
-        self.dbvt_x.remove(leafs.x);
-        self.dbvt_y.remove(leafs.y);
+        self.contacts_cache
+            .borrow_mut()
+            .entry(e)
+            .or_default()
+            .touching_wall = Some(touching_wall);
+
+        touching_wall
+    }
+
+    /// Moves the kinematic entity `e` to `new_pos`, nothing stops it. Returns the solid entities
+    /// it now overlaps, with how far each has to be pushed along the way `e` moved to get out of
+    /// its way.
+    pub fn move_kinematic(
+        &mut self,
+        e: Entity,
+        coll: &components::CollisionShape,
+        new_pos: Position,
+    ) -> Vec<(Entity, Vector<f32>)> {
+        let mut leafs: CollisionTreeLeafs = self.mapping.remove(&e).unwrap();
+
+        let last_center = leafs.aabb_y.center();
+        self.remove_leafs(e, &mut leafs);
+
+        leafs.aabb_x = coll.aabb_x(new_pos.as_vec());
+        leafs.aabb_y = coll.aabb_y(new_pos.as_vec());
+        self.insert_leafs(e, &mut leafs);
+        let (aabb_x, aabb_y) = (leafs.aabb_x, leafs.aabb_y);
+        let (layers, mask) = (leafs.layers, leafs.mask);
+        self.mapping.insert(e, leafs);
+        self.contacts_cache.borrow_mut().clear();
+
+        let displacement = aabb_y.center() - last_center;
+
+        let mut pushes: Vec<(Entity, Vector<f32>)> = Vec::new();
+        for (axis, aabb, delta) in &[
+            (Axis::X, aabb_x, displacement.x),
+            (Axis::Y, aabb_y, displacement.y),
+        ] {
+            if *delta == 0.0 {
+                continue;
+            }
+
+            let mut colls = Vec::new();
+            self.interferences(*axis, aabb, &mut colls);
+
+            let perpendicular = match axis {
+                Axis::X => Axis::Y,
+                Axis::Y => Axis::X,
+            };
+
+            for other in colls {
+                let other_leafs = &self.mapping[&other];
+                if other == e
+                    || other_leafs.coll_type != CollisionType::Solid
+                    || !other_leafs.collides_with(layers, mask)
+                {
+                    continue;
+                }
+
+                let other_aabb = other_leafs.aabb(*axis);
+
+                if overlap(aabb, other_aabb, *axis) <= TOUCH_TOLERANCE
+                    || overlap(aabb, other_aabb, perpendicular) <= TOUCH_TOLERANCE
+                {
+                    continue;
+                }
+
+                // to the far side of `e`, pushing less on the other axis wins
+                let push = match (axis, *delta > 0.0) {
+                    (Axis::X, true) => Vector::new(aabb.maxs().x - other_aabb.mins().x, 0.0),
+                    (Axis::X, false) => Vector::new(aabb.mins().x - other_aabb.maxs().x, 0.0),
+                    (Axis::Y, true) => Vector::new(0.0, aabb.maxs().y - other_aabb.mins().y),
+                    (Axis::Y, false) => Vector::new(0.0, aabb.mins().y - other_aabb.maxs().y),
+                };
+
+                match pushes.iter_mut().find(|(pushed, _)| *pushed == other) {
+                    Some((_, pushed)) if push.norm() < pushed.norm() => *pushed = push,
+                    Some(_) => (),
+                    None => pushes.push((other, push)),
+                }
+            }
+        }
+
+        pushes
+    }
+
+    /// The entities standing on `e`.
+    pub fn riders(&self, e: Entity) -> Vec<Entity> {
+        let aabb = &self.mapping[&e].aabb_y;
+        let probe = AABB::new(
+            Point::new(aabb.mins().x, aabb.maxs().y - SLOPE_TOLERANCE),
+            Point::new(aabb.maxs().x, aabb.maxs().y + SLOPE_TOLERANCE),
+        );
+
+        let mut colls = Vec::new();
+        self.interferences(Axis::Y, &probe, &mut colls);
+
+        colls
+            .into_iter()
+            .filter(|other| e != *other)
+            .filter(|other| self.touching(*other, Face::Bottom).contains(&e))
+            .collect()
+    }
+
+    /// The blocking entities `e` stands on.
+    pub fn standing_on(&self, e: Entity) -> Vec<Entity> {
+        self.touching(e, Face::Bottom)
+    }
+
+    /// How far to move the dynamic bodies, the entities with a mass, so they no longer overlap
+    /// each other. Every overlap is split between both bodies by their masses, along the axis
+    /// they overlap the least on. A body held by something that does not give way, like a wall
+    /// or a body that is held itself, takes none of it. As this only depends on where the bodies
+    /// are, the order in which they moved does not matter.
+    pub fn separate_bodies(&self) -> Vec<(Entity, Vector<f32>)> {
+        let mut bodies: Vec<Entity> = self
+            .mapping
+            .iter()
+            .filter(|(_, leafs)| leafs.mass.is_some())
+            .map(|(e, _)| *e)
+            .collect();
+        bodies.sort_by_key(|e| e.id());
+
+        let mut corrections: Vec<(Entity, Vector<f32>)> = Vec::new();
+        let mut correct = |e: Entity, by: Vector<f32>| {
+            let existing = corrections.iter().position(|(other, _)| *other == e);
+
+            match existing {
+                Some(i) => corrections[i].1 += by,
+                None => corrections.push((e, by)),
+            }
+        };
+
+        for &a in &bodies {
+            let a_leafs = &self.mapping[&a];
+            let (a_x, a_y) = (&a_leafs.aabb_x, &a_leafs.aabb_y);
+
+            let mut colls = Vec::new();
+            self.interferences(Axis::X, a_x, &mut colls);
+            self.interferences(Axis::Y, a_y, &mut colls);
+            colls.sort_by_key(|b| b.id());
+            colls.dedup();
+
+            // every pair once, the other way around it is found from `b`
+            for b in colls.into_iter().filter(|b| a.id() < b.id()) {
+                let b_leafs = &self.mapping[&b];
+                let b_mass = match b_leafs.mass {
+                    Some(mass) => mass,
+                    None => continue,
+                };
+
+                if !b_leafs.coll_type.is_blocking()
+                    || !b_leafs.collides_with(a_leafs.layers, a_leafs.mask)
+                {
+                    continue;
+                }
+
+                let (b_x, b_y) = (&b_leafs.aabb_x, &b_leafs.aabb_y);
+                let depth_x = if a_x.intersects(b_x) {
+                    overlap(a_x, b_x, Axis::X)
+                } else {
+                    std::f32::INFINITY
+                };
+                let depth_y = if a_y.intersects(b_y) {
+                    overlap(a_y, b_y, Axis::Y)
+                } else {
+                    std::f32::INFINITY
+                };
+
+                // only touching
+                if depth_x.min(depth_y) <= CONTACT_DISTANCE {
+                    continue;
+                }
+
+                // the direction from `a` to `b`, `a` is pushed the other way
+                let (axis, depth) = if depth_x < depth_y {
+                    (Axis::X, depth_x)
+                } else {
+                    (Axis::Y, depth_y)
+                };
+                let (a_center, b_center) = match axis {
+                    Axis::X => (a_x.center().x, b_x.center().x),
+                    Axis::Y => (a_y.center().y, b_y.center().y),
+                };
+                let normal = axis_normal(axis, if b_center < a_center { -1.0 } else { 1.0 });
+                let (a_face, b_face) = match (axis, normal.x + normal.y > 0.0) {
+                    (Axis::X, true) => (Face::Left, Face::Right),
+                    (Axis::X, false) => (Face::Right, Face::Left),
+                    (Axis::Y, true) => (Face::Bottom, Face::Top),
+                    (Axis::Y, false) => (Face::Top, Face::Bottom),
+                };
+
+                let inverse_mass = |e: Entity, mass: f32, face: Face| {
+                    if self.held(e, face, &mut Vec::new()) {
+                        0.0
+                    } else {
+                        1.0 / mass
+                    }
+                };
+                let a_inverse = inverse_mass(a, a_leafs.mass.unwrap(), a_face);
+                let b_inverse = inverse_mass(b, b_mass, b_face);
+
+                // stuck between things that do not give way
+                if a_inverse + b_inverse == 0.0 {
+                    continue;
+                }
+
+                let a_share = a_inverse / (a_inverse + b_inverse);
+                if a_inverse > 0.0 {
+                    correct(a, -normal * depth * a_share);
+                }
+                if b_inverse > 0.0 {
+                    correct(b, normal * depth * (1.0 - a_share));
+                }
+            }
+        }
+
+        corrections
+    }
+
+    // whether `e` can not be pushed through `face`, as something that does not give way touches
+    // it there, directly or through other bodies
+    fn held(&self, e: Entity, face: Face, visited: &mut Vec<Entity>) -> bool {
+        if visited.contains(&e) {
+            return false;
+        }
+        visited.push(e);
+
+        self.touching(e, face)
+            .into_iter()
+            .any(|other| self.mapping[&other].mass.is_none() || self.held(other, face, visited))
+    }
+
+    /// Lets `e` fall through the one-way platforms it stands on, until it no longer overlaps
+    /// them. Returns false if it does not stand on any.
+    pub fn drop_through_platforms(&mut self, e: Entity) -> bool {
+        let platforms: SmallVec<[Entity; 2]> = self
+            .touching(e, Face::Bottom)
+            .into_iter()
+            .filter(|other| self.mapping[other].coll_type == CollisionType::OneWayPlatform)
+            .collect();
+
+        if platforms.is_empty() {
+            return false;
+        }
+
+        self.dropping_through
+            .entry(e)
+            .or_insert_with(SmallVec::new)
+            .extend(platforms);
+        self.contacts_cache.borrow_mut().clear();
+
+        true
+    }
+
+    // the blocking entities touching `face` of `e`, which keep it from moving that way
+    fn touching(&self, e: Entity, face: Face) -> Vec<Entity> {
+        let leafs: &CollisionTreeLeafs = self.mapping.get(&e).unwrap();
+        let (axis, perpendicular) = match face {
+            Face::Bottom | Face::Top => (Axis::Y, Axis::X),
+            Face::Left | Face::Right => (Axis::X, Axis::Y),
+        };
+        let aabb = leafs.aabb(axis);
+        let (mins, maxs) = (aabb.mins(), aabb.maxs());
+
+        // a thin box around the face, thicker below as the surface of slopes is less exact
+        let probe = match face {
+            Face::Bottom => AABB::new(
+                Point::new(mins.x, mins.y - SLOPE_TOLERANCE),
+                Point::new(maxs.x, mins.y + SLOPE_TOLERANCE),
+            ),
+            Face::Top => AABB::new(
+                Point::new(mins.x, maxs.y - CONTACT_DISTANCE),
+                Point::new(maxs.x, maxs.y + CONTACT_DISTANCE),
+            ),
+            Face::Left => AABB::new(
+                Point::new(mins.x - CONTACT_DISTANCE, mins.y),
+                Point::new(mins.x + CONTACT_DISTANCE, maxs.y),
+            ),
+            Face::Right => AABB::new(
+                Point::new(maxs.x - CONTACT_DISTANCE, mins.y),
+                Point::new(maxs.x + CONTACT_DISTANCE, maxs.y),
+            ),
+        };
+
+        let mut colls = Vec::new();
+        self.interferences(axis, &probe, &mut colls);
+
+        let dropping_through = self.dropping_through.get(&e);
+
+        colls
+            .into_iter()
+            .filter(|other| e != *other) // no self-collisions
+            .filter(|other| {
+                dropping_through
+                    .map(|platforms| !platforms.contains(other))
+                    .unwrap_or(true)
+            })
+            .filter(|other| {
+                let other_leafs = self.mapping.get(other).unwrap();
+                if !other_leafs.coll_type.is_blocking()
+                    || !other_leafs.collides_with(leafs.layers, leafs.mask)
+                {
+                    return false;
+                }
+
+                let is_platform = other_leafs.coll_type == CollisionType::OneWayPlatform;
+                let other_aabb = other_leafs.aabb(axis);
+
+                // only the corners touch
+                if overlap(aabb, other_aabb, perpendicular) <= TOUCH_TOLERANCE {
+                    return false;
+                }
+
+                let (dist, tolerance) = match face {
+                    Face::Bottom => match other_leafs.slope {
+                        Some(slope) => match slope_surface(other_aabb, slope, aabb.center().x) {
+                            Some(surface) => (mins.y - surface, SLOPE_TOLERANCE),
+                            None => return false,
+                        },
+                        None => (mins.y - other_aabb.maxs().y, CONTACT_DISTANCE),
+                    },
+                    // platforms only block from above, slopes also with their flat bottom
+                    Face::Top if is_platform => return false,
+                    Face::Top => (other_aabb.mins().y - maxs.y, CONTACT_DISTANCE),
+                    Face::Left | Face::Right if is_platform || other_leafs.slope.is_some() => {
+                        return false
+                    }
+                    Face::Left => (mins.x - other_aabb.maxs().x, CONTACT_DISTANCE),
+                    Face::Right => (other_aabb.mins().x - maxs.x, CONTACT_DISTANCE),
+                };
+
+                dist.abs() <= tolerance
+            })
+            .collect()
+    }
+
+    /// The entities containing `point`.
+    pub fn entities_at_point(&self, point: Point<f32>, filter: QueryFilter) -> Vec<Entity> {
+        self.entities_in_aabb(&AABB::new(point, point), filter)
+    }
+
+    /// The entities overlapping or touching `aabb`.
+    pub fn entities_in_aabb(&self, aabb: &AABB<f32>, filter: QueryFilter) -> Vec<Entity> {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+
+        self.candidates(aabb, filter)
+            .into_iter()
+            .filter(|other| {
+                self.shapes(*other).into_iter().any(|(shape, slope)| {
+                    shape_planes(shape, slope, &half_extents)
+                        .iter()
+                        .all(|(normal, offset)| normal.dot(&center.coords) <= *offset)
+                })
+            })
+            .collect()
+    }
+
+    /// The first entity hit by the ray from `origin` along `dir`, at most `max_dist` away.
+    pub fn raycast(
+        &self,
+        origin: Point<f32>,
+        dir: Vector<f32>,
+        max_dist: f32,
+        filter: QueryFilter,
+    ) -> Option<Hit> {
+        self.cast_box(&AABB::new(origin, origin), dir, max_dist, filter)
+    }
+
+    /// Like `raycast`, for moving all of `aabb` along `dir`.
+    pub fn cast_box(
+        &self,
+        aabb: &AABB<f32>,
+        dir: Vector<f32>,
+        max_dist: f32,
+        filter: QueryFilter,
+    ) -> Option<Hit> {
+        let dir = dir.try_normalize(0.0)?;
+        let origin = aabb.center();
+        let half_extents = aabb.half_extents();
+
+        let end = origin + dir * max_dist;
+        let swept = AABB::new(
+            Point::new(origin.x.min(end.x), origin.y.min(end.y)) - half_extents,
+            Point::new(origin.x.max(end.x), origin.y.max(end.y)) + half_extents,
+        );
+
+        let mut hit: Option<Hit> = None;
+        for other in self.candidates(&swept, filter) {
+            for (shape, slope) in self.shapes(other) {
+                let planes = shape_planes(shape, slope, &half_extents);
+                let (toi, normal) = match ray_enters(&origin, &dir, &planes) {
+                    Some(entered) => entered,
+                    None => continue,
+                };
+
+                if toi > max_dist || hit.map(|hit| hit.toi <= toi).unwrap_or(false) {
+                    continue;
+                }
+
+                hit = Some(Hit {
+                    entity: other,
+                    toi,
+                    point: origin + dir * toi,
+                    normal,
+                });
+            }
+        }
+
+        hit
+    }
+
+    // the entities `filter` accepts with a box intersecting `aabb`, each once
+    fn candidates(&self, aabb: &AABB<f32>, filter: QueryFilter) -> Vec<Entity> {
+        let mut colls = Vec::new();
+        self.interferences(Axis::X, aabb, &mut colls);
+        self.interferences(Axis::Y, aabb, &mut colls);
+
+        let mut candidates = Vec::new();
+        for other in colls {
+            if !candidates.contains(&other) && filter.accepts(other, &self.mapping[&other]) {
+                candidates.push(other);
+            }
+        }
+
+        candidates
+    }
+
+    // the boxes of `e`, with the slope of the box if it is one
+    fn shapes(&self, e: Entity) -> SmallVec<[(&AABB<f32>, Option<Slope>); 2]> {
+        let leafs = &self.mapping[&e];
+        let (aabb_x, aabb_y) = (&leafs.aabb_x, &leafs.aabb_y);
+
+        let mut shapes = SmallVec::new();
+        shapes.push((aabb_y, leafs.slope));
+
+        // slopes only are slopes on the y axis
+        if leafs.slope.is_none() && aabb_x != aabb_y {
+            shapes.push((aabb_x, None));
+        }
+
+        shapes
+    }
+
+    pub fn remove(&mut self, e: Entity) {
+        let mut leafs = match self.mapping.remove(&e) {
+            Some(l) => l,
+            None => return,
+        };
+
+        self.remove_leafs(e, &mut leafs);
+
+        self.dropping_through.remove(&e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::{BuildData, World};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::components::{CollisionShape, LevelComponents};
+    use crate::na::Vector2;
+    use crate::nc::shape::Cuboid;
+    use crate::systems::LevelSystems;
+
+    const TILE_SIZE: f32 = 32.0;
+
+    fn create_entity(world: &mut World<LevelSystems>) -> Entity {
+        world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {})
+    }
+
+    fn solid_box(half_width: f32, half_height: f32) -> CollisionShape {
+        let half_extents = Vector2::new(half_width, half_height);
+
+        CollisionShape::new_single(
+            Cuboid::new(half_extents),
+            half_extents,
+            CollisionType::Solid,
+        )
+    }
+
+    // how deep `a` and `b` overlap, on the axis where they overlap the least
+    fn penetration(a: &AABB<f32>, b: &AABB<f32>) -> f32 {
+        overlap(a, b, Axis::X).min(overlap(a, b, Axis::Y))
+    }
+
+    #[test]
+    fn fast_entities_do_not_tunnel() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let tile = solid_box(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+        let floor = create_entity(&mut world);
+        collision_world.add(floor, &tile, Position { x: 0.0, y: 0.0 });
+
+        let shape = solid_box(5.0, 5.0);
+        let mover = create_entity(&mut world);
+        let start = Position { x: 10.0, y: 100.0 };
+        collision_world.add(mover, &shape, start);
+
+        let mut colls: Vec<Collision> = Vec::new();
+        let end = collision_world
+            .move_entity(
+                mover,
+                &shape,
+                Position { x: 10.0, y: -400.0 },
+                Some(&start),
+                &mut colls,
+            )
+            .position;
+
+        assert!((end.y - TILE_SIZE).abs() < 0.001, "ended at {:?}", end);
+        assert!(collision_world.on_ground(mover));
+    }
+
+    #[test]
+    fn contacts_with_walls_and_ceilings() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        // a corridor, ending in a wall on the right
+        let floor = create_entity(&mut world);
+        collision_world.add(floor, &solid_box(64.0, 16.0), Position { x: 0.0, y: 0.0 });
+        let ceiling = create_entity(&mut world);
+        collision_world.add(
+            ceiling,
+            &solid_box(64.0, 16.0),
+            Position { x: 0.0, y: 64.0 },
+        );
+        let wall = create_entity(&mut world);
+        collision_world.add(wall, &solid_box(16.0, 16.0), Position { x: 64.0, y: 32.0 });
+
+        let shape = solid_box(5.0, 5.0);
+        let mover = create_entity(&mut world);
+        let start = Position { x: 40.0, y: 32.0 };
+        collision_world.add(mover, &shape, start);
+
+        let mut colls: Vec<Collision> = Vec::new();
+        let moved = collision_world.move_entity(
+            mover,
+            &shape,
+            Position { x: 70.0, y: 20.0 },
+            Some(&start),
+            &mut colls,
+        );
+
+        assert_eq!(moved.position, Position { x: 54.0, y: 32.0 });
+        assert!(moved.contacts.contains(&Contact {
+            other: wall,
+            normal: Vector::new(-1.0, 0.0),
+        }));
+        assert!(moved.contacts.contains(&Contact {
+            other: floor,
+            normal: Vector::new(0.0, 1.0),
+        }));
+        assert!(collision_world.on_ground(mover));
+        assert!(!collision_world.on_ceiling(mover));
+        assert_eq!(collision_world.touching_wall(mover), Some(Side::Right));
+
+        let start = moved.position;
+        let moved = collision_world.move_entity(
+            mover,
+            &shape,
+            Position { x: 54.0, y: 100.0 },
+            Some(&start),
+            &mut colls,
+        );
+
+        assert_eq!(moved.position, Position { x: 54.0, y: 54.0 });
+        assert_eq!(
+            &moved.contacts[..],
+            &[Contact {
+                other: ceiling,
+                normal: Vector::new(0.0, -1.0),
+            }]
+        );
+        assert!(!collision_world.on_ground(mover));
+        assert!(collision_world.on_ceiling(mover));
+        assert_eq!(collision_world.touching_wall(mover), Some(Side::Right));
+        assert!(colls.is_empty());
+    }
+
+    #[test]
+    fn moving_platforms_push_and_carry() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let platform_shape = solid_box(32.0, 8.0);
+        let platform = create_entity(&mut world);
+        collision_world.add(platform, &platform_shape, Position { x: 0.0, y: 0.0 });
+
+        let shape = solid_box(5.0, 5.0);
+        let rider = create_entity(&mut world);
+        collision_world.add(rider, &shape, Position { x: 10.0, y: 16.0 });
+        let in_the_way = create_entity(&mut world);
+        collision_world.add(in_the_way, &shape, Position { x: 70.0, y: 2.0 });
+
+        assert_eq!(collision_world.riders(platform), vec![rider]);
+
+        let mut pushes =
+            collision_world.move_kinematic(platform, &platform_shape, Position { x: 8.0, y: 4.0 });
+        pushes.sort_by_key(|(e, _)| e.id());
+
+        // the rider is pushed up by as much as the platform moved, the one in the way to the side
+        assert_eq!(
+            pushes,
+            vec![
+                (rider, Vector::new(0.0, 4.0)),
+                (in_the_way, Vector::new(2.0, 0.0)),
+            ]
+        );
+    }
+
+    // moves `e` from `from` to `to`, returns where it ended up
+    fn move_from(
+        collision_world: &mut CollisionWorld,
+        e: Entity,
+        shape: &CollisionShape,
+        from: Position,
+        to: Position,
+    ) -> Position {
+        let mut colls: Vec<Collision> = Vec::new();
+        collision_world
+            .move_entity(e, shape, to, Some(&from), &mut colls)
+            .position
+    }
+
+    #[test]
+    fn one_way_platforms_only_stop_from_above() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        // the top is at y = 4
+        let half_extents = Vector2::new(32.0, 4.0);
+        let platform_shape = CollisionShape::new_single(
+            Cuboid::new(half_extents),
+            half_extents,
+            CollisionType::OneWayPlatform,
+        );
+        let platform = create_entity(&mut world);
+        collision_world.add(platform, &platform_shape, Position { x: 0.0, y: 0.0 });
+
+        let shape = solid_box(5.0, 5.0);
+        let add_mover = |world: &mut World<LevelSystems>,
+                         collision_world: &mut CollisionWorld,
+                         position: Position| {
+            let mover = create_entity(world);
+            collision_world.add(mover, &shape, position);
+            mover
+        };
+
+        // lands on it from above
+        let start = Position { x: 0.0, y: 30.0 };
+        let falling = add_mover(&mut world, &mut collision_world, start);
+        let end = move_from(
+            &mut collision_world,
+            falling,
+            &shape,
+            start,
+            Position { x: 0.0, y: -20.0 },
+        );
+        assert_eq!(end, Position { x: 0.0, y: 9.0 });
+        assert!(collision_world.on_ground(falling));
+
+        // jumps up through it
+        let start = Position { x: 20.0, y: -20.0 };
+        let jumping = add_mover(&mut world, &mut collision_world, start);
+        let end = move_from(
+            &mut collision_world,
+            jumping,
+            &shape,
+            start,
+            Position { x: 20.0, y: 30.0 },
+        );
+        assert_eq!(end, Position { x: 20.0, y: 30.0 });
+
+        // walks past it at its height
+        let start = Position { x: -60.0, y: -2.0 };
+        let walking = add_mover(&mut world, &mut collision_world, start);
+        let end = move_from(
+            &mut collision_world,
+            walking,
+            &shape,
+            start,
+            Position { x: 60.0, y: -2.0 },
+        );
+        assert_eq!(end, Position { x: 60.0, y: -2.0 });
+        assert!(!collision_world.on_ground(walking));
+    }
+
+    #[test]
+    fn dropping_through_one_way_platforms() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let half_extents = Vector2::new(32.0, 4.0);
+        let platform_shape = CollisionShape::new_single(
+            Cuboid::new(half_extents),
+            half_extents,
+            CollisionType::OneWayPlatform,
+        );
+        let platform = create_entity(&mut world);
+        collision_world.add(platform, &platform_shape, Position { x: 0.0, y: 0.0 });
+        let floor = create_entity(&mut world);
+        collision_world.add(floor, &solid_box(64.0, 8.0), Position { x: 0.0, y: -60.0 });
+
+        let shape = solid_box(5.0, 5.0);
+        let mover = create_entity(&mut world);
+        let standing = Position { x: 0.0, y: 9.0 };
+        collision_world.add(mover, &shape, standing);
+
+        // nothing to drop through while in the air
+        let above = Position { x: 0.0, y: 30.0 };
+        assert_eq!(
+            move_from(&mut collision_world, mover, &shape, standing, above),
+            above
+        );
+        assert!(!collision_world.drop_through_platforms(mover));
+        assert_eq!(
+            move_from(&mut collision_world, mover, &shape, above, standing),
+            standing
+        );
+
+        assert!(collision_world.drop_through_platforms(mover));
+        assert!(collision_world.dropping_through.contains_key(&mover));
+
+        // still overlapping the platform, so still dropping through it
+        let inside = Position { x: 0.0, y: 2.0 };
+        assert_eq!(
+            move_from(&mut collision_world, mover, &shape, standing, inside),
+            inside
+        );
+        assert!(collision_world.dropping_through.contains_key(&mover));
+
+        // below it, it stops dropping through and lands on the floor
+        let end = move_from(
+            &mut collision_world,
+            mover,
+            &shape,
+            inside,
+            Position { x: 0.0, y: -100.0 },
+        );
+        assert_eq!(end, Position { x: 0.0, y: -47.0 });
+        assert!(!collision_world.dropping_through.contains_key(&mover));
+
+        // solid floors can not be dropped through
+        assert!(collision_world.on_ground(mover));
+        assert!(!collision_world.drop_through_platforms(mover));
+
+        // so it lands on the platform again after jumping up through it
+        let end = move_from(&mut collision_world, mover, &shape, end, above);
+        assert_eq!(end, above);
+        let below = Position { x: 0.0, y: -20.0 };
+        assert_eq!(
+            move_from(&mut collision_world, mover, &shape, above, below),
+            standing
+        );
+        assert!(collision_world.on_ground(mover));
+    }
+
+    #[test]
+    fn walking_on_slopes_keeps_to_the_surface() {
+        // and where the surface is in the middle of the tile, which spans -16 to 16
+        let slopes = [
+            (Slope::Rising45, 0.0),
+            (Slope::Falling45, 0.0),
+            (Slope::Rising22Low, -8.0),
+            (Slope::Rising22High, 8.0),
+            (Slope::Falling22High, 8.0),
+            (Slope::Falling22Low, -8.0),
+        ];
+
+        for &(slope, middle) in &slopes {
+            let mut world = World::<LevelSystems>::new();
+            let mut collision_world = CollisionWorld::new();
+
+            let tile = solid_box(TILE_SIZE / 2.0, TILE_SIZE / 2.0).with_slope(slope);
+            let slope_tile = create_entity(&mut world);
+            collision_world.add(slope_tile, &tile, Position { x: 0.0, y: 0.0 });
+
+            // where the center of a mover 8 high stands on the surface
+            let standing_y = |x: f32| -12.0 + slope.height_at((x + 16.0) / 32.0) * 32.0;
+
+            let shape = solid_box(4.0, 4.0);
+            let mover = create_entity(&mut world);
+            let mut pos = Position {
+                x: -8.0,
+                y: standing_y(-8.0),
+            };
+            collision_world.add(mover, &shape, pos);
+
+            // up and down the slope, without gravity pulling it down
+            let steps = (0..8).map(|_| 2.0).chain((0..8).map(|_| -2.0));
+            for dx in steps {
+                let to = Position {
+                    x: pos.x + dx,
+                    y: pos.y,
+                };
+                pos = move_from(&mut collision_world, mover, &shape, pos, to);
+
+                assert_near(pos.x, to.x);
+                assert_near(pos.y, standing_y(pos.x));
+                assert!(collision_world.on_ground(mover), "{:?} at {:?}", slope, pos);
+
+                if pos.x.abs() < 0.001 {
+                    assert_near(pos.y, middle + 4.0);
+                }
+            }
+
+            // the bottom of the tile is flat, and blocks like any other
+            let below = Position { x: 0.0, y: -40.0 };
+            collision_world.move_entity(mover, &shape, below, None, &mut Vec::new());
+            let end = move_from(
+                &mut collision_world,
+                mover,
+                &shape,
+                below,
+                Position { x: 0.0, y: -10.0 },
+            );
+            assert_eq!(end, Position { x: 0.0, y: -20.0 }, "{:?}", slope);
+            assert!(collision_world.on_ceiling(mover));
+            assert!(!collision_world.on_ground(mover));
+        }
+    }
+
+    #[test]
+    fn masked_layers_do_not_collide() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let floor = create_entity(&mut world);
+        collision_world.add(floor, &solid_box(64.0, 16.0), Position { x: 0.0, y: 0.0 });
+        let wall = create_entity(&mut world);
+        collision_world.add(wall, &solid_box(16.0, 16.0), Position { x: 64.0, y: 32.0 });
+
+        let shape = solid_box(5.0, 5.0)
+            .with_layers(CollisionLayers::PLAYERS)
+            .with_mask(CollisionLayers::ALL.without(CollisionLayers::PLAYERS));
+        let standing = create_entity(&mut world);
+        collision_world.add(standing, &shape, Position { x: 20.0, y: 21.0 });
+        let mover = create_entity(&mut world);
+        let start = Position { x: 0.0, y: 21.0 };
+        collision_world.add(mover, &shape, start);
+
+        // walks through the other player, but not through the wall
+        let mut colls: Vec<Collision> = Vec::new();
+        let moved = collision_world.move_entity(
+            mover,
+            &shape,
+            Position { x: 70.0, y: 21.0 },
+            Some(&start),
+            &mut colls,
+        );
+
+        assert_eq!(moved.position, Position { x: 43.0, y: 21.0 });
+        assert!(moved.contacts.iter().all(|contact| contact.other == wall));
+        assert!(colls.is_empty());
+
+        // and does not stand on it either
+        let start = Position { x: 20.0, y: 31.0 };
+        collision_world.move_entity(mover, &shape, start, Some(&start), &mut colls);
+
+        assert!(!collision_world.on_ground(mover));
+        assert!(collision_world.on_ground(standing));
+    }
+
+    #[test]
+    fn dynamic_bodies_are_pushed_apart_by_mass() {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let floor = create_entity(&mut world);
+        collision_world.add(floor, &solid_box(96.0, 16.0), Position { x: 0.0, y: 0.0 });
+        let wall = create_entity(&mut world);
+        collision_world.add(wall, &solid_box(16.0, 16.0), Position { x: 192.0, y: 32.0 });
+
+        let mut add_body = |shape: CollisionShape, x: f32, y: f32| {
+            let body = create_entity(&mut world);
+            collision_world.add(body, &shape, Position { x, y });
+            body
+        };
+        let body = |mass: f32| solid_box(5.0, 5.0).with_mass(mass);
+
+        // side by side on the floor, overlapping by 2
+        let light = add_body(body(1.0), 20.0, 32.0);
+        let heavy = add_body(body(3.0), 28.0, 32.0);
+        // the same against the wall
+        let pushing = add_body(body(1.0), 174.0, 32.0);
+        let against_wall = add_body(body(3.0), 182.0, 32.0);
+        // on top of each other
+        let below = add_body(body(1.0), 100.0, 32.0);
+        let above = add_body(body(1.0), 100.0, 40.0);
+        // not colliding with each other
+        add_body(body(1.0).with_mask(CollisionLayers::NONE), 130.0, 32.0);
+        add_body(body(1.0).with_mask(CollisionLayers::NONE), 132.0, 32.0);
+
+        let corrections = collision_world.separate_bodies();
+        let correction = |e: Entity| {
+            corrections
+                .iter()
+                .find(|(other, _)| *other == e)
+                .map(|(_, by)| *by)
+                .unwrap_or_else(Vector::zeros)
+        };
+
+        assert_eq!(corrections.len(), 4);
+
+        assert_near(correction(light).x, -1.5);
+        assert_near(correction(heavy).x, 0.5);
+        assert_near(correction(pushing).x, -2.0);
+        assert_eq!(correction(against_wall), Vector::zeros());
+        assert_eq!(correction(below), Vector::zeros());
+        assert_eq!(correction(above), Vector::new(0.0, 2.0));
+    }
+
+    // a trigger, a wall, a slope and a player, side by side
+    fn query_world() -> (CollisionWorld, [Entity; 4]) {
+        let mut world = World::<LevelSystems>::new();
+        let mut collision_world = CollisionWorld::new();
+
+        let half_extents = Vector2::new(8.0, 8.0);
+        let trigger_shape = CollisionShape::new_single(
+            Cuboid::new(half_extents),
+            half_extents,
+            CollisionType::Trigger,
+        );
+        let trigger = create_entity(&mut world);
+        collision_world.add(trigger, &trigger_shape, Position { x: 32.0, y: 0.0 });
+
+        let wall = create_entity(&mut world);
+        collision_world.add(wall, &solid_box(16.0, 16.0), Position { x: 64.0, y: 0.0 });
+
+        let slope = create_entity(&mut world);
+        let slope_shape = solid_box(16.0, 16.0).with_slope(Slope::Rising45);
+        collision_world.add(slope, &slope_shape, Position { x: 128.0, y: 0.0 });
+
+        let player = create_entity(&mut world);
+        let player_shape = solid_box(5.0, 5.0).with_layers(CollisionLayers::PLAYERS);
+        collision_world.add(player, &player_shape, Position { x: 100.0, y: 40.0 });
+
+        (collision_world, [trigger, wall, slope, player])
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn raycasts_find_the_first_hit() {
+        let (collision_world, [trigger, wall, slope, player]) = query_world();
+        let right = Vector::new(1.0, 0.0);
+        let solids = QueryFilter::new().types(&[CollisionType::Solid]);
+
+        let hit = collision_world
+            .raycast(Point::new(0.0, 8.0), right, 200.0, QueryFilter::new())
+            .unwrap();
+        assert_eq!(hit.entity, trigger);
+        assert_eq!(hit.toi, 32.0);
+        assert_eq!(hit.point, Point::new(32.0, 8.0));
+        assert_eq!(hit.normal, Vector::new(-1.0, 0.0));
+
+        let hit = collision_world
+            .raycast(Point::new(0.0, 8.0), right * 3.0, 200.0, solids)
+            .unwrap();
+        assert_eq!((hit.entity, hit.toi), (wall, 64.0));
+
+        assert_eq!(
+            collision_world.raycast(Point::new(0.0, 8.0), right, 50.0, solids),
+            None
+        );
+
+        // the slope is entered through its surface, where it is 8 high
+        let hit = collision_world
+            .raycast(Point::new(0.0, 8.0), right, 200.0, solids.excluding(wall))
+            .unwrap();
+        assert_eq!(hit.entity, slope);
+        assert_near(hit.toi, 136.0);
+        assert_near(hit.normal.x, -std::f32::consts::FRAC_1_SQRT_2);
+        assert_near(hit.normal.y, std::f32::consts::FRAC_1_SQRT_2);
+
+        let down = Vector::new(0.0, -1.0);
+        let hit = collision_world
+            .raycast(Point::new(105.0, 100.0), down, 200.0, QueryFilter::new())
+            .unwrap();
+        assert_eq!(
+            (hit.entity, hit.toi, hit.normal),
+            (player, 50.0, Vector::new(0.0, 1.0))
+        );
+
+        let world_only = QueryFilter::new().layers(CollisionLayers::WORLD);
+        assert_eq!(
+            collision_world.raycast(Point::new(105.0, 100.0), down, 200.0, world_only),
+            None
+        );
+    }
+
+    #[test]
+    fn regions_find_overlapping_entities() {
+        let (collision_world, [trigger, wall, slope, _]) = query_world();
+        let all = QueryFilter::new();
+
+        assert_eq!(
+            collision_world.entities_at_point(Point::new(40.0, 8.0), all),
+            vec![trigger]
+        );
+        assert!(collision_world
+            .entities_at_point(Point::new(40.0, 8.0), all.types(&[CollisionType::Solid]))
+            .is_empty());
+
+        // below and above the surface of the slope
+        assert_eq!(
+            collision_world.entities_at_point(Point::new(150.0, 10.0), all),
+            vec![slope]
+        );
+        assert!(collision_world
+            .entities_at_point(Point::new(135.0, 20.0), all)
+            .is_empty());
+
+        let mut found = collision_world.entities_in_aabb(
+            &AABB::new(Point::new(30.0, 0.0), Point::new(70.0, 10.0)),
+            all,
+        );
+        found.sort_by_key(|e| e.id());
+        assert_eq!(found, vec![trigger, wall]);
+
+        // touching counts
+        assert_eq!(
+            collision_world.entities_in_aabb(
+                &AABB::new(Point::new(96.0, 0.0), Point::new(100.0, 1.0)),
+                all
+            ),
+            vec![wall]
+        );
+    }
+
+    #[test]
+    fn cast_boxes_stop_where_they_touch() {
+        let (collision_world, [_, wall, slope, _]) = query_world();
+        let solids = QueryFilter::new().types(&[CollisionType::Solid]);
+
+        let aabb = AABB::new(Point::new(-5.0, 3.0), Point::new(5.0, 13.0));
+        let hit = collision_world
+            .cast_box(&aabb, Vector::new(1.0, 0.0), 200.0, solids)
+            .unwrap();
+        assert_eq!(hit.entity, wall);
+        assert_eq!(hit.point, Point::new(59.0, 8.0));
+        assert_eq!(hit.normal, Vector::new(-1.0, 0.0));
+
+        // falling onto the slope, its bottom right corner touches first
+        let aabb = AABB::new(Point::new(145.0, 55.0), Point::new(155.0, 65.0));
+        let hit = collision_world
+            .cast_box(&aabb, Vector::new(0.0, -1.0), 200.0, solids)
+            .unwrap();
+        assert_eq!(hit.entity, slope);
+        assert_near(hit.toi, 28.0);
+        assert_near(hit.normal.y, std::f32::consts::FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn no_solid_penetration_at_any_velocity() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let tile = solid_box(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+
+        for _ in 0..100 {
+            let mut world = World::<LevelSystems>::new();
+            let mut collision_world = CollisionWorld::new();
+
+            let mut tile_aabbs = Vec::new();
+            for column in 0..10 {
+                for row in 0..10 {
+                    if !rng.gen_bool(0.3) {
+                        continue;
+                    }
+
+                    let pos = Position {
+                        x: column as f32 * TILE_SIZE,
+                        y: row as f32 * TILE_SIZE,
+                    };
+                    let e = create_entity(&mut world);
+                    collision_world.add(e, &tile, pos);
+                    tile_aabbs.push(tile.aabb_y(pos.as_vec()));
+                }
+            }
+
+            let shape = solid_box(rng.gen_range(1.0, 20.0), rng.gen_range(1.0, 20.0));
+
+            // start somewhere free
+            let mut pos = loop {
+                let pos = Position {
+                    x: rng.gen_range(-64.0, 10.0 * TILE_SIZE + 64.0),
+                    y: rng.gen_range(-64.0, 10.0 * TILE_SIZE + 64.0),
+                };
+                let aabb = shape.aabb_y(pos.as_vec());
+
+                if tile_aabbs
+                    .iter()
+                    .all(|tile| penetration(&aabb, tile) <= 0.0)
+                {
+                    break pos;
+                }
+            };
+
+            let mover = create_entity(&mut world);
+            collision_world.add(mover, &shape, pos);
+
+            for _ in 0..50 {
+                // mostly slow, but sometimes faster than a tile per tick
+                let max_speed = if rng.gen_bool(0.5) { 5.0 } else { 300.0 };
+                let new_pos = Position {
+                    x: pos.x + rng.gen_range(-max_speed, max_speed),
+                    y: pos.y + rng.gen_range(-max_speed, max_speed),
+                };
+
+                let mut colls: Vec<Collision> = Vec::new();
+                let last_pos = pos;
+                pos = collision_world
+                    .move_entity(mover, &shape, new_pos, Some(&last_pos), &mut colls)
+                    .position;
+
+                let aabb = shape.aabb_y(pos.as_vec());
+                for tile in &tile_aabbs {
+                    assert!(
+                        penetration(&aabb, tile) < 0.01,
+                        "moving from {:?} to {:?} ended in a tile at {:?}",
+                        last_pos,
+                        new_pos,
+                        pos
+                    );
+                }
+            }
+        }
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by_key(|e| e.id());
+        entities
+    }
+
+    #[test]
+    fn static_entities_collide_like_the_others() {
+        let mut rng = StdRng::seed_from_u64(0x961d);
+        let mut world = World::<LevelSystems>::new();
+
+        // the same room, once in the trees and once in the grid
+        let mut in_trees = CollisionWorld::new();
+        let mut in_grid = CollisionWorld::new();
+
+        for column in -10..10 {
+            for row in -10..10 {
+                if !rng.gen_bool(0.3) {
+                    continue;
+                }
+
+                // mostly tiles, some platforms and some larger than a cell
+                let half_extents = match rng.gen_range(0, 4) {
+                    0 => Vector2::new(rng.gen_range(1.0, 40.0), rng.gen_range(1.0, 40.0)),
+                    _ => Vector2::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0),
+                };
+                let coll_type = if rng.gen_bool(0.2) {
+                    CollisionType::OneWayPlatform
+                } else {
+                    CollisionType::Solid
+                };
+                let tile =
+                    CollisionShape::new_single(Cuboid::new(half_extents), half_extents, coll_type);
+                let pos = Position {
+                    x: column as f32 * TILE_SIZE,
+                    y: row as f32 * TILE_SIZE,
+                };
+
+                let e = create_entity(&mut world);
+                in_trees.add(e, &tile, pos);
+                in_grid.add_static(e, &tile, pos);
+            }
+        }
+
+        let shapes: Vec<_> = (0..5)
+            .map(|_| solid_box(rng.gen_range(1.0, 20.0), rng.gen_range(1.0, 20.0)))
+            .collect();
+        let mut movers = Vec::new();
+        for shape in &shapes {
+            let pos = Position {
+                x: rng.gen_range(-10.0 * TILE_SIZE, 10.0 * TILE_SIZE),
+                y: rng.gen_range(-10.0 * TILE_SIZE, 10.0 * TILE_SIZE),
+            };
+
+            let e = create_entity(&mut world);
+            in_trees.add(e, shape, pos);
+            in_grid.add(e, shape, pos);
+            movers.push((e, shape, pos));
+        }
+
+        for _ in 0..200 {
+            for (e, shape, pos) in &mut movers {
+                let max_speed = if rng.gen_bool(0.8) { 5.0 } else { 100.0 };
+                let new_pos = Position {
+                    x: pos.x + rng.gen_range(-max_speed, max_speed),
+                    y: pos.y + rng.gen_range(-max_speed, max_speed),
+                };
+
+                let (mut colls_trees, mut colls_grid): (Vec<Collision>, Vec<Collision>) =
+                    (Vec::new(), Vec::new());
+                let moved = in_trees.move_entity(*e, shape, new_pos, Some(&*pos), &mut colls_trees);
+                let moved_grid =
+                    in_grid.move_entity(*e, shape, new_pos, Some(&*pos), &mut colls_grid);
+
+                assert_near(moved.position.x, moved_grid.position.x);
+                assert_near(moved.position.y, moved_grid.position.y);
+                assert_eq!(
+                    sorted(colls_trees.iter().map(|c| c.collided).collect()),
+                    sorted(colls_grid.iter().map(|c| c.collided).collect())
+                );
+                assert_eq!(
+                    sorted(moved.contacts.iter().map(|c| c.other).collect()),
+                    sorted(moved_grid.contacts.iter().map(|c| c.other).collect())
+                );
+
+                assert_eq!(in_trees.on_ground(*e), in_grid.on_ground(*e));
+                assert_eq!(in_trees.on_ceiling(*e), in_grid.on_ceiling(*e));
+                assert_eq!(in_trees.touching_wall(*e), in_grid.touching_wall(*e));
+                assert_eq!(
+                    sorted(in_trees.standing_on(*e)),
+                    sorted(in_grid.standing_on(*e))
+                );
+
+                *pos = moved.position;
+            }
+
+            let point = Point::new(
+                rng.gen_range(-10.0 * TILE_SIZE, 10.0 * TILE_SIZE),
+                rng.gen_range(-10.0 * TILE_SIZE, 10.0 * TILE_SIZE),
+            );
+            let aabb = AABB::new(point, point + Vector::new(40.0, 40.0));
+            assert_eq!(
+                sorted(in_trees.entities_in_aabb(&aabb, QueryFilter::new())),
+                sorted(in_grid.entities_in_aabb(&aabb, QueryFilter::new()))
+            );
+
+            let angle = rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+            let dir = Vector::new(angle.cos(), angle.sin());
+            let hit_trees = in_trees.raycast(point, dir, 300.0, QueryFilter::new());
+            let hit_grid = in_grid.raycast(point, dir, 300.0, QueryFilter::new());
+            assert_eq!(
+                hit_trees.map(|hit| hit.toi.round()),
+                hit_grid.map(|hit| hit.toi.round())
+            );
+        }
     }
 }
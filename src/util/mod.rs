@@ -1,6 +1,7 @@
 pub use self::collision_world::CollisionWorld;
 
 pub mod collision_world;
+mod tile_grid;
 
 pub trait Transition {
     fn create_state(self) -> Option<Box<dyn State<Self>>>;
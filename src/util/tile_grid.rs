@@ -0,0 +1,355 @@
+use ecs::Entity;
+
+use smallvec::SmallVec;
+
+use crate::nc::bounding_volume::{BoundingVolume, AABB};
+
+// the most cells the grid grows to, far more than a room has. entities further out would make
+// it allocate a cell for every tile in between, they are kept in a list instead.
+const MAX_CELLS: u64 = 1 << 20;
+
+/// Static entities, kept in every cell of a uniform grid their box overlaps. Finding the entities
+/// near a box only looks at the cells it overlaps, so it does not get slower with more entities
+/// elsewhere. Grows to fit the entities inserted into it, up to `MAX_CELLS`.
+pub struct TileGrid {
+    cell_size: f32,
+    // coordinates of the cell at the start of `cells`, and how many there are on each axis
+    origin: (i32, i32),
+    columns: usize,
+    rows: usize,
+    cells: Vec<SmallVec<[Entity; 1]>>,
+    // entities the grid could not grow to, with their box
+    outside: Vec<(Entity, AABB<f32>)>,
+}
+
+// an inclusive range of cell coordinates
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+struct Cells {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl Cells {
+    fn count(&self) -> u64 {
+        let columns = i64::from(self.max.0) - i64::from(self.min.0) + 1;
+        let rows = i64::from(self.max.1) - i64::from(self.min.1) + 1;
+
+        columns.max(0).saturating_mul(rows.max(0)) as u64
+    }
+}
+
+impl TileGrid {
+    pub fn new(cell_size: f32) -> TileGrid {
+        TileGrid {
+            cell_size,
+            origin: (0, 0),
+            columns: 0,
+            rows: 0,
+            cells: Vec::new(),
+            outside: Vec::new(),
+        }
+    }
+
+    // the cells an entity with `aabb` is kept in. the maximum is exclusive, so tiles are only in
+    // the one cell they fill.
+    fn stored_in(&self, aabb: &AABB<f32>) -> Cells {
+        let min = (
+            (aabb.mins().x / self.cell_size).floor() as i32,
+            (aabb.mins().y / self.cell_size).floor() as i32,
+        );
+        let max = (
+            ((aabb.maxs().x / self.cell_size).ceil() as i32 - 1).max(min.0),
+            ((aabb.maxs().y / self.cell_size).ceil() as i32 - 1).max(min.1),
+        );
+
+        Cells { min, max }
+    }
+
+    // the cells which can hold entities touching `aabb`, including the ones ending where it starts
+    fn touched_by(&self, aabb: &AABB<f32>) -> Cells {
+        Cells {
+            min: (
+                (aabb.mins().x / self.cell_size).ceil() as i32 - 1,
+                (aabb.mins().y / self.cell_size).ceil() as i32 - 1,
+            ),
+            max: (
+                (aabb.maxs().x / self.cell_size).floor() as i32,
+                (aabb.maxs().y / self.cell_size).floor() as i32,
+            ),
+        }
+    }
+
+    // the last cell of the grid, before the origin if it is empty
+    fn end(&self) -> (i32, i32) {
+        (
+            self.origin.0 + self.columns as i32 - 1,
+            self.origin.1 + self.rows as i32 - 1,
+        )
+    }
+
+    // the part of `cells` within the grid, empty if they do not overlap
+    fn within(&self, cells: Cells) -> Cells {
+        let end = self.end();
+
+        Cells {
+            min: (
+                cells.min.0.max(self.origin.0),
+                cells.min.1.max(self.origin.1),
+            ),
+            max: (cells.max.0.min(end.0), cells.max.1.min(end.1)),
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let column = x - self.origin.0;
+        let row = y - self.origin.1;
+
+        if column < 0 || row < 0 || column as usize >= self.columns || row as usize >= self.rows {
+            return None;
+        }
+
+        Some(row as usize * self.columns + column as usize)
+    }
+
+    // grows the grid to contain `cells`, keeping what is in it. returns false if it would grow
+    // beyond `MAX_CELLS`.
+    fn reserve(&mut self, cells: Cells) -> bool {
+        if self.within(cells) == cells {
+            return true;
+        }
+
+        if self.cells.is_empty() {
+            if cells.count() > MAX_CELLS {
+                return false;
+            }
+
+            self.resize(cells);
+            return true;
+        }
+
+        let end = self.end();
+        let needed = Cells {
+            min: (
+                self.origin.0.min(cells.min.0),
+                self.origin.1.min(cells.min.1),
+            ),
+            max: (end.0.max(cells.max.0), end.1.max(cells.max.1)),
+        };
+        if needed.count() > MAX_CELLS {
+            return false;
+        }
+
+        // leaves room to grow further the same way, so adding a room tile by tile does not copy
+        // the grid for every tile
+        let pad = |grown: bool, size: usize| if grown { size as i32 / 2 } else { 0 };
+        let left = pad(needed.min.0 < self.origin.0, self.columns);
+        let below = pad(needed.min.1 < self.origin.1, self.rows);
+        let right = pad(needed.max.0 > end.0, self.columns);
+        let above = pad(needed.max.1 > end.1, self.rows);
+        let padded = Cells {
+            min: (
+                needed.min.0.saturating_sub(left),
+                needed.min.1.saturating_sub(below),
+            ),
+            max: (
+                needed.max.0.saturating_add(right),
+                needed.max.1.saturating_add(above),
+            ),
+        };
+
+        if padded.count() <= MAX_CELLS {
+            self.resize(padded);
+        } else {
+            self.resize(needed);
+        }
+
+        true
+    }
+
+    // moves the cells into a grid spanning `cells`, which contains the current one
+    fn resize(&mut self, cells: Cells) {
+        let mut grown = TileGrid {
+            cell_size: self.cell_size,
+            origin: cells.min,
+            columns: (cells.max.0 - cells.min.0 + 1) as usize,
+            rows: (cells.max.1 - cells.min.1 + 1) as usize,
+            cells: Vec::new(),
+            outside: std::mem::replace(&mut self.outside, Vec::new()),
+        };
+        grown.cells = vec![SmallVec::new(); grown.columns * grown.rows];
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let (x, y) = (self.origin.0 + column as i32, self.origin.1 + row as i32);
+                let cell = &mut self.cells[row * self.columns + column];
+                let index = grown.index(x, y).unwrap();
+
+                grown.cells[index] = std::mem::replace(cell, SmallVec::new());
+            }
+        }
+
+        *self = grown;
+    }
+
+    pub fn insert(&mut self, e: Entity, aabb: &AABB<f32>) {
+        let cells = self.stored_in(aabb);
+        if !self.reserve(cells) {
+            self.outside.push((e, *aabb));
+            return;
+        }
+
+        for y in cells.min.1..=cells.max.1 {
+            for x in cells.min.0..=cells.max.0 {
+                let index = self.index(x, y).unwrap();
+                self.cells[index].push(e);
+            }
+        }
+    }
+
+    /// Removes `e`, which was inserted with `aabb`.
+    pub fn remove(&mut self, e: Entity, aabb: &AABB<f32>) {
+        let cells = self.within(self.stored_in(aabb));
+
+        for y in cells.min.1..=cells.max.1 {
+            for x in cells.min.0..=cells.max.0 {
+                let index = self.index(x, y).unwrap();
+                self.cells[index].retain(|other| *other != e);
+            }
+        }
+
+        self.outside.retain(|(other, _)| *other != e);
+    }
+
+    /// Adds the entities which may overlap or touch `aabb` to `colls`, each once, ordered by id
+    /// so they are found in the same order in every process. Their boxes still have to be
+    /// checked.
+    pub fn visit(&self, aabb: &AABB<f32>, colls: &mut Vec<Entity>) {
+        let cells = self.within(self.touched_by(aabb));
+        let mut found = Vec::new();
+
+        for y in cells.min.1..=cells.max.1 {
+            for x in cells.min.0..=cells.max.0 {
+                let index = self.index(x, y).unwrap();
+                found.extend_from_slice(&self.cells[index]);
+            }
+        }
+
+        found.extend(
+            self.outside
+                .iter()
+                .filter(|(_, other)| other.intersects(aabb))
+                .map(|(e, _)| *e),
+        );
+
+        // entities spanning several cells are in each of them
+        found.sort_by_key(|e| e.id());
+        found.dedup();
+
+        colls.extend(found);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ecs::{BuildData, World};
+
+    use super::*;
+    use crate::components::LevelComponents;
+    use crate::na::Point2;
+    use crate::systems::LevelSystems;
+
+    fn aabb(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> AABB<f32> {
+        AABB::new(Point2::new(min_x, min_y), Point2::new(max_x, max_y))
+    }
+
+    fn visit(grid: &TileGrid, aabb: &AABB<f32>) -> Vec<Entity> {
+        let mut colls = Vec::new();
+        grid.visit(aabb, &mut colls);
+        colls
+    }
+
+    #[test]
+    fn finds_entities_touching_the_box() {
+        let mut world = World::<LevelSystems>::new();
+        let mut create =
+            || world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {});
+        let (tile, big, far) = (create(), create(), create());
+
+        let mut grid = TileGrid::new(32.0);
+        grid.insert(tile, &aabb(0.0, 0.0, 32.0, 32.0));
+        grid.insert(big, &aabb(-70.0, -10.0, -1.0, 50.0));
+        grid.insert(far, &aabb(320.0, 320.0, 352.0, 352.0));
+
+        assert_eq!(visit(&grid, &aabb(10.0, 10.0, 20.0, 20.0)), vec![tile]);
+        // touching the tile from above, in the next cell
+        assert_eq!(visit(&grid, &aabb(10.0, 32.0, 20.0, 40.0)), vec![tile]);
+        assert!(visit(&grid, &aabb(33.0, 0.0, 40.0, 10.0)).is_empty());
+
+        let mut found = visit(&grid, &aabb(-5.0, 0.0, 5.0, 5.0));
+        found.sort_by_key(|e| e.id());
+        assert_eq!(found, vec![tile, big]);
+        assert_eq!(visit(&grid, &aabb(-100.0, -100.0, -65.0, -5.0)), vec![big]);
+        assert_eq!(visit(&grid, &aabb(330.0, 330.0, 340.0, 340.0)), vec![far]);
+        assert!(visit(&grid, &aabb(1000.0, 1000.0, 1010.0, 1010.0)).is_empty());
+
+        // entities in several cells are only found once
+        assert_eq!(visit(&grid, &aabb(-60.0, -5.0, -40.0, 45.0)), vec![big]);
+
+        grid.remove(big, &aabb(-70.0, -10.0, -1.0, 50.0));
+        assert_eq!(visit(&grid, &aabb(-5.0, 0.0, 5.0, 5.0)), vec![tile]);
+        assert!(visit(&grid, &aabb(-100.0, -100.0, -65.0, -5.0)).is_empty());
+    }
+
+    #[test]
+    fn far_entities_are_kept_outside_the_grid() {
+        let mut world = World::<LevelSystems>::new();
+        let mut create =
+            || world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {});
+        let (tile, far) = (create(), create());
+
+        let mut grid = TileGrid::new(32.0);
+        grid.insert(tile, &aabb(0.0, 0.0, 32.0, 32.0));
+        grid.insert(far, &aabb(1e8, -1e8, 1e8 + 32.0, -1e8 + 32.0));
+        assert_eq!((grid.cells.len(), grid.outside.len()), (1, 1));
+
+        assert_eq!(visit(&grid, &aabb(10.0, 10.0, 20.0, 20.0)), vec![tile]);
+        assert_eq!(
+            visit(&grid, &aabb(1e8, -1e8, 1e8 + 10.0, -1e8 + 10.0)),
+            vec![far]
+        );
+        // a box over both of them only looks at the cells of the grid
+        assert_eq!(visit(&grid, &aabb(-1e9, -1e9, 1e9, 1e9)), vec![tile, far]);
+
+        grid.remove(far, &aabb(1e8, -1e8, 1e8 + 32.0, -1e8 + 32.0));
+        assert!(grid.outside.is_empty());
+        assert_eq!(visit(&grid, &aabb(-1e9, -1e9, 1e9, 1e9)), vec![tile]);
+    }
+
+    #[test]
+    fn grows_to_fit_a_room() {
+        let mut world = World::<LevelSystems>::new();
+        let tiles: Vec<Entity> = (0..400)
+            .map(|_| {
+                world.create_entity(|_: BuildData<'_, LevelComponents>, _: &mut LevelComponents| {})
+            })
+            .collect();
+
+        // 20 tiles wide, added row by row downwards
+        let corner = |i: usize| ((i % 20) as f32 * 32.0, (i / 20) as f32 * -32.0);
+
+        let mut grid = TileGrid::new(32.0);
+        for (i, tile) in tiles.iter().enumerate() {
+            let (x, y) = corner(i);
+            grid.insert(*tile, &aabb(x, y, x + 32.0, y + 32.0));
+        }
+        assert!(grid.outside.is_empty());
+
+        for (i, tile) in tiles.iter().enumerate() {
+            let (x, y) = corner(i);
+            assert_eq!(
+                visit(&grid, &aabb(x + 10.0, y + 10.0, x + 20.0, y + 20.0)),
+                vec![*tile]
+            );
+        }
+    }
+}